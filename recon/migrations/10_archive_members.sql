@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN parent_archive VARCHAR;
+ALTER TABLE files ADD COLUMN extracted_path VARCHAR;
@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN git_remote VARCHAR;
+ALTER TABLE files ADD COLUMN git_commit VARCHAR;
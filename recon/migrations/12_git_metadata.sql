@@ -0,0 +1,4 @@
+ALTER TABLE files ADD COLUMN git_tracked boolean;
+ALTER TABLE files ADD COLUMN git_last_commit VARCHAR;
+ALTER TABLE files ADD COLUMN git_last_author VARCHAR;
+ALTER TABLE files ADD COLUMN git_last_commit_time DATETIME;
@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN s3_etag VARCHAR;
+ALTER TABLE files ADD COLUMN s3_storage_class VARCHAR;
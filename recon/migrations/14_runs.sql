@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS runs (
+  id INTEGER PRIMARY KEY,
+  started_at DATETIME,
+  finished_at DATETIME,
+  root VARCHAR,
+  files INT
+);
+
+ALTER TABLE files ADD COLUMN run_id INT;
+
+-- files always holds the latest state, every finished run keeps a copy of
+-- the integrity relevant fields of the files it saw here
+CREATE TABLE IF NOT EXISTS snapshots (
+  run_id INT NOT NULL,
+  abs_path VARCHAR NOT NULL,
+  path VARCHAR NOT NULL,
+
+  size BIGINT,
+  mode VARCHAR,
+  user VARCHAR,
+  'group' VARCHAR,
+  uid INT,
+  gid INT,
+  mtime DATETIME,
+  ctime DATETIME,
+
+  crc32 VARCHAR,
+  md5 VARCHAR,
+  sha256 VARCHAR,
+  sha512 VARCHAR,
+
+  PRIMARY KEY (run_id, abs_path)
+);
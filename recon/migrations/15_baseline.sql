@@ -0,0 +1,15 @@
+-- the trusted state of files, as recorded by `recon baseline create`
+CREATE TABLE IF NOT EXISTS baseline (
+  abs_path VARCHAR PRIMARY KEY,
+  path VARCHAR NOT NULL,
+
+  size BIGINT,
+  mode VARCHAR,
+  user VARCHAR,
+  'group' VARCHAR,
+  uid INT,
+  gid INT,
+  sha256 VARCHAR,
+
+  created_at DATETIME
+);
@@ -0,0 +1,2 @@
+-- when a file was last found by a walk, stale rows keep their last sighting
+ALTER TABLE files ADD COLUMN last_seen DATETIME;
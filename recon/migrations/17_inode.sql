@@ -0,0 +1,2 @@
+-- with size and mtime, tells if a file changed since it was indexed
+ALTER TABLE files ADD COLUMN inode BIGINT;
@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN literals_match JSON;
@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN is_known_good BOOLEAN;
@@ -0,0 +1,7 @@
+ALTER TABLE files ADD COLUMN vt_lookup JSON;
+
+CREATE TABLE IF NOT EXISTS vt_cache (
+  sha256 TEXT PRIMARY KEY NOT NULL,
+  verdict JSON NOT NULL,
+  fetched_at DATETIME NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN bazaar_match JSON;
@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN packages JSON;
+ALTER TABLE files ADD COLUMN osv_vulns JSON;
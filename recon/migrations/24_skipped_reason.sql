@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN skipped_reason TEXT;
@@ -0,0 +1,8 @@
+-- files which couldn't be listed, read or unpacked, by the run that came across them
+CREATE TABLE IF NOT EXISTS scan_errors (
+  id INTEGER PRIMARY KEY,
+  run_id INT,
+  path VARCHAR NOT NULL,
+  error VARCHAR NOT NULL,
+  created_at DATETIME
+);
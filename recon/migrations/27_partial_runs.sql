@@ -0,0 +1,2 @@
+-- runs stopped with Ctrl-C before seeing every file, they keep no snapshot
+ALTER TABLE runs ADD COLUMN partial BOOLEAN;
@@ -0,0 +1,14 @@
+-- the content of text files, by the rowid of their file in `files`, for `source.full_text`.
+-- files that aren't text get a null content, so they're not read again
+CREATE VIRTUAL TABLE contents USING fts5(content);
+
+CREATE TRIGGER contents_changed AFTER UPDATE OF size, mtime, inode ON files
+WHEN old.size IS NOT new.size OR old.mtime IS NOT new.mtime OR old.inode IS NOT new.inode
+BEGIN
+  DELETE FROM contents WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER contents_removed AFTER DELETE ON files
+BEGIN
+  DELETE FROM contents WHERE rowid = old.id;
+END;
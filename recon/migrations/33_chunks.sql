@@ -0,0 +1,25 @@
+-- how many chunks of the file are in `chunks`, null until it's chunked
+ALTER TABLE files ADD COLUMN chunks INTEGER;
+
+-- content defined chunks of files, for `computed_fields.chunks`. `hash` is the first 128 bits
+-- of the sha256 of the chunk
+CREATE TABLE chunks (
+  file_id INTEGER NOT NULL,
+  offset INTEGER NOT NULL,
+  size INTEGER NOT NULL,
+  hash TEXT NOT NULL,
+  PRIMARY KEY (file_id, offset)
+);
+CREATE INDEX chunks_hash ON chunks (hash);
+
+CREATE TRIGGER chunks_changed AFTER UPDATE OF size, mtime, inode ON files
+WHEN old.size IS NOT new.size OR old.mtime IS NOT new.mtime OR old.inode IS NOT new.inode
+BEGIN
+  DELETE FROM chunks WHERE file_id = old.id;
+  UPDATE files SET chunks = NULL WHERE id = old.id;
+END;
+
+CREATE TRIGGER chunks_removed AFTER DELETE ON files
+BEGIN
+  DELETE FROM chunks WHERE file_id = old.id;
+END;
@@ -0,0 +1,10 @@
+-- folders with what the files under them add up to, at any depth, for `source.dirs`.
+-- `matches` counts files with a match in any `*_match` column
+CREATE TABLE dirs (
+  path TEXT PRIMARY KEY,
+  depth INTEGER NOT NULL,
+  size INTEGER NOT NULL,
+  files INTEGER NOT NULL,
+  newest_mtime TEXT,
+  matches INTEGER NOT NULL
+);
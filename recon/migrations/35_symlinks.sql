@@ -0,0 +1,3 @@
+ALTER TABLE files ADD COLUMN symlink_target TEXT;
+ALTER TABLE files ADD COLUMN is_broken_symlink boolean;
+ALTER TABLE files ADD COLUMN is_escaping_symlink boolean;
@@ -0,0 +1,3 @@
+-- hard links of a file share its dev and inode
+ALTER TABLE files ADD COLUMN dev BIGINT;
+ALTER TABLE files ADD COLUMN nlink BIGINT;
@@ -0,0 +1,5 @@
+-- file attributes, and alternate data streams on Windows
+ALTER TABLE files ADD COLUMN is_hidden boolean;
+ALTER TABLE files ADD COLUMN is_system boolean;
+ALTER TABLE files ADD COLUMN is_readonly boolean;
+ALTER TABLE files ADD COLUMN ads JSON;
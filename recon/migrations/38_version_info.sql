@@ -0,0 +1,2 @@
+-- strings of the VERSIONINFO resource of Windows executables
+ALTER TABLE files ADD COLUMN version_info JSON;
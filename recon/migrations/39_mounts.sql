@@ -0,0 +1,3 @@
+-- where the filesystem of a file is mounted, and its type
+ALTER TABLE files ADD COLUMN mount_point TEXT;
+ALTER TABLE files ADD COLUMN filesystem_type TEXT;
@@ -0,0 +1,2 @@
+-- the symlink a file was reached through, itself or a folder above it
+ALTER TABLE files ADD COLUMN via_symlink TEXT;
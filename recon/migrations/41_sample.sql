@@ -0,0 +1,2 @@
+-- whether a sampled scan picked the file to compute fields of
+ALTER TABLE files ADD COLUMN is_sampled BOOLEAN;
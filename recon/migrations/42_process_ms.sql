@@ -0,0 +1,2 @@
+-- wall time spent computing fields of a file, over the stages it went through
+ALTER TABLE files ADD COLUMN process_ms INTEGER;
@@ -0,0 +1,2 @@
+-- lines matching the last `recon grep` pattern, kept apart from `content_match`
+ALTER TABLE files ADD COLUMN grep_match JSON;
@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN weak_key_perms boolean;
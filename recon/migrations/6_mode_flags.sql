@@ -0,0 +1,3 @@
+ALTER TABLE files ADD COLUMN is_suid boolean;
+ALTER TABLE files ADD COLUMN is_sgid boolean;
+ALTER TABLE files ADD COLUMN is_world_writable boolean;
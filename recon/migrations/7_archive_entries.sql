@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN archive_entries JSON;
@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN compression_ratio DOUBLE;
+ALTER TABLE files ADD COLUMN is_zip_bomb boolean;
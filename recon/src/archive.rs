@@ -0,0 +1,401 @@
+use crate::config::UnpackOptions;
+use crate::data::{ArchiveEntries, ArchiveEntry, UnpackReport};
+use anyhow::{bail, Result};
+use flate2::read::GzDecoder;
+use std::fs::{self, File as FsFile};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use tempfile::TempDir;
+
+/// Cap on the number of members listed per archive
+const MAX_ENTRIES: usize = 10_000;
+const TAR_MAGIC_OFFSET: usize = 257;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+        }
+    }
+}
+
+fn is_tar_header(buf: &[u8]) -> bool {
+    buf.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5) == Some(b"ustar")
+}
+
+/// Detect an archive format by its magic bytes (extensions lie)
+///
+/// # Errors
+///
+/// This function will return an error on I/O failure
+pub fn detect(path: &Path) -> Result<Option<ArchiveFormat>> {
+    let mut header = vec![];
+    FsFile::open(path)?.take(512).read_to_end(&mut header)?;
+
+    if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        return Ok(Some(ArchiveFormat::Zip));
+    }
+    if is_tar_header(&header) {
+        return Ok(Some(ArchiveFormat::Tar));
+    }
+    if header.starts_with(&[0x1f, 0x8b]) {
+        // a gzip stream is only an archive if it wraps a tar
+        let mut inner = vec![];
+        let _res = GzDecoder::new(FsFile::open(path)?)
+            .take(512)
+            .read_to_end(&mut inner);
+        if is_tar_header(&inner) {
+            return Ok(Some(ArchiveFormat::TarGz));
+        }
+    }
+    Ok(None)
+}
+
+fn list_zip(path: &Path) -> Result<(Vec<ArchiveEntry>, bool)> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(FsFile::open(path)?))?;
+    let mut entries = vec![];
+    for i in 0..archive.len().min(MAX_ENTRIES) {
+        // raw access: no decompression or decryption needed for listing
+        let member = archive.by_index_raw(i)?;
+        entries.push(ArchiveEntry {
+            name: member.name().to_string(),
+            size: member.size(),
+            compressed_size: Some(member.compressed_size()),
+            is_dir: member.is_dir(),
+        });
+    }
+    Ok((entries, archive.len() > MAX_ENTRIES))
+}
+
+fn list_tar<R: Read>(reader: R) -> Result<(Vec<ArchiveEntry>, bool)> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = vec![];
+    for member in archive.entries()? {
+        if entries.len() == MAX_ENTRIES {
+            return Ok((entries, true));
+        }
+        let member = member?;
+        entries.push(ArchiveEntry {
+            name: String::from_utf8_lossy(&member.path_bytes()).to_string(),
+            size: member.header().size()?,
+            compressed_size: None,
+            is_dir: member.header().entry_type().is_dir(),
+        });
+    }
+    Ok((entries, false))
+}
+
+/// List the members of an archive without extracting it
+///
+/// # Errors
+///
+/// This function will return an error on I/O failure or a malformed archive
+pub fn list(path: &Path, format: ArchiveFormat) -> Result<ArchiveEntries> {
+    let (entries, truncated) = match format {
+        ArchiveFormat::Zip => list_zip(path)?,
+        ArchiveFormat::Tar => list_tar(BufReader::new(FsFile::open(path)?))?,
+        ArchiveFormat::TarGz => list_tar(GzDecoder::new(BufReader::new(FsFile::open(path)?)))?,
+    };
+    Ok(ArchiveEntries {
+        format: format.name().to_string(),
+        entries,
+        truncated,
+    })
+}
+
+fn tar_uncompressed_size<R: Read>(reader: R) -> Result<u64> {
+    let mut archive = tar::Archive::new(reader);
+    let mut total = 0u64;
+    for member in archive.entries()? {
+        total = total.saturating_add(member?.header().size()?);
+    }
+    Ok(total)
+}
+
+/// Total (uncompressed, compressed) sizes of an archive's members, as declared by the archive
+///
+/// # Errors
+///
+/// This function will return an error on I/O failure or a malformed archive
+pub fn sizes(path: &Path, format: ArchiveFormat) -> Result<(u64, u64)> {
+    let file_size = std::fs::metadata(path)?.len();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(BufReader::new(FsFile::open(path)?))?;
+            let (mut size, mut compressed) = (0u64, 0u64);
+            for i in 0..archive.len() {
+                let member = archive.by_index_raw(i)?;
+                size = size.saturating_add(member.size());
+                compressed = compressed.saturating_add(member.compressed_size());
+            }
+            Ok((size, compressed))
+        }
+        ArchiveFormat::Tar => Ok((
+            tar_uncompressed_size(BufReader::new(FsFile::open(path)?))?,
+            file_size,
+        )),
+        ArchiveFormat::TarGz => Ok((
+            tar_uncompressed_size(GzDecoder::new(BufReader::new(FsFile::open(path)?)))?,
+            file_size,
+        )),
+    }
+}
+
+///
+/// A file extracted from an archive
+///
+pub struct Member {
+    /// where the member was extracted to
+    pub location: PathBuf,
+    /// path inside the archive; members of nested archives are joined with `!/`
+    pub name: String,
+    /// name of the nested archive containing this member, `None` for the top level archive
+    pub parent: Option<String>,
+}
+
+///
+/// An archive extracted into a temporary folder
+///
+pub struct Unpacked {
+    pub dir: TempDir,
+    pub members: Vec<Member>,
+    pub report: UnpackReport,
+}
+
+impl Unpacked {
+    /// Finalize the report. Extracted files are kept for good if asked to, otherwise
+    /// the temporary folder is handed back and removed once dropped.
+    #[must_use]
+    pub fn finish(self, keep_temp: bool) -> (UnpackReport, Option<TempDir>) {
+        let mut report = self.report;
+        if keep_temp {
+            report.temp_dir = Some(self.dir.into_path().display().to_string());
+            (report, None)
+        } else {
+            (report, Some(self.dir))
+        }
+    }
+}
+
+/// Tracks what's left of the unpack budget and what was extracted so far
+struct Unpacking<'a> {
+    opts: &'a UnpackOptions,
+    remaining_bytes: u64,
+    members: Vec<Member>,
+    report: UnpackReport,
+}
+
+/// Join an archive member path onto `dest`, refusing absolute paths and `..` (zip-slip)
+fn safe_join(dest: &Path, member: &Path) -> Option<PathBuf> {
+    let mut res = dest.to_path_buf();
+    for c in member.components() {
+        match c {
+            Component::Normal(part) => res.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (res != dest).then_some(res)
+}
+
+impl Unpacking<'_> {
+    /// Write a single member to disk, within budget. Returns `false` once the budget is exhausted.
+    fn write_member<R: Read>(&mut self, reader: R, size: u64, target: &Path) -> Result<bool> {
+        if size > self.remaining_bytes {
+            self.report.truncated = true;
+            return Ok(false);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // never trust declared sizes, cap the actual bytes written too
+        let mut out = FsFile::create(target)?;
+        let written = io::copy(&mut reader.take(self.remaining_bytes + 1), &mut out)?;
+        if written > self.remaining_bytes {
+            self.report.truncated = true;
+            return Ok(false);
+        }
+        self.remaining_bytes -= written;
+        self.report.files += 1;
+        self.report.bytes += written;
+        Ok(true)
+    }
+
+    fn extract_zip(&mut self, path: &Path, dest: &Path) -> Result<Vec<(PathBuf, String)>> {
+        let mut archive = zip::ZipArchive::new(BufReader::new(FsFile::open(path)?))?;
+        let mut extracted = vec![];
+        for i in 0..archive.len() {
+            let member = archive.by_index(i)?;
+            if member.is_dir() {
+                continue;
+            }
+            let Some(target) = member
+                .enclosed_name()
+                .and_then(|name| safe_join(dest, name))
+            else {
+                self.report.errors.push(format!(
+                    "{}: unsafe member path '{}'",
+                    path.display(),
+                    member.name()
+                ));
+                continue;
+            };
+            let name = member.name().to_string();
+            let size = member.size();
+            if !self.write_member(member, size, &target)? {
+                break;
+            }
+            extracted.push((target, name));
+        }
+        Ok(extracted)
+    }
+
+    fn extract_tar<R: Read>(
+        &mut self,
+        reader: R,
+        path: &Path,
+        dest: &Path,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let mut archive = tar::Archive::new(reader);
+        let mut extracted = vec![];
+        for member in archive.entries()? {
+            let member = member?;
+            // links and special files are never materialized
+            if !member.header().entry_type().is_file() {
+                continue;
+            }
+            let name = member.path()?.to_path_buf();
+            let Some(target) = safe_join(dest, &name) else {
+                self.report.errors.push(format!(
+                    "{}: unsafe member path '{}'",
+                    path.display(),
+                    name.display()
+                ));
+                continue;
+            };
+            let size = member.header().size()?;
+            if !self.write_member(member, size, &target)? {
+                break;
+            }
+            extracted.push((target, name.display().to_string()));
+        }
+        Ok(extracted)
+    }
+
+    fn extract(
+        &mut self,
+        path: &Path,
+        format: ArchiveFormat,
+        dest: &Path,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        match format {
+            ArchiveFormat::Zip => self.extract_zip(path, dest),
+            ArchiveFormat::Tar => self.extract_tar(BufReader::new(FsFile::open(path)?), path, dest),
+            ArchiveFormat::TarGz => self.extract_tar(
+                GzDecoder::new(BufReader::new(FsFile::open(path)?)),
+                path,
+                dest,
+            ),
+        }
+    }
+
+    /// Extract an archive, then recurse into any archives it contained
+    fn unpack_into(
+        &mut self,
+        path: &Path,
+        format: ArchiveFormat,
+        dest: &Path,
+        depth: usize,
+        parent: Option<&str>,
+    ) {
+        self.report.depth = self.report.depth.max(depth);
+        let extracted = match self.extract(path, format, dest) {
+            Ok(extracted) => extracted,
+            Err(err) => {
+                self.report
+                    .errors
+                    .push(format!("{}: {}", path.display(), err));
+                return;
+            }
+        };
+        let extracted = extracted
+            .into_iter()
+            .map(|(location, name)| {
+                let name = parent.map_or_else(|| name.clone(), |p| format!("{}!/{}", p, name));
+                (location, name)
+            })
+            .collect::<Vec<_>>();
+        self.members
+            .extend(extracted.iter().map(|(location, name)| Member {
+                location: location.clone(),
+                name: name.clone(),
+                parent: parent.map(ToString::to_string),
+            }));
+
+        if depth >= self.opts.max_depth() {
+            return;
+        }
+        for (location, name) in extracted {
+            match detect(&location) {
+                Ok(Some(nested)) => {
+                    let mut nested_dest = location.clone().into_os_string();
+                    nested_dest.push(".unpacked");
+                    self.unpack_into(
+                        &location,
+                        nested,
+                        Path::new(&nested_dest),
+                        depth + 1,
+                        Some(&name),
+                    );
+                }
+                Ok(None) => {}
+                Err(err) => self.report.errors.push(format!("{}: {}", name, err)),
+            }
+            if self.report.truncated {
+                return;
+            }
+        }
+    }
+}
+
+/// Recursively extract an archive into a temporary folder, within the configured budgets.
+/// Failures along the way are recorded in the report rather than aborting.
+///
+/// # Errors
+///
+/// This function will return an error if the archive cannot be read, or a temporary folder
+/// cannot be created
+pub fn unpack(path: &Path, opts: &UnpackOptions) -> Result<Option<Unpacked>> {
+    let Some(format) = detect(path)? else {
+        return Ok(None);
+    };
+    if opts.max_depth() == 0 {
+        bail!("unpack max_depth must be at least 1");
+    }
+    let dir = tempfile::Builder::new().prefix("recon-").tempdir()?;
+    let mut unpacking = Unpacking {
+        opts,
+        remaining_bytes: opts.max_total_bytes(),
+        members: vec![],
+        report: UnpackReport {
+            format: format.name().to_string(),
+            ..Default::default()
+        },
+    };
+    unpacking.unpack_into(path, format, dir.path(), 1, None);
+    Ok(Some(Unpacked {
+        dir,
+        members: unpacking.members,
+        report: unpacking.report,
+    }))
+}
@@ -1,21 +1,43 @@
 #![allow(clippy::must_use_candidate)]
 use clap::crate_version;
 use clap::ArgAction;
+use recon::config::Severity;
+use recon::data::ValuesTable;
+use recon::observer::{JsonProgress, Observer};
+use recon::out::{CsvOptions, CsvQuote, SbomFormat};
+use recon::syslog;
 use recon::workflow;
-use recon::workflow::RunOptions;
+use recon::workflow::{
+    Baseline, Digest, Dupes, DupesAction, Ignores, Mode, Overlap, RunOptions, Sample, Similar,
+};
+use std::collections::BTreeMap;
 use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::Path;
 use std::time::Instant;
 use tracing::metadata::LevelFilter;
 use tracing_subscriber::{filter, EnvFilter, Registry};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use anyhow::Result;
-use clap::{Arg, Command};
+use anyhow::{bail, Context, Result};
+use clap::{Arg, ArgMatches, Command};
 use std::process::exit;
 
+/// Arguments of walking files, taken by `scan` and `run` as well
+const SCAN_ARGS: &[&str] = &[
+    "files-from",
+    "delete",
+    "force",
+    "syslog",
+    "prune",
+    "dry-run",
+];
+
 #[allow(clippy::too_many_lines)]
 pub fn command() -> Command {
-    Command::new("recon")
+    let recon = Command::new("recon")
         .version(crate_version!())
         .about("SQL over files with security processing and tests")
         .arg(
@@ -23,21 +45,48 @@ pub fn command() -> Command {
                 .short('c')
                 .long("config")
                 .value_name("CONFIG_FILE")
-                .help("Point to a configuration"),
+                .global(true)
+                .action(ArgAction::Append)
+                .help("Point to a configuration file or URL, repeat to merge several (later ones override)"),
+        )
+        .arg(
+            Arg::new("profile")
+                .short('p')
+                .long("profile")
+                .value_name("PROFILE")
+                .global(true)
+                .help("Apply a profile from the configuration's `profiles` on top of it"),
         )
         .arg(
             Arg::new("root")
                 .short('r')
                 .long("root")
                 .value_name("ROOT")
-                .help("Target folder to scan"),
+                .global(true)
+                .help("Target folder or archive to scan"),
+        )
+        .arg(
+            Arg::new("files-from")
+                .long("files-from")
+                .value_name("LIST")
+                .help("Index only the paths listed in this file, one per line (- for stdin), instead of walking"),
         )
         .arg(
             Arg::new("query")
                 .short('q')
                 .long("query")
                 .value_name("SQL")
-                .help("Query with SQL"),
+                .value_parser(parse_query)
+                .help("Query with SQL, or read it from a file with @FILE or from stdin with -"),
+        )
+        .arg(
+            Arg::new("query-name")
+                .long("query-name")
+                .value_name("NAME")
+                .num_args(0..=1)
+                .default_missing_value("")
+                .action(ArgAction::Append)
+                .help("Run a named query of the config's `queries` (repeat for several, leave out NAME for all), each a result set of its own"),
         )
         .arg(
             Arg::new("file")
@@ -45,6 +94,7 @@ pub fn command() -> Command {
                 .long("file")
                 .value_name("DB_FILE")
                 .default_value(recon::DB_FILE)
+                .global(true)
                 .help("Use a specific DB file (file or :memory: for in memory)"),
         )
         .arg(
@@ -59,70 +109,765 @@ pub fn command() -> Command {
                 .short('u')
                 .long("update")
                 .action(ArgAction::SetTrue)
-                .help("Always walk files and update DB before query. Leave off to run query on existing recon.db."),
+                .help("Always walk files and update DB before query, like `recon run`. Leave off to run query on existing recon.db."),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Update DB processing all files again, including ones unchanged since the last run"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Stop at the first file or field that fails, instead of recording it and moving on"),
+        )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Keep running, updating DB and query results as files change"),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .value_name("ADDR")
+                .value_parser(clap::value_parser!(SocketAddr))
+                .requires("watch")
+                .help("Serve Prometheus metrics on ADDR at /metrics while watching"),
+        )
+        .arg(
+            Arg::new("syslog")
+                .long("syslog")
+                .value_name("TARGET")
+                .value_parser(parse_syslog)
+                .help("Send a record per matched file to syslog or journald: 'journald', 'syslog' (/dev/log), a socket path or host:port (UDP)"),
+        )
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .action(ArgAction::SetTrue)
+                .help("Remove files no longer on disk from DB when updating"),
         )
         .arg(
             Arg::new("all")
                 .short('a')
                 .long("all")
-                .help("Walk all files (dont consider .gitignore)")
+                .global(true)
+                .help("Walk all files, same as --no-gitignore --no-global-gitignore --no-git-exclude")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-gitignore")
+                .long("no-gitignore")
+                .global(true)
+                .help("Don't honor .gitignore files")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("no-global-gitignore")
+                .long("no-global-gitignore")
+                .global(true)
+                .help("Don't honor the global gitignore (git's core.excludesFile)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-git-exclude")
+                .long("no-git-exclude")
+                .global(true)
+                .help("Don't honor .git/info/exclude")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("skip-git-dir")
+                .long("skip-git-dir")
+                .global(true)
+                .help("Leave out .git folders")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hidden")
+                .long("hidden")
+                .global(true)
+                .overrides_with("no-hidden")
+                .help("Walk hidden files and folders (default)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-hidden")
+                .long("no-hidden")
+                .global(true)
+                .overrides_with("hidden")
+                .help("Leave out hidden files and folders")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("one-file-system")
+                .short('x')
+                .long("one-file-system")
+                .global(true)
+                .help("Don't walk into other filesystems mounted under the root")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("follow-symlinks")
+                .short('L')
+                .long("follow-symlinks")
+                .global(true)
+                .help("Walk into symlinked folders, recording links that loop back as scan errors")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sample")
+                .long("sample")
+                .value_name("PERCENT")
+                .global(true)
+                .value_parser(parse_percent)
+                .conflicts_with("sample-count")
+                .help("Compute fields of a random share of the walked files only, such as 5%"),
+        )
+        .arg(
+            Arg::new("sample-count")
+                .long("sample-count")
+                .value_name("COUNT")
+                .global(true)
+                .value_parser(clap::value_parser!(u64))
+                .help("Compute fields of this many walked files picked at random only"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Walk and report how many files and bytes each field would go through, without computing fields or touching the DB")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .value_name("DEPTH")
+                .global(true)
+                .value_parser(clap::value_parser!(usize))
+                .help("Don't walk deeper than this many levels below the root (1: only files right in it)"),
+        )
+        .arg(
+            Arg::new("min-size")
+                .long("min-size")
+                .value_name("SIZE")
+                .global(true)
+                .value_parser(parse_size)
+                .help("Skip files smaller than this while walking, in bytes or such as 10KB"),
+        )
+        .arg(
+            Arg::new("max-size")
+                .long("max-size")
+                .value_name("SIZE")
+                .global(true)
+                .value_parser(parse_size)
+                .help("Skip files larger than this while walking, in bytes or such as 2GB"),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .global(true)
+                .action(ArgAction::Append)
+                .help("Leave out files and folders matching a glob while walking, such as node_modules, repeat for more"),
+        )
         .arg(
             Arg::new("no-progress")
                 .long("no-progress")
+                .global(true)
                 .help("Don't display progress bars")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("progress")
+                .long("progress")
+                .value_name("STYLE")
+                .global(true)
+                .value_parser(["bar", "json", "none"])
+                .help("How to show progress: bars (default), JSON lines on stderr for CI, or none"),
+        )
         .arg(
             Arg::new("inmem")
                 .short('m')
                 .long("inmem")
+                .global(true)
                 .help("Don't cache index to disk, run in-memory only")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("OUT_FILE")
+                .global(true)
+                .help("Write results to a file, in the format of its extension (csv, json, jsonl, html) unless a format is given"),
+        )
+        .arg(
+            Arg::new("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .global(true)
+                .help("Output only these comma separated columns, in order (column=name renames)"),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .value_name("COLUMNS")
+                .global(true)
+                .help("Sort results by comma separated columns, each as column[:asc|:desc]"),
+        )
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .value_name("ROWS")
+                .global(true)
+                .value_parser(clap::value_parser!(usize))
+                .help("Output at most this many rows (after sorting)"),
+        )
         .arg(
             Arg::new("xargs")
                 .long("xargs")
+                .global(true)
                 .help("Output as xargs formatted list")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("xargs0")
+                .long("xargs0")
+                .global(true)
+                .help("Output as a NUL separated list, for paths with spaces or newlines (use with xargs -0)")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("json")
                 .long("json")
+                .global(true)
                 .help("Output as JSON")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("jsonl")
+                .long("jsonl")
+                .global(true)
+                .help("Output as JSON lines, an object per row")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("csv")
                 .long("csv")
+                .global(true)
                 .help("Output as CSV")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("csv-delimiter")
+                .long("csv-delimiter")
+                .value_name("DELIMITER")
+                .global(true)
+                .default_value("comma")
+                .value_parser(["comma", "tab", "semicolon"])
+                .help("Delimit CSV values with this"),
+        )
+        .arg(
+            Arg::new("csv-quote")
+                .long("csv-quote")
+                .value_name("QUOTE")
+                .global(true)
+                .default_value("necessary")
+                .value_parser(["necessary", "always", "non-numeric", "never"])
+                .help("Quote CSV values"),
+        )
+        .arg(
+            Arg::new("no-header")
+                .long("no-header")
+                .global(true)
+                .help("Don't output column names as the first CSV record")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sbom")
+                .long("sbom")
+                .value_name("FORMAT")
+                .global(true)
+                .value_parser(["cyclonedx", "spdx"])
+                .help("Output files (path and digests) and packages as an SBOM"),
+        )
+        .arg(
+            Arg::new("html")
+                .long("html")
+                .value_name("REPORT_FILE")
+                .global(true)
+                .help("Also write results as a standalone HTML report"),
+        )
+        .arg(
+            Arg::new("junit")
+                .long("junit")
+                .value_name("REPORT_FILE")
+                .global(true)
+                .help("Also write rule matches as a JUnit XML report, a test case per rule and a failure per file"),
+        )
+        .arg(
+            Arg::new("human")
+                .long("human")
+                .global(true)
+                .help("Output as a table with sizes in units, relative times and shortened hashes and paths")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("no-style")
                 .long("no-style")
+                .global(true)
                 .help("Output as a table with no styles")
                 .action(ArgAction::SetTrue),
         )
         .arg(
             Arg::new("fail-some")
                 .long("fail-some")
+                .global(true)
                 .help("Exit code failure if *some* files are found")
                 .action(ArgAction::SetTrue),
         )
         .arg(
             Arg::new("fail-none")
                 .long("fail-none")
+                .global(true)
                 .help("Exit code failure if *no* files are found")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("fail-over")
+                .long("fail-over")
+                .value_name("COUNT")
+                .global(true)
+                .value_parser(clap::value_parser!(usize))
+                .help("Exit code failure if *more* than COUNT files are found"),
+        )
+        .arg(
+            Arg::new("fail-under")
+                .long("fail-under")
+                .value_name("COUNT")
+                .global(true)
+                .value_parser(clap::value_parser!(usize))
+                .help("Exit code failure if *fewer* than COUNT files are found"),
+        )
+        .arg(
+            Arg::new("fail-on-severity")
+                .long("fail-on-severity")
+                .value_name("SEVERITY")
+                .global(true)
+                .value_parser(["info", "low", "medium", "high", "critical"])
+                .help("Exit code failure if a match of a rule with this severity or higher is found"),
+        )
         .arg(
             Arg::new("verbose")
                 .long("verbose")
+                .global(true)
                 .help("Show logs")
                 .action(ArgAction::SetTrue),
+        );
+    // subcommands share the top level's arguments of walking and watching
+    let args = |ids: &[&str]| {
+        recon
+            .get_arguments()
+            .filter(|arg| ids.contains(&arg.get_id().as_str()))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let scan_args = args(SCAN_ARGS);
+    let watch_args = args(&["watch", "metrics"]);
+    let named_args = args(&["query-name"]);
+    let sql = Arg::new("query")
+        .value_name("SQL")
+        .value_parser(parse_query)
+        .help("Query with SQL, or read it from a file with @FILE or from stdin with - (default: the config's query, or all files)");
+    recon
+        .subcommand(
+            Command::new("scan")
+                .about("Walk files and update the DB, then show the run")
+                .args(&scan_args),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Query the DB as it is, without walking")
+                .arg(sql.clone())
+                .args(&named_args),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Walk files and update the DB, then query it")
+                .args(scan_args)
+                .args(watch_args)
+                .arg(sql)
+                .args(named_args),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve a JSON API for querying an existing DB")
+                .arg(
+                    Arg::new("bind")
+                        .long("bind")
+                        .value_name("ADDR")
+                        .default_value("127.0.0.1:8080")
+                        .value_parser(clap::value_parser!(SocketAddr))
+                        .help("Address to listen on"),
+                ),
+        )
+        .subcommand(
+            Command::new("stats").about(
+                "Show an overview of an existing DB: counts, sizes, types, matches, largest and newest files",
+            ),
         )
+        .subcommand(
+            Command::new("export")
+                .about("Write an existing DB to a compressed bundle that any recon version can import")
+                .arg(
+                    Arg::new("bundle")
+                        .long("bundle")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Bundle to write, such as scan.json.zst"),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Replace what the DB holds with an exported bundle")
+                .arg(
+                    Arg::new("bundle")
+                        .long("bundle")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Bundle to read"),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Work with configuration files")
+                .subcommand_required(true)
+                .subcommand(Command::new("validate").about(
+                    "Check the configuration without scanning: parse it, compile rules, load referenced files and prepare queries",
+                )),
+        )
+        .subcommand(
+            Command::new("baseline")
+                .about("Record trusted file hashes, or verify files against them")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create").about("Walk and hash files, and trust them as a baseline"),
+                )
+                .subcommand(Command::new("verify").about(
+                    "Walk and hash files, and report files added, removed or changed since the baseline",
+                )),
+        )
+        .subcommand(
+            Command::new("grep")
+                .about("Search the content of text files with a regex, and print matching lines")
+                .arg(
+                    Arg::new("pattern")
+                        .value_name("PATTERN")
+                        .required(true)
+                        .help("Regex to search for"),
+                ),
+        )
+        .subcommand(
+            Command::new("similar")
+                .about("Walk text files, and report clusters of near duplicates by their minhash")
+                .arg(
+                    Arg::new("threshold")
+                        .long("threshold")
+                        .value_name("SIMILARITY")
+                        .default_value("0.8")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("How similar files in a cluster are at least, from 0 to 1, as the share of word shingles they have in common"),
+                ),
+        )
+        .subcommand(
+            Command::new("overlap")
+                .about("Walk and chunk files, and report pairs of files sharing much of their content")
+                .arg(
+                    Arg::new("threshold")
+                        .long("threshold")
+                        .value_name("SHARE")
+                        .default_value("0.5")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("How much of the smaller file of a pair is found in the other at least, from 0 to 1"),
+                ),
+        )
+        .subcommand(
+            Command::new("dupes")
+                .about("Walk and hash files, and report sets of duplicate files")
+                .arg(
+                    Arg::new("by")
+                        .long("by")
+                        .value_name("DIGEST")
+                        .default_value("sha256")
+                        .value_parser(["sha256", "sha512", "md5", "crc32", "norm_hash"])
+                        .help("Digest to compare files by"),
+                )
+                .arg(
+                    Arg::new("min-dupe-size")
+                        .long("min-dupe-size")
+                        .value_name("SIZE")
+                        .default_value("1")
+                        .value_parser(parse_size)
+                        .help("Leave sets of files smaller than this out of the report, in bytes or such as 1MB"),
+                )
+                .arg(
+                    Arg::new("action")
+                        .long("action")
+                        .value_name("ACTION")
+                        .value_parser(["hardlink", "delete"])
+                        .help("Keep the first file of each set (by path), and hardlink or delete the others"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .requires("action")
+                        .action(ArgAction::SetTrue)
+                        .help("Show what the action would do, without doing it"),
+                ),
+        )
+}
+
+/// A size in bytes, or such as `100MB`
+fn parse_size(size: &str) -> Result<u64, String> {
+    recon::config::parse_size(size).map_err(|err| err.to_string())
+}
+
+/// A share of files to sample, in percent such as `5%`
+fn parse_percent(percent: &str) -> Result<f64, String> {
+    match percent.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => Err(format!(
+            "invalid share '{}', should be above 0% up to 100%",
+            percent
+        )),
+    }
+}
+
+/// A query as given, or read from a file with `@query.sql`, or from stdin with `-`
+fn parse_query(query: &str) -> Result<String, String> {
+    let read = if query == "-" {
+        let mut sql = String::new();
+        io::stdin().read_to_string(&mut sql).map(|_| sql)
+    } else if let Some(path) = query.strip_prefix('@') {
+        fs::read_to_string(path)
+    } else {
+        return Ok(query.to_string());
+    };
+    read.map_err(|err| format!("cannot read query: {}", err))
+}
+
+fn parse_syslog(target: &str) -> Result<syslog::Target, String> {
+    target
+        .parse()
+        .map_err(|err| format!("invalid target '{}': {}", target, err))
+}
+
+/// Sort, limit and select columns of results as asked for
+fn shape(matches: &ArgMatches, vt: &ValuesTable) -> Result<ValuesTable> {
+    let mut vt = vt.clone();
+    if let Some(sort) = matches.get_one::<String>("sort") {
+        vt.sort_by_columns(sort)?;
+    }
+    if let Some(limit) = matches.get_one::<usize>("limit") {
+        vt.rows.truncate(*limit);
+    }
+    if let Some(columns) = matches.get_one::<String>("columns") {
+        vt = vt.select_columns(columns)?;
+    }
+    Ok(vt)
+}
+
+/// CSV delimiter, quoting and header as asked for
+fn csv_options(matches: &ArgMatches) -> CsvOptions {
+    CsvOptions {
+        delimiter: match matches
+            .get_one::<String>("csv-delimiter")
+            .map(String::as_str)
+        {
+            Some("tab") => b'\t',
+            Some("semicolon") => b';',
+            _ => b',',
+        },
+        quote: match matches.get_one::<String>("csv-quote").map(String::as_str) {
+            Some("always") => CsvQuote::Always,
+            Some("non-numeric") => CsvQuote::NonNumeric,
+            Some("never") => CsvQuote::Never,
+            _ => CsvQuote::Necessary,
+        },
+        header: !matches.get_flag("no-header"),
+    }
+}
+
+/// Whether an output format was asked for by flag
+fn any_format_flag(matches: &ArgMatches) -> bool {
+    matches.contains_id("sbom")
+        || ["csv", "json", "jsonl", "xargs", "xargs0"]
+            .iter()
+            .any(|flag| matches.get_flag(flag))
+}
+
+/// Format results as asked for, and whether a summary should follow
+fn render(matches: &ArgMatches, vt: &ValuesTable) -> Result<(bool, String)> {
+    let shaped;
+    let vt = if ["sort", "limit", "columns"]
+        .iter()
+        .any(|arg| matches.contains_id(arg))
+    {
+        shaped = shape(matches, vt)?;
+        &shaped
+    } else {
+        vt
+    };
+    if let Some(report) = matches.get_one::<String>("html") {
+        fs::write(report, vt.to_html()?)
+            .with_context(|| format!("cannot write report to '{report}'"))?;
+    }
+    if let Some(report) = matches.get_one::<String>("junit") {
+        fs::write(report, vt.to_junit()?)
+            .with_context(|| format!("cannot write report to '{report}'"))?;
+    }
+    // with no format flag, `--output` picks one by its extension
+    let any_flag = any_format_flag(matches);
+    let ext = matches
+        .get_one::<String>("output")
+        .filter(|_| !any_flag)
+        .and_then(|out| Path::new(out).extension())
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let by_ext = |flag: &str, exts: &[&str]| {
+        matches.get_flag(flag) || ext.as_deref().is_some_and(|ext| exts.contains(&ext))
+    };
+    Ok(if let Some(format) = matches.get_one::<String>("sbom") {
+        let format = if format == "spdx" {
+            SbomFormat::Spdx
+        } else {
+            SbomFormat::CycloneDx
+        };
+        (false, vt.to_sbom(format)?)
+    } else if by_ext("csv", &["csv"]) {
+        (false, vt.to_csv_with(&csv_options(matches))?)
+    } else if by_ext("json", &["json"]) {
+        (false, vt.to_json()?)
+    } else if by_ext("jsonl", &["jsonl", "ndjson"]) {
+        (false, vt.to_jsonl()?)
+    } else if by_ext("xargs", &[]) {
+        (false, vt.to_xargs()?)
+    } else if by_ext("xargs0", &[]) {
+        (false, vt.to_xargs0()?)
+    } else if matches!(ext.as_deref(), Some("html" | "htm")) {
+        (false, vt.to_html()?)
+    } else if matches.get_flag("human") {
+        (true, vt.to_human_table()?)
+    } else {
+        (true, vt.to_table()?)
+    })
+}
+
+/// Print results, or write them to `--output`. Returns whether a summary should follow,
+/// which it always does for `--output`.
+fn emit(matches: &ArgMatches, out: &str, with_summary: bool) -> Result<bool> {
+    if let Some(path) = matches.get_one::<String>("output") {
+        fs::write(path, out).with_context(|| format!("cannot write results to '{path}'"))?;
+        Ok(true)
+    } else {
+        print!("{out}");
+        Ok(with_summary)
+    }
+}
+
+/// Print results, and whether they pass `--fail-some` / `--fail-none` / `--fail-over` /
+/// `--fail-under` / `--fail-on-severity`
+fn report(matches: &ArgMatches, vt: &ValuesTable, t: Instant) -> Result<bool> {
+    if vt.incomplete {
+        bail!(workflow::INTERRUPTED);
+    }
+    let (with_summary, out) = render(matches, vt)?;
+    let with_summary = emit(matches, &out, with_summary)?;
+
+    if with_summary {
+        eprintln!(
+            "{} of {} files in {:?}",
+            vt.rows.len(),
+            vt.total_rows,
+            t.elapsed()
+        );
+    }
+    Ok(passes(matches, vt))
+}
+
+/// Whether results pass `--fail-some` / `--fail-none` / `--fail-over` / `--fail-under` /
+/// `--fail-on-severity`
+fn passes(matches: &ArgMatches, vt: &ValuesTable) -> bool {
+    // suppressed findings don't count
+    let len = vt.findings();
+
+    // note: negative-positive logic below
+    let within = matches
+        .get_one::<usize>("fail-over")
+        .is_none_or(|max| len <= *max)
+        && matches
+            .get_one::<usize>("fail-under")
+            .is_none_or(|min| len >= *min);
+    let below_severity = matches
+        .get_one::<String>("fail-on-severity")
+        .is_none_or(|severity| {
+            let severity = match severity.as_str() {
+                "info" => Severity::Info,
+                "low" => Severity::Low,
+                "medium" => Severity::Medium,
+                "high" => Severity::High,
+                _ => Severity::Critical,
+            };
+            vt.max_severity().is_none_or(|max| max < severity)
+        });
+    within
+        && below_severity
+        && if matches.get_flag("fail-some") {
+            len == 0
+        } else if matches.get_flag("fail-none") {
+            len != 0
+        } else {
+            true
+        }
+}
+
+/// Print results by name, each under its name or all as one JSON document keyed by name
+fn print_sections(matches: &ArgMatches, sections: Vec<(String, ValuesTable)>) -> Result<()> {
+    if matches.get_flag("json") {
+        // one document with a table per section
+        let sections = sections.into_iter().collect::<BTreeMap<_, _>>();
+        println!("{}", serde_json::to_string_pretty(&sections)?);
+    } else {
+        for (name, vt) in sections {
+            let (_, out) = render(matches, &vt)?;
+            println!("{name}:");
+            print!("{out}");
+        }
+    }
+    Ok(())
+}
+
+/// Prints the warnings of a run to stderr, without showing progress
+struct Warnings;
+
+impl Observer for Warnings {
+    fn on_warning(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// A flag of walking or querying, off when the subcommand doesn't take it
+fn flag(args: &ArgMatches, id: &str) -> bool {
+    value(args, id).unwrap_or_default()
+}
+
+/// A value of walking or querying, none when the subcommand doesn't take it
+fn value<T: Clone + Send + Sync + 'static>(args: &ArgMatches, id: &str) -> Option<T> {
+    args.try_get_one::<T>(id).ok().flatten().cloned()
 }
 
 #[tokio::main]
@@ -153,10 +898,38 @@ async fn main() -> anyhow::Result<()> {
         )
         .init();
 
-    let opts = RunOptions {
+    // the arguments of walking and querying come with `scan`, `query` and `run`
+    let (mode, args) = match matches.subcommand() {
+        Some(("scan", sub)) => (Mode::Scan, sub),
+        Some(("query", sub)) => (Mode::Query, sub),
+        Some(("run", sub)) => (Mode::Run, sub),
+        _ => (Mode::Auto, &matches),
+    };
+    // named queries, all of them when given without a name
+    let query_names = args
+        .try_get_many::<String>("query-name")
+        .ok()
+        .flatten()
+        .map(|names| {
+            let names = names.cloned().collect::<Vec<_>>();
+            if names.iter().any(String::is_empty) {
+                vec![]
+            } else {
+                names
+            }
+        });
+    let all = matches.get_flag("all");
+    let progress = matches.get_one::<String>("progress").map(String::as_str);
+    let no_spinner = matches.get_flag("no-progress") || progress == Some("none");
+    let mut opts = RunOptions {
         root: matches.get_one::<String>("root").cloned(),
-        config: matches.get_one::<String>("config").cloned(),
-        pre_delete: matches.get_flag("delete"),
+        files_from: value(args, "files-from"),
+        config: matches
+            .get_many::<String>("config")
+            .map(|c| c.cloned().collect())
+            .unwrap_or_default(),
+        profile: matches.get_one::<String>("profile").cloned(),
+        pre_delete: flag(args, "delete"),
         db_url: env::var("DATABASE_URL").ok(),
         db_file: if matches.get_flag("inmem") {
             ":memory:".to_string()
@@ -166,43 +939,243 @@ async fn main() -> anyhow::Result<()> {
                 .cloned()
                 .expect("should have default set")
         },
-        update: matches.get_flag("update"),
-        all_files: matches.get_flag("all"),
-        no_spinner: matches.get_flag("no-progress"),
-        query: matches.get_one::<String>("query").cloned(),
+        mode,
+        update: flag(args, "update") || flag(args, "force"),
+        ignores: Ignores {
+            gitignore: !(all || matches.get_flag("no-gitignore")),
+            global_gitignore: !(all || matches.get_flag("no-global-gitignore")),
+            git_exclude: !(all || matches.get_flag("no-git-exclude")),
+            skip_git_dir: matches.get_flag("skip-git-dir"),
+            hidden: !matches.get_flag("no-hidden"),
+        },
+        one_file_system: matches.get_flag("one-file-system"),
+        follow_symlinks: matches.get_flag("follow-symlinks"),
+        dry_run: flag(args, "dry-run"),
+        sample: matches
+            .get_one::<f64>("sample")
+            .map(|percent| Sample::Percent(*percent))
+            .or_else(|| {
+                matches
+                    .get_one::<u64>("sample-count")
+                    .map(|count| Sample::Count(*count))
+            }),
+        max_depth: matches.get_one::<usize>("max-depth").copied(),
+        min_size: matches.get_one::<u64>("min-size").copied(),
+        max_size: matches.get_one::<u64>("max-size").copied(),
+        exclude: matches
+            .get_many::<String>("exclude")
+            .map(|e| e.cloned().collect())
+            .unwrap_or_default(),
+        no_spinner,
+        // the spinner prints warnings too, otherwise they're printed on their own
+        observer: match progress {
+            Some("json") => Some(Box::new(JsonProgress::default()) as Box<dyn Observer>),
+            _ if no_spinner => Some(Box::new(Warnings)),
+            _ => None,
+        },
+        cancel: None,
+        query: value(args, "query"),
+        watch: flag(args, "watch"),
+        metrics: value(args, "metrics"),
+        syslog: value(args, "syslog"),
+        prune: flag(args, "prune"),
+        force: flag(args, "force"),
+        strict: matches.get_flag("strict"),
+        baseline: None,
+        dupes: None,
+        similar: None,
+        overlap: None,
+        grep: None,
+        jsonl: None,
     };
+    // JSON lines are written as they're read, unless every row is needed first
+    if matches!(matches.subcommand(), None | Some(("query" | "run", _)))
+        && query_names.is_none()
+        && !opts.watch
+        && matches.get_flag("jsonl")
+        && !matches.get_flag("fail-some")
+        && !matches.get_flag("fail-none")
+        && ![
+            "output",
+            "sort",
+            "limit",
+            "columns",
+            "html",
+            "junit",
+            "fail-over",
+            "fail-under",
+            "fail-on-severity",
+        ]
+        .iter()
+        .any(|arg| matches.contains_id(arg))
+    {
+        opts.jsonl = Some(tokio::sync::Mutex::new(Box::new(io::stdout())));
+    }
 
     let res: Result<bool> = match matches.subcommand() {
-        None => {
+        None | Some(("query" | "run", _)) if query_names.is_some() => {
+            let names = query_names.unwrap_or_default();
+            workflow::run_queries(&opts, &names)
+                .await
+                .and_then(|sections| {
+                    if sections.iter().any(|(_, vt)| vt.incomplete) {
+                        bail!(workflow::INTERRUPTED);
+                    }
+                    let ok = sections.iter().all(|(_, vt)| passes(&matches, vt));
+                    print_sections(&matches, sections)?;
+                    Ok(ok)
+                })
+        }
+        None | Some(("run", _)) if opts.watch => {
+            workflow::run_with(&opts, |vt| {
+                let (with_summary, out) = render(&matches, &vt)?;
+                let with_summary = emit(&matches, &out, with_summary)?;
+                if with_summary {
+                    eprintln!("{} of {} files", vt.rows.len(), vt.total_rows);
+                }
+                Ok(())
+            })
+            .await?;
+            Ok(true)
+        }
+        None | Some(("query" | "run", _)) => {
             let t = Instant::now();
-
             let vt = workflow::run(&opts).await?;
-
-            let (with_summary, out) = if matches.get_flag("csv") {
-                (false, vt.to_csv()?)
-            } else if matches.get_flag("json") {
-                (false, vt.to_json()?)
-            } else if matches.get_flag("xargs") {
-                (false, vt.to_xargs()?)
+            report(&matches, &vt, t)
+        }
+        Some(("scan", _)) => workflow::run(&opts).await.and_then(|vt| {
+            if vt.incomplete {
+                bail!(workflow::INTERRUPTED);
+            }
+            let (_, out) = render(&matches, &vt)?;
+            emit(&matches, &out, false)?;
+            Ok(true)
+        }),
+        Some(("serve", sub)) => {
+            let addr = sub
+                .get_one::<SocketAddr>("bind")
+                .expect("should have default set");
+            workflow::serve(&opts, *addr).await?;
+            Ok(true)
+        }
+        Some(("stats", _)) => {
+            let sections = workflow::stats(&opts).await?;
+            print_sections(
+                &matches,
+                sections
+                    .into_iter()
+                    .map(|(name, vt)| (name.to_string(), vt))
+                    .collect(),
+            )?;
+            Ok(true)
+        }
+        Some((name @ ("export" | "import"), sub)) => {
+            let bundle = Path::new(sub.get_one::<String>("bundle").expect("should be required"));
+            let vt = if name == "export" {
+                workflow::export(&opts, bundle).await
             } else {
-                (true, vt.to_table()?)
+                workflow::import(&opts, bundle).await
             };
-            print!("{out}");
-
-            let len = vt.rows.len();
-            if with_summary {
-                eprintln!("{len} of {} files in {:?}", vt.total_rows, t.elapsed());
+            vt.and_then(|vt| {
+                let (_, out) = render(&matches, &vt)?;
+                print!("{out}");
+                Ok(true)
+            })
+        }
+        Some(("config", sub)) => match sub.subcommand() {
+            Some(("validate", _)) => {
+                let problems = workflow::validate(&opts).await?;
+                for problem in &problems {
+                    println!("error: {problem}");
+                }
+                if problems.is_empty() {
+                    println!("configuration is valid");
+                }
+                Ok(problems.is_empty())
             }
-
-            // note: negative-positive logic below
-            let computed_success = if matches.get_flag("fail-some") {
-                len == 0
-            } else if matches.get_flag("fail-none") {
-                len != 0
-            } else {
-                true
+            _ => unreachable!("subcommand is required"),
+        },
+        Some(("baseline", sub)) => {
+            let t = Instant::now();
+            opts.update = true;
+            opts.baseline = match sub.subcommand() {
+                Some(("create", _)) => Some(Baseline::Create),
+                Some(("verify", _)) => Some(Baseline::Verify),
+                _ => unreachable!("subcommand is required"),
             };
-            Ok(computed_success)
+            let vt = workflow::run(&opts).await?;
+            report(&matches, &vt, t)
+        }
+        Some(("dupes", sub)) => {
+            let t = Instant::now();
+            opts.update = true;
+            opts.dupes = Some(Dupes {
+                by: match sub.get_one::<String>("by").map(String::as_str) {
+                    Some("sha512") => Digest::Sha512,
+                    Some("md5") => Digest::Md5,
+                    Some("crc32") => Digest::Crc32,
+                    Some("norm_hash") => Digest::NormHash,
+                    _ => Digest::Sha256,
+                },
+                min_size: *sub
+                    .get_one::<u64>("min-dupe-size")
+                    .expect("should have default set"),
+                action: match sub.get_one::<String>("action").map(String::as_str) {
+                    Some("hardlink") => Some(DupesAction::Hardlink),
+                    Some("delete") => Some(DupesAction::Delete),
+                    _ => None,
+                },
+                dry_run: sub.get_flag("dry-run"),
+            });
+            let vt = workflow::run(&opts).await?;
+            report(&matches, &vt, t)
+        }
+        Some(("similar", sub)) => {
+            let t = Instant::now();
+            opts.update = true;
+            opts.similar = Some(Similar {
+                threshold: *sub
+                    .get_one::<f64>("threshold")
+                    .expect("should have default set"),
+            });
+            let vt = workflow::run(&opts).await?;
+            report(&matches, &vt, t)
+        }
+        Some(("overlap", sub)) => {
+            let t = Instant::now();
+            opts.update = true;
+            opts.overlap = Some(Overlap {
+                threshold: *sub
+                    .get_one::<f64>("threshold")
+                    .expect("should have default set"),
+            });
+            let vt = workflow::run(&opts).await?;
+            report(&matches, &vt, t)
+        }
+        Some(("grep", sub)) => {
+            opts.grep = sub.get_one::<String>("pattern").cloned();
+            let vt = workflow::run(&opts).await?;
+            // like grep, one `path:line:match` per line unless another format is asked for
+            if any_format_flag(&matches)
+                || matches.get_flag("human")
+                || matches.contains_id("output")
+            {
+                let (_, out) = render(&matches, &vt)?;
+                emit(&matches, &out, false)?;
+            } else {
+                for row in &vt.rows {
+                    let [path, line, found] = &row[..] else {
+                        continue;
+                    };
+                    println!(
+                        "{}:{}:{}",
+                        path.as_str().unwrap_or_default(),
+                        line,
+                        found.as_str().unwrap_or_default()
+                    );
+                }
+            }
+            Ok(!vt.rows.is_empty())
         }
         _ => Ok(false),
     };
@@ -0,0 +1,263 @@
+#![allow(clippy::struct_excessive_bools)]
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::config::{ComputedFields, Config, UnpackOptions};
+use crate::data::{File, ValuesTable};
+use crate::db::Db;
+use crate::observer::{Observer, Silent};
+use crate::workflow::{Ignores, Sample, Scan};
+
+///
+/// Builds a `Recon`, for embedding recon in other programs instead of going
+/// through `workflow::run`
+///
+#[derive(Default)]
+pub struct ReconBuilder {
+    root: Option<String>,
+    config: Config,
+    db: Option<PathBuf>,
+    ignores: Ignores,
+    one_file_system: bool,
+    follow_symlinks: bool,
+    sample: Option<Sample>,
+    force: bool,
+    strict: bool,
+    observer: Option<Box<dyn Observer>>,
+    cancel: CancellationToken,
+}
+
+impl ReconBuilder {
+    /// Folder, archive or bucket (`s3://bucket/prefix`) to scan (default: `config.source.root`, or `.`)
+    #[must_use]
+    pub fn root(mut self, root: impl Into<String>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Start from a loaded configuration, such as one from `Config::from_path`
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Fields computed while walking files
+    #[must_use]
+    pub fn default_fields(mut self, fields: ComputedFields) -> Self {
+        self.config.source.default_fields = Some(fields);
+        self
+    }
+
+    /// Fields computed for the files picked by `before_computed_fields_query`, after walking
+    #[must_use]
+    pub fn computed_fields(mut self, fields: ComputedFields) -> Self {
+        self.config.source.computed_fields = Some(fields);
+        self
+    }
+
+    /// Extract archives found while walking, scanning their members as files
+    #[must_use]
+    pub const fn unpack(mut self, unpack: UnpackOptions) -> Self {
+        self.config.source.unpack = Some(unpack);
+        self
+    }
+
+    /// DB file to store files in, created if missing (default: in memory)
+    #[must_use]
+    pub fn db(mut self, file: impl Into<PathBuf>) -> Self {
+        self.db = Some(file.into());
+        self
+    }
+
+    /// Which ignore rules to honor while walking, such as `Ignores::all()` to walk files
+    /// ignored by git too (default: all of them, with hidden files walked)
+    #[must_use]
+    pub const fn ignores(mut self, ignores: Ignores) -> Self {
+        self.ignores = ignores;
+        self
+    }
+
+    /// Don't walk into other filesystems mounted under the root, such as `/proc` or
+    /// network mounts when scanning `/`
+    #[must_use]
+    pub const fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Walk into symlinked folders, such as shared folders linked into deployment trees.
+    /// Links leading back to a folder above them are recorded as scan errors.
+    #[must_use]
+    pub const fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Compute fields of a random subset of the walked files only, marking them in
+    /// `is_sampled`, for a quick picture of a large tree
+    #[must_use]
+    pub const fn sample(mut self, sample: Sample) -> Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    /// Process all files on every scan, including ones already stored and unchanged
+    #[must_use]
+    pub const fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Fail a scan on the first file or field that fails, instead of recording it
+    #[must_use]
+    pub const fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Receive progress, matches and errors while scanning
+    #[must_use]
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Stop scans once `cancel` is cancelled, such as when the request they serve goes away
+    #[must_use]
+    pub fn cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Connect to the DB, creating its tables if needed
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the DB can't be opened or created
+    pub async fn build(mut self) -> Result<Recon> {
+        if self.strict {
+            for fields in [
+                &mut self.config.source.default_fields,
+                &mut self.config.source.computed_fields,
+            ] {
+                fields.get_or_insert_with(ComputedFields::default).strict = true;
+            }
+        }
+        let db_url = self.db.as_ref().map_or_else(
+            || "sqlite::memory:".to_string(),
+            |file| format!("sqlite:{}?mode=rwc", file.display()),
+        );
+        let db = Db::connect(&db_url, &self.config.db.clone().unwrap_or_default()).await?;
+        Ok(Recon {
+            root: self.root.unwrap_or_else(|| self.config.source.root()),
+            config: self.config,
+            db,
+            ignores: self.ignores,
+            one_file_system: self.one_file_system,
+            follow_symlinks: self.follow_symlinks,
+            sample: self.sample,
+            force: self.force,
+            observer: self.observer.unwrap_or_else(|| Box::new(Silent)),
+            cancel: self.cancel,
+        })
+    }
+}
+
+///
+/// A DB of scanned files, to scan into and query
+///
+pub struct Recon {
+    root: String,
+    config: Config,
+    db: Db,
+    ignores: Ignores,
+    one_file_system: bool,
+    follow_symlinks: bool,
+    sample: Option<Sample>,
+    force: bool,
+    observer: Box<dyn Observer>,
+    cancel: CancellationToken,
+}
+
+impl Recon {
+    #[must_use]
+    pub fn builder() -> ReconBuilder {
+        ReconBuilder::default()
+    }
+
+    /// Walk the root, storing files with their fields, as a new run. Files already stored
+    /// and unchanged since are not processed again, unless `force` is set.
+    ///
+    /// Once cancelled, the scan stops after the files in flight and returns `false`, leaving
+    /// the run marked as `partial`. The next scan picks up where it stopped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O, processing or db failure
+    pub async fn scan(&mut self) -> Result<bool> {
+        let run_id = self.db.start_run(&self.root).await?;
+        info!("run: {}", run_id);
+        let scan = Scan {
+            root: &self.root,
+            paths: None,
+            source: &self.config.source,
+            resume: !self.force,
+            ignores: self.ignores,
+            one_file_system: self.one_file_system,
+            follow_symlinks: self.follow_symlinks,
+            sample: self.sample,
+            prune: self.config.source.prune.unwrap_or_default(),
+            observer: self.observer.as_ref(),
+            cancel: &self.cancel,
+            cloned: None,
+        };
+        // extracted archive members must stay on disk until their fields are computed
+        let temp_dirs = scan.store(&self.db).await?;
+        for columns in self
+            .config
+            .db
+            .iter()
+            .flat_map(|db| db.indexes.iter().flatten())
+        {
+            self.db.create_index(columns).await?;
+        }
+        if !self.cancel.is_cancelled() {
+            Box::pin(scan.compute(&self.db)).await?;
+        }
+        drop(temp_dirs);
+        if self.cancel.is_cancelled() {
+            self.db.interrupt_run().await?;
+            return Ok(false);
+        }
+        self.db.finish_run().await?;
+        Ok(true)
+    }
+
+    /// Run a query, returning rows of any shape
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the query fails
+    pub async fn query(&self, q: &str) -> Result<ValuesTable> {
+        self.db.query_table(q).await
+    }
+
+    /// Run a query selecting files, such as `select * from files where ...`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the query fails, or doesn't select `files` columns
+    pub async fn query_files(&self, q: &str) -> Result<Vec<File>> {
+        self.db.query_files(q).await
+    }
+
+    /// The underlying DB
+    #[must_use]
+    pub const fn db(&self) -> &Db {
+        &self.db
+    }
+}
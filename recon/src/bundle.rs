@@ -0,0 +1,152 @@
+use crate::data::ValuesTable;
+use crate::db::Db;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const FORMAT: &str = "recon-bundle";
+/// The bundle version written. Bundles of a later version may carry what this recon
+/// can't tell apart, and are refused.
+const VERSION: u32 = 1;
+/// The tables a bundle carries, in the order they're restored
+const TABLES: &[&str] = &[
+    "runs",
+    "files",
+    "scan_errors",
+    "snapshots",
+    "baseline",
+    "vt_cache",
+    "chunks",
+    "contents",
+    "dirs",
+];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const ZSTD_LEVEL: i32 = 9;
+
+#[derive(Serialize, Deserialize)]
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+}
+
+///
+/// What a DB holds, as JSON: each table as its column names and rows of values, with
+/// the values of `JSON` columns (such as `*_match`) as they are rather than as text.
+/// Since it goes by names rather than by the schema, any recon can import it.
+///
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    format: String,
+    version: u32,
+    /// the version of recon that exported it
+    recon: String,
+    created_at: DateTime<Utc>,
+    tables: BTreeMap<String, Table>,
+}
+
+/// A table of how many rows of each table went in or out
+fn summary(counts: Vec<(String, u64)>) -> ValuesTable {
+    let rows = counts
+        .into_iter()
+        .map(|(table, rows)| vec![serde_json::json!(table), serde_json::json!(rows)])
+        .collect::<Vec<_>>();
+    ValuesTable {
+        columns: vec!["table".to_string(), "rows".to_string()],
+        total_rows: u32::try_from(rows.len()).unwrap_or(u32::MAX),
+        rows,
+        incomplete: false,
+    }
+}
+
+/// Write every table of `db` to a zstd compressed bundle at `path`
+///
+/// # Errors
+///
+/// This function will return an error on db failure, or if the bundle can't be written
+pub async fn export(db: &Db, path: &Path) -> Result<ValuesTable> {
+    let mut tables = BTreeMap::new();
+    let mut counts = vec![];
+    for table in TABLES {
+        let vt = db.dump(table).await?;
+        counts.push(((*table).to_string(), vt.rows.len() as u64));
+        tables.insert(
+            (*table).to_string(),
+            Table {
+                columns: vt.columns,
+                rows: vt.rows,
+            },
+        );
+    }
+    let bundle = Bundle {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        recon: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        tables,
+    };
+
+    let file =
+        fs::File::create(path).with_context(|| format!("cannot create '{}'", path.display()))?;
+    let mut out = zstd::Encoder::new(BufWriter::new(file), ZSTD_LEVEL)?;
+    serde_json::to_writer(&mut out, &bundle)?;
+    out.finish()?.flush()?;
+    Ok(summary(counts))
+}
+
+/// Replace what `db` holds with a bundle at `path`, compressed or not. Tables and columns
+/// of the bundle this DB doesn't know of are left out.
+///
+/// # Errors
+///
+/// This function will return an error if the bundle can't be read or is of a later version,
+/// or on db failure
+pub async fn import(db: &Db, path: &Path) -> Result<ValuesTable> {
+    let mut reader = BufReader::new(
+        fs::File::open(path).with_context(|| format!("cannot open '{}'", path.display()))?,
+    );
+    let mut magic = [0; 4];
+    let compressed = reader.read_exact(&mut magic).is_ok() && magic == ZSTD_MAGIC;
+    let reader = fs::File::open(path)?;
+    let bundle: Bundle = if compressed {
+        serde_json::from_reader(zstd::Decoder::new(reader)?)
+    } else {
+        serde_json::from_reader(BufReader::new(reader))
+    }
+    .with_context(|| format!("'{}' is not a bundle", path.display()))?;
+
+    if bundle.format != FORMAT {
+        bail!("'{}' is not a bundle", path.display());
+    }
+    if bundle.version > VERSION {
+        bail!(
+            "'{}' is a version {} bundle, from recon {}, and this recon reads up to version {}",
+            path.display(),
+            bundle.version,
+            bundle.recon,
+            VERSION
+        );
+    }
+
+    let tables = bundle
+        .tables
+        .into_iter()
+        .map(|(name, table)| {
+            let vt = ValuesTable {
+                columns: table.columns,
+                rows: table.rows,
+                ..ValuesTable::default()
+            };
+            (name, vt)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let ordered = TABLES
+        .iter()
+        .filter_map(|name| tables.get_key_value(*name))
+        .map(|(name, vt)| (name.as_str(), vt))
+        .collect::<Vec<_>>();
+    Ok(summary(db.restore(&ordered).await?))
+}
@@ -1,8 +1,19 @@
-use anyhow::Result;
+use crate::data::File;
+use crate::processor;
+use crate::schedule::Schedule;
+use crate::scripting;
+use crate::yara_engine::Rules;
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use anyhow::{Context, Result};
+use ignore::overrides::{Override, OverrideBuilder};
 use regex::Regex;
+use serde::de::IntoDeserializer;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 ///
 /// Computed fields to add on to an indexed file
@@ -12,42 +23,1327 @@ use std::path::Path;
 ///
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct ComputedFields {
-    pub is_archive: Option<Vec<String>>,
-    pub is_document: Option<Vec<String>>,
-    pub is_media: Option<Vec<String>>,
-    pub is_code: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "extensions")]
+    pub is_archive: Option<Extensions>,
+    #[serde(default, deserialize_with = "extensions")]
+    pub is_document: Option<Extensions>,
+    #[serde(default, deserialize_with = "extensions")]
+    pub is_media: Option<Extensions>,
+    #[serde(default, deserialize_with = "extensions")]
+    pub is_code: Option<Extensions>,
     pub is_ignored: Option<Vec<String>>,
 
     pub bytes_type: Option<bool>,
     pub is_binary: Option<bool>,
-    pub file_magic: Option<bool>,
-    pub crc32: Option<bool>,
-    pub sha256: Option<bool>,
-    pub sha512: Option<bool>,
-    pub md5: Option<bool>,
-    pub simhash: Option<bool>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub file_magic: Option<FieldOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub crc32: Option<FieldOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub sha256: Option<FieldOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub sha512: Option<FieldOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub md5: Option<FieldOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub simhash: Option<FieldOptions>,
+    /// sha256 of content with line endings and trailing whitespace normalized, the same for
+    /// a text file checked out on Windows and Linux
+    #[serde(default, deserialize_with = "field_options")]
+    pub norm_hash: Option<FieldOptions>,
+    /// `MinHash` signature of text over word shingles, to find near duplicates and cluster
+    /// them with `recon similar`
+    #[serde(default, deserialize_with = "field_options")]
+    pub minhash: Option<FieldOptions>,
+    #[serde(default)]
+    pub strings: Option<StringsOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub iocs: Option<FieldOptions>,
+    #[serde(default, deserialize_with = "field_options")]
+    pub crypto_material: Option<FieldOptions>,
+    pub weak_key_perms: Option<bool>,
+    pub archive_entries: Option<bool>,
+    pub compression_ratio: Option<bool>,
+    /// strings of the `VERSIONINFO` resource of Windows executables and DLLs: company,
+    /// product, file version, original filename and the like
+    #[serde(default, deserialize_with = "field_options")]
+    pub version_info: Option<FieldOptions>,
+    #[serde(default)]
+    pub is_zip_bomb: Option<ZipBombOptions>,
+    pub git_tracked: Option<bool>,
+    pub git_last_commit: Option<bool>,
+    pub git_last_author: Option<bool>,
+    pub git_last_commit_time: Option<bool>,
+
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub crc32_match: Option<DigestSet>,
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub sha256_match: Option<DigestSet>,
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub sha512_match: Option<DigestSet>,
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub md5_match: Option<DigestSet>,
+    #[serde(default)]
+    pub simhash_match: Option<SimhashMatchOptions>,
+    #[serde(default)]
+    pub bazaar_match: Option<BazaarMatchOptions>,
+
+    #[serde(default)]
+    pub path_match: Option<Patterns<Regex>>,
+
+    #[serde(default)]
+    pub content_match: Option<Patterns<regex::bytes::Regex>>,
+
+    /// what of the text `content_match` finds goes in its snippets, rather than all of it:
+    /// `mask` its middle characters, `hash` it, or `drop` snippets. The same text is
+    /// redacted in `strings`, `iocs` and the full text index.
+    #[serde(default)]
+    pub redact: Option<Redaction>,
+
+    #[serde(default)]
+    pub literals_match: Option<LiteralsMatchOptions>,
+
+    #[serde(default)]
+    pub bytes_match: Option<BTreeMap<String, ByteSignature>>,
+
+    #[serde(default, deserialize_with = "rules_or_yara_options")]
+    pub yara_match: Option<YaraMatchOptions>,
+
+    #[serde(default)]
+    pub allowlist: Option<AllowlistOptions>,
+
+    #[serde(default)]
+    pub vt_lookup: Option<VtLookupOptions>,
+
+    pub packages: Option<bool>,
+    #[serde(default)]
+    pub osv_vulns: Option<OsvVulnsOptions>,
+    /// split files into content defined chunks, kept in the `chunks` table, to find files
+    /// sharing much of their content
+    #[serde(default)]
+    pub chunks: Option<ChunksOptions>,
+
+    /// skip content fields (digests, magic, simhash, iocs, crypto material) of files larger
+    /// than this, such as `2GB`. a field's own `max_size` takes precedence
+    #[serde(default, deserialize_with = "size")]
+    pub max_file_size: Option<u64>,
+
+    /// seconds a content scanning field (digests, magic, strings, iocs, crypto material,
+    /// yara, content and bytes matches) may spend on a file before it's given up on, and
+    /// recorded in `errors` (default: no limit)
+    #[serde(default)]
+    pub field_timeout: Option<u64>,
+
+    /// a script deciding if the fields here are computed for a file at all, such as
+    /// `file.size < 1000000 && file.ext in ["rs", "toml"]`
+    #[serde(default)]
+    pub process_if: Option<Script>,
+
+    /// custom fields computed by scripts, by name. stored in the `extra` column
+    #[serde(default)]
+    pub scripted_fields: Option<BTreeMap<String, Script>>,
+
+    /// custom fields computed by processors registered by name (see `processor::Processor`),
+    /// along with their options, or `true`. stored in the `extra` column
+    #[serde(default)]
+    pub extra: Option<BTreeMap<String, serde_yaml::Value>>,
+
+    /// stop at the first file or field that fails, instead of recording the failure in
+    /// `errors` or `scan_errors` and moving on. set with `--strict`
+    #[serde(skip)]
+    pub strict: bool,
+}
+
+impl ComputedFields {
+    /// The fields turned on here by name, each with the size of files above which it
+    /// skips them, if it does
+    #[must_use]
+    pub fn enabled_fields(&self) -> Vec<(String, Option<u64>)> {
+        // options for all the fields here, rather than fields
+        const OPTIONS: [&str; 3] = ["max_file_size", "field_timeout", "process_if"];
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(self) else {
+            return vec![];
+        };
+        fields
+            .into_iter()
+            .filter(|(name, _)| !OPTIONS.contains(&name.as_str()))
+            .filter_map(|(name, value)| match value {
+                serde_json::Value::Null | serde_json::Value::Bool(false) => None,
+                serde_json::Value::Object(options)
+                    if options.len() == 2
+                        && options.contains_key("enabled")
+                        && options.contains_key("max_size") =>
+                {
+                    // field options
+                    (options["enabled"] != serde_json::Value::Bool(false)).then(|| {
+                        let max_size = options["max_size"].as_u64().or(self.max_file_size);
+                        (name, max_size)
+                    })
+                }
+                _ if name == "yara_match" => {
+                    let max_size = self.yara_match.as_ref().and_then(|yara| yara.max_size);
+                    Some((name, max_size))
+                }
+                _ => Some((name, None)),
+            })
+            .collect()
+    }
+
+    /// Turn on the fields others here are computed from, such as `sha256` for `sha256_match`,
+    /// unless the `before` stage computes them already, even if they are turned off here
+    #[must_use]
+    pub fn with_dependencies(mut self, before: &Self) -> Self {
+        let allowlist = self.allowlist.as_ref();
+        let needs = [
+            (
+                self.crc32_match.is_some() || allowlist.is_some_and(|a| a.crc32.is_some()),
+                &mut self.crc32,
+                &before.crc32,
+            ),
+            (
+                self.sha256_match.is_some()
+                    || self.bazaar_match.is_some()
+                    || self.vt_lookup.is_some()
+                    || allowlist.is_some_and(|a| a.sha256.is_some()),
+                &mut self.sha256,
+                &before.sha256,
+            ),
+            (
+                self.sha512_match.is_some() || allowlist.is_some_and(|a| a.sha512.is_some()),
+                &mut self.sha512,
+                &before.sha512,
+            ),
+            (
+                self.md5_match.is_some() || allowlist.is_some_and(|a| a.md5.is_some()),
+                &mut self.md5,
+                &before.md5,
+            ),
+            (
+                self.simhash_match.is_some(),
+                &mut self.simhash,
+                &before.simhash,
+            ),
+        ];
+        let enabled =
+            |options: &Option<FieldOptions>| options.as_ref().is_some_and(FieldOptions::enabled);
+        for (needed, field, before) in needs {
+            if needed && !enabled(field) && !enabled(before) {
+                field.get_or_insert_with(FieldOptions::default).enabled = Some(true);
+            }
+        }
+        // yara skips files by their class, which needs classifying them
+        let yara = self.yara_match.as_ref();
+        let classes = [
+            (
+                yara.is_some_and(|y| y.skip_archives.unwrap_or(false)),
+                &mut self.is_archive,
+                &before.is_archive,
+            ),
+            (
+                yara.is_some_and(|y| y.skip_media.unwrap_or(false)),
+                &mut self.is_media,
+                &before.is_media,
+            ),
+        ];
+        for (needed, class, before) in classes {
+            if needed && class.is_none() && before.is_none() {
+                *class = Some(Extensions::Builtin);
+            }
+        }
+        self
+    }
+
+    fn problems(&self, section: &str, problems: &mut Vec<String>) {
+        let allowlist = self.allowlist.as_ref();
+        let digest_sets = [
+            ("crc32_match", self.crc32_match.as_ref()),
+            ("sha256_match", self.sha256_match.as_ref()),
+            ("sha512_match", self.sha512_match.as_ref()),
+            ("md5_match", self.md5_match.as_ref()),
+            ("allowlist.crc32", allowlist.and_then(|a| a.crc32.as_ref())),
+            (
+                "allowlist.sha256",
+                allowlist.and_then(|a| a.sha256.as_ref()),
+            ),
+            (
+                "allowlist.sha512",
+                allowlist.and_then(|a| a.sha512.as_ref()),
+            ),
+            ("allowlist.md5", allowlist.and_then(|a| a.md5.as_ref())),
+        ];
+        for (field, set) in digest_sets {
+            if let Some(Err(err)) = set.map(DigestSet::set) {
+                problems.push(format!("{}.{}: {}", section, field, describe(&err)));
+            }
+        }
+        if let Some(Err(err)) = self
+            .literals_match
+            .as_ref()
+            .map(LiteralsMatchOptions::matcher)
+        {
+            problems.push(format!("{}.literals_match: {}", section, describe(&err)));
+        }
+        if let Some(avg_size) = self.chunks.as_ref().and_then(|c| c.avg_size) {
+            if !ChunksOptions::AVG_SIZES.contains(&avg_size) {
+                problems.push(format!(
+                    "{}.chunks: avg_size should be 1KB to 4MB, got {}",
+                    section, avg_size
+                ));
+            }
+        }
+        for hash in self.simhash_match.iter().flat_map(|opts| &opts.hashes) {
+            if u64::from_str_radix(hash, 16).is_err() {
+                problems.push(format!(
+                    "{}.simhash_match: invalid simhash '{}'",
+                    section, hash
+                ));
+            }
+        }
+        let scripts = self
+            .process_if
+            .iter()
+            .map(|script| ("process_if".to_string(), script))
+            .chain(
+                self.scripted_fields
+                    .iter()
+                    .flatten()
+                    .map(|(name, script)| (format!("scripted_fields.{}", name), script)),
+            );
+        for (field, script) in scripts {
+            if let Err(err) = script.ast() {
+                problems.push(format!("{}.{}: {}", section, field, describe(&err)));
+            }
+        }
+        for (name, options) in self.extra.iter().flatten() {
+            if *options != serde_yaml::Value::Bool(false) && !processor::is_registered(name) {
+                problems.push(format!(
+                    "{}.extra: no processor registered as '{}'",
+                    section, name
+                ));
+            }
+        }
+        if let Some(yara) = &self.yara_match {
+            if let Err(err) = Rules::compile(&File::default(), &yara.rules) {
+                problems.push(format!("{}.yara_match: {}", section, describe(&err)));
+            }
+        }
+    }
+}
+
+///
+/// Extensions making up a class of files (`is_archive`, `is_code`, ...): the built-in list
+/// for the class, or a list to use instead
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Extensions {
+    Builtin,
+    List(Vec<String>),
+}
+
+/// A class is `true` (or given no value) for the built-in extensions, `false` to skip it,
+/// or a list of extensions
+fn extensions<'de, D>(deserializer: D) -> Result<Option<Extensions>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Visitor;
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Option<Extensions>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("true for the built-in extensions, or a list of extensions")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Some(Extensions::Builtin))
+        }
+
+        fn visit_bool<E: serde::de::Error>(self, enabled: bool) -> Result<Self::Value, E> {
+            Ok(enabled.then_some(Extensions::Builtin))
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            serde::Deserialize::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+                .map(|list| Some(Extensions::List(list)))
+        }
+    }
+    deserializer.deserialize_any(Visitor)
+}
+
+///
+/// How serious a match of a rule is, to read matches as findings
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+///
+/// How matched text is kept out of snippets, so a scan for secrets doesn't store them
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Redaction {
+    /// keep the first and last two characters of long enough text, and mask the rest
+    Mask,
+    /// replace the text with the start of its sha256, to tell the same secret apart
+    Hash,
+    /// leave snippets out
+    Drop,
+}
+
+///
+/// What a rule (a yara set, pattern or digest set) stands for, recorded on its matches
+///
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl RuleInfo {
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.name.is_none() && self.severity.is_none() && self.description.is_none()
+    }
+
+    /// This info, named `name` unless it has a name of its own
+    #[must_use]
+    pub fn named(&self, name: &str) -> Self {
+        Self {
+            name: self.name.clone().or_else(|| Some(name.to_string())),
+            ..self.clone()
+        }
+    }
+}
+
+///
+/// A pattern of a named set, with what it stands for
+///
+#[derive(Clone, Debug, Serialize)]
+#[serde(bound(serialize = "serde_regex::Serde<T>: serde::Serialize"))]
+pub struct NamedPattern<T> {
+    pub pattern: serde_regex::Serde<T>,
+
+    #[serde(flatten)]
+    pub info: RuleInfo,
+}
+
+///
+/// A single regex, or several by name to tell which of them matched
+///
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+#[serde(bound(serialize = "serde_regex::Serde<T>: serde::Serialize"))]
+pub enum Patterns<T> {
+    One(serde_regex::Serde<T>),
+    Named(BTreeMap<String, NamedPattern<T>>),
+}
+
+// by hand, so a bad regex is reported as such rather than as a mismatched shape
+impl<'de, T> serde::Deserialize<'de> for Patterns<T>
+where
+    serde_regex::Serde<T>: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawPattern {
+            Plain(String),
+            Rule {
+                pattern: String,
+                #[serde(flatten)]
+                info: RuleInfo,
+            },
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            One(String),
+            Named(BTreeMap<String, RawPattern>),
+        }
+        let compile =
+            |pattern: String| serde::Deserialize::deserialize(pattern.into_deserializer());
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::One(pattern) => Self::One(compile(pattern)?),
+            Raw::Named(patterns) => Self::Named(
+                patterns
+                    .into_iter()
+                    .map(|(name, raw)| {
+                        let (pattern, info) = match raw {
+                            RawPattern::Plain(pattern) => (pattern, RuleInfo::default()),
+                            RawPattern::Rule { pattern, info } => (pattern, info),
+                        };
+                        Ok((
+                            name,
+                            NamedPattern {
+                                pattern: compile(pattern)?,
+                                info,
+                            },
+                        ))
+                    })
+                    .collect::<Result<_, D::Error>>()?,
+            ),
+        })
+    }
+}
+
+impl<T> Patterns<T> {
+    /// Patterns with their names, a single pattern goes by `default`
+    #[must_use]
+    pub fn named<'a>(&'a self, default: &'a str) -> Vec<(&'a str, &'a T)> {
+        match self {
+            Self::One(re) => vec![(default, &re.0)],
+            Self::Named(res) => res
+                .iter()
+                .map(|(name, p)| (name.as_str(), &p.pattern.0))
+                .collect(),
+        }
+    }
+
+    /// What the pattern named `name` stands for, if told
+    #[must_use]
+    pub fn info(&self, name: &str) -> Option<&RuleInfo> {
+        match self {
+            Self::One(_) => None,
+            Self::Named(res) => res.get(name).map(|p| &p.info),
+        }
+    }
+}
+
+///
+/// Digests to match, listed inline or loaded from a file, such as a known-bad hash set
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct DigestSet {
+    /// digests, in hex
+    #[serde(default)]
+    pub digests: Vec<String>,
+
+    /// a file with more digests, one per line. blank lines and `#` comments are skipped
+    #[serde(default)]
+    pub from_file: Option<String>,
+
+    /// what matching a digest of the set stands for
+    #[serde(flatten)]
+    pub info: RuleInfo,
+
+    /// built on first use, and shared by all files
+    #[serde(skip)]
+    set: OnceLock<HashSet<Box<[u8]>>>,
+}
+
+impl DigestSet {
+    /// Whether `digest` is in the set, regardless of hex case
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the digests file cannot be read, or has
+    /// an invalid digest
+    pub fn contains(&self, digest: &str) -> Result<bool> {
+        let set = self.set()?;
+        Ok(digest_key(digest).is_some_and(|key| set.contains(&key)))
+    }
+
+    fn set(&self) -> Result<&HashSet<Box<[u8]>>> {
+        if let Some(set) = self.set.get() {
+            return Ok(set);
+        }
+        // kept as bytes rather than hex strings, as sets can have millions of digests
+        let mut set = HashSet::new();
+        for digest in &self.digests {
+            set.insert(digest_key(digest).with_context(|| format!("invalid digest '{}'", digest))?);
+        }
+        if let Some(file) = &self.from_file {
+            let text = fs::read_to_string(file)
+                .with_context(|| format!("cannot read digests from '{}'", file))?;
+            for (i, line) in text.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                set.insert(digest_key(line).with_context(|| {
+                    format!("invalid digest '{}' in '{}' line {}", line, file, i + 1)
+                })?);
+            }
+        }
+        Ok(self.set.get_or_init(|| set))
+    }
+}
+
+///
+/// The abuse.ch malware bazaar feed of recent malware sha256 digests, downloaded and kept in a
+/// cache file between runs
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct BazaarMatchOptions {
+    /// feed URL, one digest per line (default: the recent sha256 export)
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// hours before the cached feed is downloaded again (default: 1)
+    #[serde(default)]
+    pub refresh_hours: Option<u32>,
+
+    /// where to keep the feed (default: `recon/bazaar.txt` in the user's cache folder)
+    #[serde(default)]
+    pub cache_file: Option<String>,
+
+    /// loaded on first use, and shared by all files
+    #[serde(skip)]
+    digests: OnceLock<DigestSet>,
+}
+
+impl BazaarMatchOptions {
+    #[must_use]
+    pub fn url(&self) -> &str {
+        self.url
+            .as_deref()
+            .unwrap_or("https://bazaar.abuse.ch/export/txt/sha256/recent/")
+    }
+
+    /// Where the feed is kept
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no `cache_file` is given, and the user has no
+    /// cache folder
+    pub fn cache_file(&self) -> Result<PathBuf> {
+        if let Some(cache_file) = &self.cache_file {
+            return Ok(PathBuf::from(cache_file));
+        }
+        let cache_dir = dirs::cache_dir()
+            .context("no cache folder for the bazaar feed, set `cache_file`")?
+            .join("recon");
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("cannot create cache '{}'", cache_dir.display()))?;
+        Ok(cache_dir.join("bazaar.txt"))
+    }
+
+    /// The feed digests, downloading the feed first if the cached one is missing or older
+    /// than `refresh_hours`. A stale cache is used when the feed can't be downloaded.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there's no feed to use
+    pub fn digests(&self) -> Result<&DigestSet> {
+        if let Some(digests) = self.digests.get() {
+            return Ok(digests);
+        }
+        let cache_file = self.cache_file()?;
+        let max_age = Duration::from_secs(u64::from(self.refresh_hours.unwrap_or(1)) * 3600);
+        let fresh = fs::metadata(&cache_file)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age < max_age);
+        if !fresh {
+            if let Err(err) = download(self.url(), &cache_file) {
+                if !cache_file.exists() {
+                    return Err(err);
+                }
+                tracing::warn!(
+                    "using a stale feed in '{}': {:#}",
+                    cache_file.display(),
+                    err
+                );
+            }
+        }
+        let digests = DigestSet {
+            from_file: Some(cache_file.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        // loaded now, for errors to surface here
+        digests.set()?;
+        Ok(self.digests.get_or_init(|| digests))
+    }
+}
+
+/// Download `url` into `file`, replacing it only once fully downloaded. The blocking client
+/// can't run on the async runtime configs are loaded and fields computed on, so it gets a
+/// thread of its own.
+fn download(url: &str, file: &Path) -> Result<()> {
+    let text = std::thread::scope(|scope| {
+        scope
+            .spawn(|| reqwest::blocking::get(url)?.error_for_status()?.text())
+            .join()
+    })
+    .map_err(|_| anyhow::anyhow!("download thread panicked"))
+    .and_then(|res| Ok(res?))
+    .with_context(|| format!("cannot download '{}'", url))?;
+    let partial = file.with_extension("partial");
+    fs::write(&partial, text)?;
+    fs::rename(&partial, file)?;
+    Ok(())
+}
+
+///
+/// Known good digests (such as NSRL), to mark files with `is_known_good`
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct AllowlistOptions {
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub crc32: Option<DigestSet>,
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub sha256: Option<DigestSet>,
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub sha512: Option<DigestSet>,
+    #[serde(default, deserialize_with = "digests_or_set")]
+    pub md5: Option<DigestSet>,
+
+    /// leave known good files out of the `*_match` fields (default: true)
+    #[serde(default)]
+    pub exclude_matches: Option<bool>,
+}
+
+impl AllowlistOptions {
+    #[must_use]
+    pub fn exclude_matches(&self) -> bool {
+        self.exclude_matches.unwrap_or(true)
+    }
+}
+
+/// A hex digest as bytes, without leading zeros so that unpadded digests (crc32) compare
+/// equal to padded ones
+fn digest_key(hex: &str) -> Option<Box<[u8]>> {
+    let hex = hex.trim_start_matches('0');
+    let padded = if hex.len() % 2 == 1 {
+        format!("0{}", hex)
+    } else {
+        hex.to_string()
+    };
+    (0..padded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(padded.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+///
+/// A content field which is either on (`true`), or given options
+///
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldOptions {
+    /// (default: true)
+    #[serde(default)]
+    pub enabled: Option<bool>,
+
+    /// skip files larger than this, such as `100MB` (default: `max_file_size`)
+    #[serde(default, deserialize_with = "size")]
+    pub max_size: Option<u64>,
+}
+
+impl FieldOptions {
+    #[must_use]
+    pub fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Why the field is skipped for a file of `size` bytes, if it is
+    #[must_use]
+    pub fn skip_reason(&self, size: Option<i64>, max_file_size: Option<u64>) -> Option<String> {
+        let max_size = self.max_size.or(max_file_size)?;
+        let size = u64::try_from(size?).ok()?;
+        (self.enabled() && size > max_size).then(|| format!("larger than {} bytes", max_size))
+    }
+}
+
+/// Field options are either a bool, or a map of options
+fn field_options<'de, D>(deserializer: D) -> Result<Option<FieldOptions>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Visitor;
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Option<FieldOptions>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("a bool, or a map with enabled and max_size")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_bool<E: serde::de::Error>(self, enabled: bool) -> Result<Self::Value, E> {
+            Ok(Some(FieldOptions {
+                enabled: Some(enabled),
+                ..Default::default()
+            }))
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            serde::Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                .map(Some)
+        }
+    }
+    deserializer.deserialize_any(Visitor)
+}
+
+/// Parse a size in bytes, optionally with a unit: `512`, `64KB`, `1.5 GiB`. `K`, `M`, `G`
+/// and `T` (with or without `B`) are powers of 1000, and `KiB`, `MiB`, ... powers of 1024.
+///
+/// # Errors
+///
+/// This function will return an error if the size can't be parsed
+pub fn parse_size(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("invalid size '{}'", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000_u64.pow(2),
+        "g" | "gb" => 1000_u64.pow(3),
+        "t" | "tb" => 1000_u64.pow(4),
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => anyhow::bail!("invalid size unit '{}' in '{}'", unit.trim(), text),
+    };
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// A size is a number of bytes, or a string with a unit (see [`parse_size`])
+fn size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Visitor;
+    impl serde::de::Visitor<'_> for Visitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("a number of bytes, or a size such as 100MB")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, size: u64) -> Result<Self::Value, E> {
+            Ok(Some(size))
+        }
+
+        fn visit_str<E: serde::de::Error>(self, size: &str) -> Result<Self::Value, E> {
+            parse_size(size).map(Some).map_err(E::custom)
+        }
+    }
+    deserializer.deserialize_any(Visitor)
+}
+
+/// A digest matcher is either a list of digests, or a map with digests and a file to load
+fn digests_or_set<'de, D>(deserializer: D) -> Result<Option<DigestSet>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Visitor;
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Option<DigestSet>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("a list of digests, or a map with digests and from_file")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Ok(Some(DigestSet {
+                digests: serde::Deserialize::deserialize(
+                    serde::de::value::SeqAccessDeserializer::new(seq),
+                )?,
+                ..Default::default()
+            }))
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            serde::Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                .map(Some)
+        }
+    }
+    deserializer.deserialize_any(Visitor)
+}
+
+///
+/// Literal strings to find in content, all at once
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct LiteralsMatchOptions {
+    /// strings to look for
+    #[serde(default)]
+    pub literals: Vec<String>,
+
+    /// a file with more strings to look for, one per line
+    #[serde(default)]
+    pub file: Option<String>,
+
+    /// ignore ASCII case (default: false)
+    #[serde(default)]
+    pub case_insensitive: Option<bool>,
+
+    /// built on first use, and shared by all files
+    #[serde(skip)]
+    matcher: OnceLock<(AhoCorasick, Vec<String>)>,
+}
+
+impl LiteralsMatchOptions {
+    /// The automaton matching all literals, along with the literals by pattern id
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the literals file cannot be read
+    pub fn matcher(&self) -> Result<&(AhoCorasick, Vec<String>)> {
+        if let Some(matcher) = self.matcher.get() {
+            return Ok(matcher);
+        }
+        let mut literals = self.literals.clone();
+        if let Some(file) = &self.file {
+            let text = fs::read_to_string(file)
+                .with_context(|| format!("cannot read literals from '{}'", file))?;
+            literals.extend(
+                text.lines()
+                    .filter(|l| !l.is_empty())
+                    .map(ToString::to_string),
+            );
+        }
+        // standard matching, so that literals inside or overlapping others are found too
+        let automaton = AhoCorasickBuilder::new()
+            .match_kind(MatchKind::Standard)
+            .ascii_case_insensitive(self.case_insensitive.unwrap_or(false))
+            .build(&literals);
+        Ok(self.matcher.get_or_init(|| (automaton, literals)))
+    }
+}
+
+///
+/// YARA rules, with limits on which files to scan and for how long
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct YaraMatchOptions {
+    /// rules source
+    pub rules: String,
+
+    /// seconds to scan a file before giving up on it (default: 5)
+    #[serde(default)]
+    pub timeout: Option<i32>,
+
+    /// skip files larger than this, in bytes
+    #[serde(default)]
+    pub max_size: Option<u64>,
+
+    /// skip files classified by `is_archive`, with the built-in extensions unless it's
+    /// given (default: false)
+    #[serde(default)]
+    pub skip_archives: Option<bool>,
+
+    /// skip files classified by `is_media`, with the built-in extensions unless it's
+    /// given (default: false)
+    #[serde(default)]
+    pub skip_media: Option<bool>,
+
+    /// what a match of these rules stands for
+    #[serde(flatten)]
+    pub info: RuleInfo,
+}
+
+impl YaraMatchOptions {
+    #[must_use]
+    pub fn timeout(&self) -> i32 {
+        self.timeout.unwrap_or(5)
+    }
+}
+
+/// `yara_match` is either just the rules, or the rules along with scan options
+fn rules_or_yara_options<'de, D>(deserializer: D) -> Result<Option<YaraMatchOptions>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Visitor;
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Option<YaraMatchOptions>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("yara rules, or a map with rules and scan options")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_str<E: serde::de::Error>(self, rules: &str) -> Result<Self::Value, E> {
+            Ok(Some(YaraMatchOptions {
+                rules: rules.to_string(),
+                ..Default::default()
+            }))
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            serde::Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                .map(Some)
+        }
+    }
+    deserializer.deserialize_any(Visitor)
+}
+
+///
+/// Split files into content defined chunks (`FastCDC`) once all fields are computed, so
+/// files sharing much of their content share chunks even when bytes moved around
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ChunksOptions {
+    /// the size chunks are around, such as `64KB`. chunks are a quarter to 4 times that
+    /// (default: 64KB)
+    #[serde(default, deserialize_with = "size")]
+    pub avg_size: Option<u64>,
+}
+
+impl ChunksOptions {
+    /// average sizes the chunker works with
+    pub const AVG_SIZES: std::ops::RangeInclusive<u64> = 1024..=4 * 1024 * 1024;
+
+    #[must_use]
+    pub fn avg_size(&self) -> u64 {
+        let (min, max) = Self::AVG_SIZES.into_inner();
+        self.avg_size.unwrap_or(64 * 1024).clamp(min, max)
+    }
+}
+
+///
+/// Look up sha256 digests on VT, once all fields are computed
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct VtLookupOptions {
+    /// env var holding the API key (default: `VT_API_KEY`)
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+
+    /// requests to make per minute at most (default: 4, the public API limit)
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+
+    /// days a verdict is kept in the DB before it's looked up again (default: 7)
+    #[serde(default)]
+    pub cache_days: Option<u32>,
+
+    /// API base URL (default: `https://www.virustotal.com/api/v3`)
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl VtLookupOptions {
+    #[must_use]
+    pub fn api_key_env(&self) -> &str {
+        self.api_key_env.as_deref().unwrap_or("VT_API_KEY")
+    }
+
+    #[must_use]
+    pub fn requests_per_minute(&self) -> u32 {
+        self.requests_per_minute.unwrap_or(4).max(1)
+    }
+
+    #[must_use]
+    pub fn cache_days(&self) -> u32 {
+        self.cache_days.unwrap_or(7)
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &str {
+        self.url
+            .as_deref()
+            .unwrap_or("https://www.virustotal.com/api/v3")
+    }
+}
+
+///
+/// Look up known vulnerabilities of `packages` on OSV, once all fields are computed
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct OsvVulnsOptions {
+    /// API base URL (default: `https://api.osv.dev/v1`)
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl OsvVulnsOptions {
+    #[must_use]
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or("https://api.osv.dev/v1")
+    }
+}
+
+///
+/// A byte signature, in hex with `??` for any byte, and optionally anchored at an
+/// offset: `4D 5A ?? ?? @0`
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ByteSignature {
+    /// bytes to match, `None` matches any byte
+    pub bytes: Vec<Option<u8>>,
+    /// where the signature must start, anywhere if not given
+    pub offset: Option<usize>,
+}
+
+impl TryFrom<String> for ByteSignature {
+    type Error = String;
+
+    fn try_from(sig: String) -> Result<Self, Self::Error> {
+        let mut bytes = vec![];
+        let mut offset = None;
+        for token in sig.split_whitespace() {
+            if let Some(at) = token.strip_prefix('@') {
+                let parsed = at.strip_prefix("0x").map_or_else(
+                    || at.parse::<usize>().ok(),
+                    |hex| usize::from_str_radix(hex, 16).ok(),
+                );
+                offset =
+                    Some(parsed.ok_or_else(|| format!("invalid offset '{}' in '{}'", token, sig))?);
+                continue;
+            }
+            for pair in token.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).unwrap_or_default();
+                bytes.push(match pair {
+                    "??" => None,
+                    _ if pair.len() != 2 => {
+                        return Err(format!("odd number of hex digits in '{}'", sig))
+                    }
+                    // from_str_radix would take a sign, such as `+1`
+                    _ => Some(
+                        u8::from_str_radix(pair, 16)
+                            .ok()
+                            .filter(|_| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                            .ok_or_else(|| format!("invalid byte '{}' in '{}'", pair, sig))?,
+                    ),
+                });
+            }
+        }
+        if bytes.is_empty() {
+            return Err(format!("empty signature '{}'", sig));
+        }
+        Ok(Self { bytes, offset })
+    }
+}
+
+impl From<ByteSignature> for String {
+    fn from(sig: ByteSignature) -> Self {
+        let bytes = sig
+            .bytes
+            .iter()
+            .map(|b| b.map_or_else(|| "??".to_string(), |b| format!("{:02X}", b)));
+        let offset = sig.offset.map(|o| format!("@{}", o));
+        bytes.chain(offset).collect::<Vec<_>>().join(" ")
+    }
+}
+
+impl ByteSignature {
+    /// Does the signature match `content` at `at`
+    #[must_use]
+    pub fn matches_at(&self, content: &[u8], at: usize) -> bool {
+        at.checked_add(self.bytes.len())
+            .and_then(|end| content.get(at..end))
+            .is_some_and(|window| {
+                window
+                    .iter()
+                    .zip(&self.bytes)
+                    .all(|(b, sig)| sig.is_none() || *sig == Some(*b))
+            })
+    }
+}
+
+///
+/// Options for printable strings extraction
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct StringsOptions {
+    /// minimum length of a run of printable characters to be considered a string (default: 4)
+    #[serde(default)]
+    pub min_len: Option<usize>,
 
+    /// include UTF-16LE encoded strings, common in Windows binaries (default: true)
     #[serde(default)]
-    pub crc32_match: Option<Vec<String>>,
+    pub utf16: Option<bool>,
+
+    /// truncate stored strings to this many bytes
     #[serde(default)]
-    pub sha256_match: Option<Vec<String>>,
+    pub max_size: Option<usize>,
+}
+
+impl StringsOptions {
+    #[must_use]
+    pub fn min_len(&self) -> usize {
+        self.min_len.unwrap_or(4)
+    }
+
+    #[must_use]
+    pub fn utf16(&self) -> bool {
+        self.utf16.unwrap_or(true)
+    }
+}
+
+///
+/// Options for zip bomb detection
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ZipBombOptions {
+    /// flag archives whose uncompressed/compressed ratio exceeds this (default: 100)
     #[serde(default)]
-    pub sha512_match: Option<Vec<String>>,
+    pub max_ratio: Option<f64>,
+}
+
+impl ZipBombOptions {
+    #[must_use]
+    pub fn max_ratio(&self) -> f64 {
+        self.max_ratio.unwrap_or(100.0)
+    }
+}
+
+///
+/// Reference hashes for near-duplicate matching with simhash
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct SimhashMatchOptions {
+    /// simhash values (hex) of reference files
     #[serde(default)]
-    pub md5_match: Option<Vec<String>>,
+    pub hashes: Vec<String>,
+
+    /// match files within this many differing bits of a reference (default: 3)
     #[serde(default)]
-    pub simhash_match: Option<Vec<String>>,
+    pub max_distance: Option<u32>,
+}
+
+impl SimhashMatchOptions {
+    #[must_use]
+    pub fn max_distance(&self) -> u32 {
+        self.max_distance.unwrap_or(3)
+    }
+}
+
+///
+/// A Rhai script run on files, which sees the file with the fields computed so far as `file`
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Script {
+    pub source: String,
+
+    /// compiled on first use, and shared by all files
+    ast: OnceLock<rhai::AST>,
+}
+
+impl From<String> for Script {
+    fn from(source: String) -> Self {
+        Self {
+            source,
+            ast: OnceLock::new(),
+        }
+    }
+}
+
+impl From<Script> for String {
+    fn from(script: Script) -> Self {
+        script.source
+    }
+}
+
+impl Script {
+    /// The compiled script
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the script doesn't compile
+    pub fn ast(&self) -> Result<&rhai::AST> {
+        if let Some(ast) = self.ast.get() {
+            return Ok(ast);
+        }
+        let ast = scripting::compile(&self.source)?;
+        Ok(self.ast.get_or_init(|| ast))
+    }
+}
 
-    #[serde(with = "serde_regex")]
+///
+/// Options for unpacking archives found while walking
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct UnpackOptions {
+    /// how many levels of nested archives to extract (default: 3)
     #[serde(default)]
-    pub path_match: Option<Regex>,
+    pub max_depth: Option<usize>,
 
-    #[serde(with = "serde_regex")]
+    /// stop extracting an archive after this many bytes in total (default: 1GB)
     #[serde(default)]
-    pub content_match: Option<regex::bytes::Regex>,
+    pub max_total_bytes: Option<u64>,
 
+    /// keep extracted files around after the run, for inspection (default: false)
     #[serde(default)]
-    pub yara_match: Option<String>,
+    pub keep_temp: Option<bool>,
+}
+
+impl UnpackOptions {
+    #[must_use]
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(3)
+    }
+
+    #[must_use]
+    pub fn max_total_bytes(&self) -> u64 {
+        self.max_total_bytes.unwrap_or(1024 * 1024 * 1024)
+    }
+
+    #[must_use]
+    pub fn keep_temp(&self) -> bool {
+        self.keep_temp.unwrap_or(false)
+    }
+}
+
+///
+/// Options for indexing the content of text files, to search with `match` in `contents`
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct FullTextOptions {
+    /// skip files larger than this, such as `10MB` (default: 1MB)
+    #[serde(default, deserialize_with = "size")]
+    pub max_size: Option<u64>,
+}
+
+impl FullTextOptions {
+    #[must_use]
+    pub fn max_size(&self) -> u64 {
+        self.max_size.unwrap_or(1024 * 1024)
+    }
+
+    /// Selects the files small enough to index, whose content isn't indexed yet
+    #[must_use]
+    pub fn query(&self) -> String {
+        format!(
+            "select * from files where size <= {} and not exists \
+             (select 1 from contents where contents.rowid = files.id)",
+            self.max_size()
+        )
+    }
 }
 
 ///
@@ -61,6 +1357,10 @@ pub struct Source {
     #[serde(default)]
     pub query: Option<String>,
 
+    /// queries by name, run with `--query-name` for a result set each
+    #[serde(default)]
+    pub queries: Option<BTreeMap<String, String>>,
+
     #[serde(default)]
     pub before_computed_fields_query: Option<String>,
 
@@ -69,6 +1369,60 @@ pub struct Source {
 
     #[serde(default)]
     pub computed_fields: Option<ComputedFields>,
+
+    /// more fields computed after `computed_fields`, each on the files its filter narrows
+    /// down to, in order
+    #[serde(default)]
+    pub stages: Option<Vec<Stage>>,
+
+    #[serde(default)]
+    pub unpack: Option<UnpackOptions>,
+
+    /// keep the content of text files in the `contents` full text index
+    #[serde(default)]
+    pub full_text: Option<FullTextOptions>,
+
+    /// sum up the files under each folder in the `dirs` table, once fields are computed
+    #[serde(default)]
+    pub dirs: Option<bool>,
+
+    /// store folders and symlinks too, not just files, with their content fields left out
+    #[serde(default)]
+    pub dirs_and_links: Option<bool>,
+
+    /// don't walk deeper than this many levels below the root (1: only files right in it)
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+
+    /// skip files smaller than this while walking, such as `10KB`
+    #[serde(default, deserialize_with = "size")]
+    pub min_size: Option<u64>,
+
+    /// skip files larger than this while walking, such as `2GB`
+    #[serde(default, deserialize_with = "size")]
+    pub max_size: Option<u64>,
+
+    /// only walk files matching one of these globs (in `.gitignore` syntax, from the
+    /// root), such as `*.rs`. These take precedence over `.gitignore`
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+
+    /// leave out files and folders matching any of these globs while walking, such as
+    /// `node_modules` or `target/`
+    #[serde(default)]
+    pub exclude: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub prune: Option<bool>,
+
+    /// re-scan on this cron schedule while serving or watching, such as `0 3 * * *`
+    #[serde(default)]
+    pub schedule: Option<String>,
+
+    /// findings to accept, by rule, path glob and sha256, each until an optional expiry
+    /// date (default: `.recon-suppress.yaml`, when there's one)
+    #[serde(default)]
+    pub suppress_file: Option<String>,
 }
 
 impl Source {
@@ -77,6 +1431,71 @@ impl Source {
         self.root.clone().unwrap_or_else(|| ".".to_string())
     }
 
+    /// The `include` and `exclude` globs as overrides of a walk from `root`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a glob is invalid
+    pub fn overrides(&self, root: &str) -> Result<Override> {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in self.include.iter().flatten() {
+            overrides
+                .add(glob)
+                .with_context(|| format!("invalid include glob '{}'", glob))?;
+        }
+        for glob in self.exclude.iter().flatten() {
+            overrides
+                .add(&format!("!{}", glob))
+                .with_context(|| format!("invalid exclude glob '{}'", glob))?;
+        }
+        Ok(overrides.build()?)
+    }
+
+    /// The named `queries` given by `names`, or all of them when none are given
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a name isn't one of `queries`, or there are no
+    /// queries at all
+    pub fn named_queries(&self, names: &[String]) -> Result<Vec<(String, String)>> {
+        let queries = self.queries.clone().unwrap_or_default();
+        if names.is_empty() {
+            if queries.is_empty() {
+                anyhow::bail!("no named queries in config, add some under `source.queries`");
+            }
+            return Ok(queries.into_iter().collect());
+        }
+        names
+            .iter()
+            .map(|name| {
+                let Some(query) = queries.get(name) else {
+                    let known = queries.keys().cloned().collect::<Vec<_>>().join(", ");
+                    anyhow::bail!(
+                        "no query '{}' in config (queries: {})",
+                        name,
+                        if known.is_empty() { "none" } else { &known }
+                    );
+                };
+                Ok((name.clone(), query.clone()))
+            })
+            .collect()
+    }
+
+    /// The `schedule` to re-scan on, if any
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the schedule is invalid
+    pub fn schedule(&self) -> Result<Option<Schedule>> {
+        self.schedule
+            .as_ref()
+            .map(|expr| {
+                expr.parse()
+                    .with_context(|| format!("invalid schedule '{}'", expr))
+            })
+            .transpose()
+    }
+
     #[must_use]
     pub fn query(&self) -> String {
         self.before_computed_fields_query
@@ -86,12 +1505,163 @@ impl Source {
 
     #[must_use]
     pub fn default_fields(&self) -> ComputedFields {
-        self.default_fields.clone().unwrap_or_default()
+        self.default_fields
+            .clone()
+            .unwrap_or_default()
+            .with_dependencies(&ComputedFields::default())
     }
 
     #[must_use]
     pub fn computed_fields(&self) -> ComputedFields {
-        self.computed_fields.clone().unwrap_or_default()
+        self.computed_fields
+            .clone()
+            .unwrap_or_default()
+            .with_dependencies(&self.default_fields())
+    }
+
+    /// The stages after `computed_fields`, with the fields they depend on turned on
+    #[must_use]
+    pub fn stages(&self) -> Vec<Stage> {
+        let default_fields = self.default_fields();
+        self.stages
+            .iter()
+            .flatten()
+            .map(|stage| Stage {
+                fields: stage.fields.clone().with_dependencies(&default_fields),
+                ..stage.clone()
+            })
+            .collect()
+    }
+}
+
+///
+/// Fields computed for the files matching a filter, such as the expensive ones for
+/// the few files cheap metadata narrowed down:
+///
+/// ```yaml
+/// stages:
+///   - name: executables
+///     where: size < 10485760 and ext in ('exe', 'dll')
+///     fields:
+///       sha256: true
+/// ```
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Stage {
+    /// shown in problems with the stage (default: its position)
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// a SQL condition on `files` picking the files to compute fields of (default: all)
+    #[serde(default, rename = "where")]
+    pub filter: Option<String>,
+
+    #[serde(default)]
+    pub fields: ComputedFields,
+}
+
+impl Stage {
+    /// The query picking the files which are yet to go through the `nth` stage (from 1)
+    #[must_use]
+    pub fn query(&self, nth: i64) -> String {
+        format!(
+            "select * from files where coalesce(stage, 0) < {} and ({})",
+            nth,
+            self.filter.as_deref().unwrap_or("1")
+        )
+    }
+
+    /// How the stage is referred to in problems
+    #[must_use]
+    pub fn label(&self, nth: i64) -> String {
+        self.name.as_ref().map_or_else(
+            || format!("stages[{}]", nth - 1),
+            |name| format!("stages.{}", name),
+        )
+    }
+}
+
+///
+/// Tuning for the `SQLite` DB. Defaults favor fast scans over durability, as the DB
+/// is a cache that can always be rebuilt.
+///
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct DbOptions {
+    /// journal mode: delete, truncate, persist, memory, wal or off (default: wal)
+    #[serde(default)]
+    pub journal_mode: Option<String>,
+
+    /// how often to sync to disk: off, normal, full or extra (default: normal)
+    #[serde(default)]
+    pub synchronous: Option<String>,
+
+    /// bytes of the DB file to memory map, 0 to disable (default: 256MB)
+    #[serde(default)]
+    pub mmap_size: Option<u64>,
+
+    /// how long to wait for a locked DB, in milliseconds (default: 5000)
+    #[serde(default)]
+    pub busy_timeout_ms: Option<u64>,
+
+    /// indexes to create on `files`, each a column or comma separated columns
+    #[serde(default)]
+    pub indexes: Option<Vec<String>>,
+
+    /// env var holding a key to encrypt the DB with, when built with the `sqlcipher`
+    /// feature (default: `RECON_DB_KEY`)
+    #[serde(default)]
+    pub key_env: Option<String>,
+
+    /// file holding the key, in place of the env var
+    #[serde(default)]
+    pub key_file: Option<String>,
+}
+
+impl DbOptions {
+    #[must_use]
+    pub fn journal_mode(&self) -> &str {
+        self.journal_mode.as_deref().unwrap_or("wal")
+    }
+
+    #[must_use]
+    pub fn synchronous(&self) -> &str {
+        self.synchronous.as_deref().unwrap_or("normal")
+    }
+
+    #[must_use]
+    pub fn mmap_size(&self) -> u64 {
+        self.mmap_size.unwrap_or(256 * 1024 * 1024)
+    }
+
+    #[must_use]
+    pub fn busy_timeout_ms(&self) -> u64 {
+        self.busy_timeout_ms.unwrap_or(5000)
+    }
+
+    #[must_use]
+    pub fn key_env(&self) -> &str {
+        self.key_env.as_deref().unwrap_or("RECON_DB_KEY")
+    }
+
+    /// The key the DB is encrypted with, if any: the content of `key_file`, or else the
+    /// value of the `key_env` env var
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the key file can't be read, or is empty
+    pub fn key(&self) -> Result<Option<String>> {
+        if let Some(key_file) = &self.key_file {
+            let key = fs::read_to_string(key_file)
+                .with_context(|| format!("cannot read key file '{}'", key_file))?;
+            let key = key.trim_end_matches(['\r', '\n']);
+            if key.is_empty() {
+                anyhow::bail!("key file '{}' is empty", key_file);
+            }
+            return Ok(Some(key.to_string()));
+        }
+        Ok(std::env::var(self.key_env())
+            .ok()
+            .filter(|key| !key.is_empty()))
     }
 }
 
@@ -101,8 +1671,21 @@ impl Source {
 ///
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// config files to load first, with this one overriding them. relative paths are
+    /// relative to this file
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+
+    /// named overrides of this config (such as `quick` or `deep`), merged on top of it when
+    /// selected with `--profile`
+    #[serde(default)]
+    pub profiles: Option<BTreeMap<String, serde_yaml::Value>>,
+
     #[serde(default)]
     pub source: Source,
+
+    #[serde(default)]
+    pub db: Option<DbOptions>,
 }
 
 impl Config {
@@ -117,13 +1700,318 @@ impl Config {
         Ok(conf)
     }
 
-    /// load configuration from file
+    /// load configuration from file, along with the files it includes
     ///
     /// # Errors
     ///
-    /// This function will return an error on I/O failure
+    /// This function will return an error on I/O or parse failure
     #[tracing::instrument(level = "trace", skip_all, err)]
     pub fn from_path(file: &Path) -> Result<Self> {
-        Self::from_text(&fs::read_to_string(file)?)
+        Self::from_locations(&[Location::File(file.to_path_buf())], None)
+    }
+
+    /// load configuration from files or `http(s)://` URLs, see [`Location::parse`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O, download or parse failure, a checksum
+    /// mismatch, an include cycle or an unknown profile
+    pub fn from_sources(sources: &[&str], profile: Option<&str>) -> Result<Self> {
+        Self::from_locations(
+            &sources
+                .iter()
+                .map(|s| Location::parse(s))
+                .collect::<Vec<_>>(),
+            profile,
+        )
+    }
+
+    /// load configuration from locations merged in order: maps are merged key by key, and
+    /// any other value of a later one replaces the earlier one. `profile` is then merged
+    /// on top the same way.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O, download or parse failure, a checksum
+    /// mismatch, an include cycle or an unknown profile
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub fn from_locations(locations: &[Location], profile: Option<&str>) -> Result<Self> {
+        let mut merged = serde_yaml::Value::Null;
+        let mut loaded = vec![];
+        for location in locations {
+            merge_yaml(&mut merged, load_yaml(location, &mut vec![], &mut loaded)?);
+        }
+        if let Some(profile) = profile {
+            let profiles = merged.get("profiles");
+            let Some(over) = profiles.and_then(|profiles| profiles.get(profile)) else {
+                let known = profiles
+                    .and_then(serde_yaml::Value::as_mapping)
+                    .map(|profiles| {
+                        profiles
+                            .keys()
+                            .filter_map(serde_yaml::Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .filter(|known| !known.is_empty())
+                    .unwrap_or_else(|| "none".to_string());
+                anyhow::bail!("no profile '{}' in config (profiles: {})", profile, known);
+            };
+            let over = over.clone();
+            merge_yaml(&mut merged, over);
+        }
+        if merged.is_null() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_value(merged).or_else(|err| {
+            // a merged config has no lines, so find the config the problem comes from
+            for (name, text) in &loaded {
+                if let Err(err) = serde_yaml::from_str::<Self>(text) {
+                    return Err(err).with_context(|| format!("invalid config '{}'", name));
+                }
+            }
+            Err(err.into())
+        })
+    }
+
+    /// Problems that would otherwise only surface mid-scan: digest sets and the files they
+    /// reference, literals files, simhash values, and yara rules (which are compiled here).
+    /// Patterns are already compiled when the config is parsed.
+    #[must_use]
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for (section, fields) in [
+            ("default_fields", &self.source.default_fields),
+            ("computed_fields", &self.source.computed_fields),
+        ] {
+            if let Some(fields) = fields {
+                fields.problems(section, &mut problems);
+            }
+        }
+        if let Err(err) = self.source.overrides(".") {
+            problems.push(format!("source: {}", describe(&err)));
+        }
+        if let Err(err) = self.source.schedule() {
+            problems.push(format!("source: {}", describe(&err)));
+        }
+        for (nth, stage) in (1..).zip(self.source.stages.iter().flatten()) {
+            stage
+                .fields
+                .problems(&format!("{}.fields", stage.label(nth)), &mut problems);
+        }
+        problems
+    }
+}
+
+///
+/// Where a config is loaded from: a local file, or a URL optionally pinned to a sha256
+/// checksum of its content
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    File(PathBuf),
+    Url { url: String, sha256: Option<String> },
+}
+
+impl Location {
+    /// Parse a config location. `http://` and `https://` are URLs, and may be pinned with a
+    /// `#sha256=<hex>` suffix; anything else is a file path.
+    #[must_use]
+    pub fn parse(source: &str) -> Self {
+        if !(source.starts_with("https://") || source.starts_with("http://")) {
+            return Self::File(PathBuf::from(source));
+        }
+        match source.split_once("#sha256=") {
+            Some((url, sha256)) => Self::Url {
+                url: url.to_string(),
+                sha256: Some(sha256.to_lowercase()),
+            },
+            None => Self::Url {
+                url: source.to_string(),
+                sha256: None,
+            },
+        }
+    }
+
+    /// An include of this config: URLs are kept, and relative paths are taken from this
+    /// config's folder (or URL)
+    fn join(&self, include: &str) -> Self {
+        match (Self::parse(include), self) {
+            (Self::File(path), Self::File(file)) => {
+                Self::File(file.parent().unwrap_or_else(|| Path::new("")).join(path))
+            }
+            (Self::File(path), Self::Url { url, .. }) if path.is_relative() => {
+                let (base, _) = url.rsplit_once('/').unwrap_or((url, ""));
+                Self::parse(&format!("{}/{}", base, include))
+            }
+            (location, _) => location,
+        }
+    }
+
+    /// Is this a URL pinned to a checksum
+    const fn is_pinned(&self) -> bool {
+        matches!(
+            self,
+            Self::Url {
+                sha256: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// Identifies the config for cycle detection
+    fn key(&self) -> Result<String> {
+        Ok(match self {
+            Self::File(file) => fs::canonicalize(file)
+                .with_context(|| format!("cannot read config '{}'", self))?
+                .to_string_lossy()
+                .to_string(),
+            Self::Url { url, .. } => url.clone(),
+        })
+    }
+
+    /// The config text. URLs are cached under `RECON_CACHE_DIR` (default: `recon/configs` in
+    /// the user's cache folder): a pinned URL is only downloaded when its cached copy doesn't
+    /// match the checksum, and an unpinned one falls back to its cached copy when it can't be
+    /// downloaded.
+    fn read(&self) -> Result<String> {
+        let (url, sha256) = match self {
+            Self::File(file) => {
+                return fs::read_to_string(file)
+                    .with_context(|| format!("cannot read config '{}'", self))
+            }
+            Self::Url { url, sha256 } => (url, sha256),
+        };
+        let cache_dir = match std::env::var_os("RECON_CACHE_DIR") {
+            Some(cache_dir) => PathBuf::from(cache_dir),
+            None => dirs::cache_dir()
+                .context("no cache folder for remote configs, set RECON_CACHE_DIR")?
+                .join("recon")
+                .join("configs"),
+        };
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("cannot create cache '{}'", cache_dir.display()))?;
+        let cache_file = cache_dir.join(format!("{}.yaml", sha256_hex(url.as_bytes())));
+
+        if let Some(sha256) = sha256 {
+            if let Ok(text) = fs::read_to_string(&cache_file) {
+                if sha256_hex(text.as_bytes()) == *sha256 {
+                    return Ok(text);
+                }
+            }
+            download(url, &cache_file)?;
+            let text = fs::read_to_string(&cache_file)?;
+            let actual = sha256_hex(text.as_bytes());
+            if actual != *sha256 {
+                fs::remove_file(&cache_file)?;
+                anyhow::bail!(
+                    "config '{}' has sha256 {}, expected {}",
+                    url,
+                    actual,
+                    sha256
+                );
+            }
+            return Ok(text);
+        }
+
+        if let Err(err) = download(url, &cache_file) {
+            if !cache_file.exists() {
+                return Err(err);
+            }
+            tracing::warn!("using a cached copy of '{}': {:#}", url, err);
+        }
+        Ok(fs::read_to_string(&cache_file)?)
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(file) => write!(f, "{}", file.display()),
+            Self::Url { url, .. } => write!(f, "{}", url),
+        }
+    }
+}
+
+/// An error with its causes on one line, like `{:#}`, but skipping causes already spelled
+/// out by the error they caused
+pub(crate) fn describe(err: &anyhow::Error) -> String {
+    let mut parts: Vec<String> = vec![];
+    for cause in err.chain() {
+        let cause = cause.to_string().trim().to_string();
+        if !parts.last().is_some_and(|last| last.contains(&cause)) {
+            parts.push(cause);
+        }
+    }
+    parts.join(": ")
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::Digest;
+    format!("{:x}", sha2::Sha256::digest(bytes))
+}
+
+/// A config as YAML, with its includes merged under it. The text of each config read is
+/// kept in `loaded`, to point at the line of a problem found once merged.
+fn load_yaml(
+    location: &Location,
+    including: &mut Vec<String>,
+    loaded: &mut Vec<(String, String)>,
+) -> Result<serde_yaml::Value> {
+    let key = location.key()?;
+    if including.contains(&key) {
+        anyhow::bail!("config '{}' includes itself", location);
+    }
+    let text = location.read()?;
+    let mut value: serde_yaml::Value = serde_yaml::from_str(&text)
+        .with_context(|| format!("cannot parse config '{}'", location))?;
+    loaded.push((location.to_string(), text));
+    let includes: Vec<String> = value
+        .get("include")
+        .map(|include| serde_yaml::from_value(include.clone()))
+        .transpose()
+        .with_context(|| format!("`include` of '{}' is not a list of configs", location))?
+        .unwrap_or_default();
+    if includes.is_empty() {
+        return Ok(value);
+    }
+
+    including.push(key);
+    let mut merged = serde_yaml::Value::Null;
+    for include in includes {
+        let include = location.join(&include);
+        // a pinned config can't vouch for what it includes unless that's pinned too
+        if location.is_pinned() && !include.is_pinned() {
+            anyhow::bail!(
+                "config '{}' is pinned, so its include '{}' needs a `#sha256=` pin too",
+                location,
+                include
+            );
+        }
+        merge_yaml(&mut merged, load_yaml(&include, including, loaded)?);
+    }
+    including.pop();
+    if let serde_yaml::Value::Mapping(map) = &mut value {
+        map.remove("include");
+    }
+    merge_yaml(&mut merged, value);
+    Ok(merged)
+}
+
+/// Merge `over` into `base`: maps key by key, anything else replaced
+fn merge_yaml(base: &mut serde_yaml::Value, over: serde_yaml::Value) {
+    match (base, over) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (_, serde_yaml::Value::Null) => {}
+        (base, over) => *base = over,
     }
 }
@@ -1,49 +1,138 @@
+use crate::git;
 use crate::matching::{
-    content_match, crc32_match, md5_match, path_match, sha256_match, sha512_match, simhash_match,
+    bazaar_match, bytes_match, content_match, crc32_match, is_known_good, literals_match,
+    md5_match, path_match, redact_snippets, redact_text, sha256_match, sha512_match, simhash_match,
     yara_match,
 };
 use crate::os;
-use crate::out::{to_csv, to_json, to_table, to_xargs};
+use crate::out::{
+    to_csv, to_csv_with, to_html, to_human_table, to_json, to_jsonl, to_junit, to_sbom, to_table,
+    to_xargs, to_xargs0, CsvOptions, SbomFormat,
+};
 use crate::processing::{
-    bytes_type, crc32, file_magic, is_archive, is_binary, is_code, is_document, is_ignored,
-    is_media, md5, sha256, sha512, simhash,
+    archive_entries, bytes_type, compression_ratio, crc32, crypto_material, file_magic,
+    git_last_author, git_last_commit, git_last_commit_time, git_tracked, iocs, is_archive,
+    is_binary, is_code, is_document, is_ignored, is_media, is_zip_bomb, md5, minhash, norm_hash,
+    packages, sha256, sha512, simhash, strings, version_info, weak_key_perms,
 };
+use crate::processor;
+use crate::scripting;
 
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use chrono::Utc;
+use s3::serde_types::Object;
 use serde::{Deserialize, Serialize};
 use sqlx::types::Json;
 use sqlx_meta::SqlxMeta;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use ignore::DirEntry;
 
 use crate::{
-    config::ComputedFields,
-    os::{ftime, user_and_group},
+    config::{AllowlistOptions, ComputedFields, RuleInfo, Severity},
+    os::{data_streams, ftime, hidden_and_system, user_and_group},
 };
 
 pub const DB_FILE: &str = "recon.db";
 
+/// Keep the result of computing a field, or record its failure in `errors` and move on,
+/// unless `strict`
+macro_rules! store_field {
+    ($name:ident, $res:expr, $fields:ident, $file:ident, $errors:ident, $wrap:expr) => {
+        match $res {
+            Ok(val) => $file.$name = val.map($wrap),
+            Err(err) if $fields.strict => {
+                return Err(err.context(format!("{} on '{}'", stringify!($name), $file.path)))
+            }
+            Err(err) => {
+                $errors.insert(stringify!($name).to_string(), format!("{:#}", err));
+            }
+        }
+    };
+}
 macro_rules! process_content {
-    ($name:ident, $fields:ident, $file:ident) => {
+    ($name:ident, $fields:ident, $file:ident, $errors:ident) => {
+        if let Some(fval) = &$fields.$name {
+            store_field!(
+                $name,
+                $name(&$file, fval),
+                $fields,
+                $file,
+                $errors,
+                Into::into
+            );
+        }
+    };
+}
+macro_rules! process_json {
+    ($name:ident, $fields:ident, $file:ident, $errors:ident) => {
         if let Some(fval) = &$fields.$name {
-            $file.$name = $name(&$file, fval)
-                .with_context(|| format!("{} on '{}'", stringify!($name), $file.path))?
-                .map(|t| t.into());
+            store_field!($name, $name(&$file, fval), $fields, $file, $errors, Json);
         }
     };
 }
-macro_rules! process_match {
-    ($name:ident, $fields:ident, $file:ident) => {
+/// Like `process_content`, giving up on the field after `field_timeout` seconds, in which
+/// case it is left out and the timeout recorded in `errors`
+macro_rules! process_timed {
+    ($name:ident, $fields:ident, $file:ident, $errors:ident, $wrap:expr) => {
         if let Some(fval) = &$fields.$name {
-            $file.$name = $name(&$file, fval)?.map(Json);
+            let res = match $fields.field_timeout {
+                None => $name(&$file, fval),
+                Some(secs) => {
+                    let (file, fval) = ($file.clone(), fval.clone());
+                    timed(stringify!($name), secs, &mut $errors, move || {
+                        $name(&file, &fval)
+                    })
+                }
+            };
+            store_field!($name, res, $fields, $file, $errors, $wrap);
+        }
+    };
+}
+/// Like `process_timed`, unless the file is larger than the field's `max_size`, in which
+/// case the field is left out and the reason recorded in `skipped`
+macro_rules! process_sized {
+    ($name:ident, $fields:ident, $file:ident, $skipped:ident, $errors:ident, $wrap:expr) => {
+        if let Some(fval) = &$fields.$name {
+            if let Some(reason) = fval.skip_reason($file.size, $fields.max_file_size) {
+                $skipped.push(format!("{}: {}", stringify!($name), reason));
+            } else {
+                let enabled = fval.enabled();
+                let res = match $fields.field_timeout {
+                    None => $name(&$file, &enabled),
+                    Some(secs) => {
+                        let file = $file.clone();
+                        timed(stringify!($name), secs, &mut $errors, move || {
+                            $name(&file, &enabled)
+                        })
+                    }
+                };
+                store_field!($name, res, $fields, $file, $errors, $wrap);
+            }
         }
     };
 }
 
+/// Numbers compare by value, anything else by its text
+fn compare_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => match (a.as_str(), b.as_str()) {
+            (Some(a), Some(b)) => a.cmp(b),
+            _ => a.to_string().cmp(&b.to_string()),
+        },
+    }
+}
+
 ///
 /// A table of result values for a query
 /// Useful for dynamic data manipulation or display
@@ -53,9 +142,134 @@ pub struct ValuesTable {
     pub columns: Vec<String>,
     pub rows: Vec<Vec<serde_json::Value>>,
     pub total_rows: u32,
+    /// the scan was cancelled before it saw every file, so rows may be missing or stale
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
 }
 
 impl ValuesTable {
+    /// Sort rows by comma separated columns, each optionally suffixed with `:asc` (default)
+    /// or `:desc` (e.g. `size:desc,path`). Nulls sort last.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a column is not in the results, or the order is
+    /// neither `asc` nor `desc`
+    pub fn sort_by_columns(&mut self, spec: &str) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let keys = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(|k| {
+                let (col, order) = k.split_once(':').unwrap_or((k, "asc"));
+                let idx = self
+                    .columns
+                    .iter()
+                    .position(|have| have == col.trim())
+                    .with_context(|| format!("no column '{}' in results", col.trim()))?;
+                match order.trim() {
+                    "asc" => Ok((idx, false)),
+                    "desc" => Ok((idx, true)),
+                    other => bail!("sort order should be 'asc' or 'desc', not '{}'", other),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.rows.sort_by(|a, b| {
+            keys.iter()
+                .map(|(idx, desc)| {
+                    let (a, b) = (&a[*idx], &b[*idx]);
+                    match (a.is_null(), b.is_null()) {
+                        (true, true) => Ordering::Equal,
+                        (true, false) => Ordering::Greater,
+                        (false, true) => Ordering::Less,
+                        (false, false) if *desc => compare_values(b, a),
+                        (false, false) => compare_values(a, b),
+                    }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(())
+    }
+
+    /// The highest severity of the matched rules in the `*_match` columns
+    #[must_use]
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(_, col)| col.ends_with("_match"))
+            .flat_map(|(idx, _)| self.rows.iter().map(move |row| &row[idx]))
+            // JSON columns come as text
+            .filter_map(|val| serde_json::from_str::<Match>(val.as_str()?).ok())
+            .filter(|m| m.is_match && !m.suppressed)
+            .flat_map(|m| m.rules.into_iter().filter_map(|rule| rule.severity))
+            .max()
+    }
+
+    /// Rows that are findings: all of them, but those with every matching `*_match`
+    /// column suppressed
+    #[must_use]
+    pub fn findings(&self) -> usize {
+        let columns = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, col)| col.ends_with("_match"))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        self.rows
+            .iter()
+            .filter(|row| {
+                let mut matched = columns
+                    .iter()
+                    .filter_map(|idx| serde_json::from_str::<Match>(row[*idx].as_str()?).ok())
+                    .filter(|m| m.is_match)
+                    .peekable();
+                matched.peek().is_none() || matched.any(|m| !m.suppressed)
+            })
+            .count()
+    }
+
+    /// Keep only the given columns, in the given order, renaming those given as `column=name`
+    /// (e.g. `path,size,sha256=digest`)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a column is not in the results
+    pub fn select_columns(&self, spec: &str) -> Result<Self> {
+        if self.rows.is_empty() && self.columns.is_empty() {
+            return Ok(self.clone());
+        }
+        let selected = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(|c| {
+                let (col, name) = c.split_once('=').unwrap_or((c, c));
+                let idx = self
+                    .columns
+                    .iter()
+                    .position(|have| have == col.trim())
+                    .with_context(|| format!("no column '{}' in results", col.trim()))?;
+                Ok((idx, name.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            columns: selected.iter().map(|(_, name)| name.clone()).collect(),
+            rows: self
+                .rows
+                .iter()
+                .map(|row| selected.iter().map(|(idx, _)| row[*idx].clone()).collect())
+                .collect(),
+            total_rows: self.total_rows,
+            incomplete: self.incomplete,
+        })
+    }
+
     /// Export as csv
     ///
     /// # Errors
@@ -65,6 +279,15 @@ impl ValuesTable {
         to_csv(self)
     }
 
+    /// Export as csv, with the given delimiter, quoting and header
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O failure
+    pub fn to_csv_with(&self, opts: &CsvOptions) -> Result<String> {
+        to_csv_with(self, opts)
+    }
+
     /// Convert to a xargs-friendly format (a newline separated list of values)
     ///
     /// # Errors
@@ -74,6 +297,15 @@ impl ValuesTable {
         to_xargs(self)
     }
 
+    /// Convert to a `xargs -0` friendly format (NUL terminated values)
+    ///
+    /// # Errors
+    ///
+    /// This function is not expected to error, just conforms to an interface
+    pub fn to_xargs0(&self) -> Result<String> {
+        to_xargs0(self)
+    }
+
     /// Export as JSON
     ///
     /// # Errors
@@ -83,6 +315,33 @@ impl ValuesTable {
         to_json(self)
     }
 
+    /// Export as JSON lines
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on serialization failure
+    pub fn to_jsonl(&self) -> Result<String> {
+        to_jsonl(self)
+    }
+
+    /// Export as a standalone HTML report
+    ///
+    /// # Errors
+    ///
+    /// This function is not expected to error, just conforms to an interface
+    pub fn to_html(&self) -> Result<String> {
+        to_html(self)
+    }
+
+    /// Export rule matches as a `JUnit` XML report
+    ///
+    /// # Errors
+    ///
+    /// This function is not expected to error, just conforms to an interface
+    pub fn to_junit(&self) -> Result<String> {
+        to_junit(self)
+    }
+
     /// Returns an ASCII drawn table
     ///
     /// # Errors
@@ -91,6 +350,25 @@ impl ValuesTable {
     pub fn to_table(&self) -> Result<String> {
         to_table(self)
     }
+
+    /// Returns an ASCII drawn table with values for people: sizes in units, timestamps as
+    /// relative time, and long hashes and paths shortened
+    ///
+    /// # Errors
+    ///
+    /// This function is not expected to error, just conforms to an interface
+    pub fn to_human_table(&self) -> Result<String> {
+        to_human_table(self)
+    }
+
+    /// Export files and packages as an SBOM
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on serialization failure
+    pub fn to_sbom(&self, format: SbomFormat) -> Result<String> {
+        to_sbom(self, format)
+    }
 }
 
 ///
@@ -102,12 +380,148 @@ pub struct Match {
     pub on: String,
     pub by: HashMap<String, bool>,
     pub details: Option<serde_json::Value>,
+    /// the matched rules with a name, severity or description configured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RuleInfo>,
+    /// every rule matched is accepted by a suppression, so it's not a finding
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub suppressed: bool,
+}
+
+///
+/// Indicators of compromise found in file content
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Iocs {
+    pub urls: Vec<String>,
+    pub ips: Vec<String>,
+    pub domains: Vec<String>,
+    pub emails: Vec<String>,
+}
+
+///
+/// A package pinned by a lockfile or manifest
+///
+#[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Package {
+    /// OSV ecosystem: `crates.io`, `npm`, `PyPI` or `Go`
+    pub ecosystem: String,
+    pub name: String,
+    pub version: String,
+}
+
+///
+/// Known vulnerabilities of a package, by OSV id
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct OsvVuln {
+    pub ecosystem: String,
+    pub package: String,
+    pub version: String,
+    pub ids: Vec<String>,
+}
+
+///
+/// What VT knows about a file, by its sha256
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct VtVerdict {
+    pub found: bool,
+    pub malicious: Option<u32>,
+    pub suspicious: Option<u32>,
+    pub engines: Option<u32>,
+    /// first submission, RFC 3339
+    pub first_seen: Option<String>,
+    pub link: Option<String>,
+}
+
+///
+/// Strings of the `VERSIONINFO` resource of a Windows executable or DLL
+///
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub company_name: Option<String>,
+    pub product_name: Option<String>,
+    pub product_version: Option<String>,
+    pub file_description: Option<String>,
+    pub file_version: Option<String>,
+    pub internal_name: Option<String>,
+    pub original_filename: Option<String>,
+    pub legal_copyright: Option<String>,
+}
+
+///
+/// A named (alternate) data stream of a file, besides its content
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct DataStream {
+    pub name: String,
+    pub size: u64,
+}
+
+///
+/// A single piece of cryptographic material (certificate, key) found in a file
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoItem {
+    pub kind: String,
+    pub label: Option<String>,
+    pub encrypted: bool,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    pub not_before: Option<String>,
+    pub not_after: Option<String>,
+}
+
+///
+/// Certificates and keys found in a file
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoMaterial {
+    pub has_certificate: bool,
+    pub has_private_key: bool,
+    pub items: Vec<CryptoItem>,
+}
+
+///
+/// A single member of an archive
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: Option<u64>,
+    pub is_dir: bool,
+}
+
+///
+/// Members of an archive, listed without extracting
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntries {
+    pub format: String,
+    pub entries: Vec<ArchiveEntry>,
+    pub truncated: bool,
+}
+
+///
+/// The outcome of recursively extracting an archive
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct UnpackReport {
+    pub format: String,
+    pub files: u64,
+    pub bytes: u64,
+    pub depth: usize,
+    pub truncated: bool,
+    pub errors: Vec<String>,
+    pub temp_dir: Option<String>,
 }
 
 ///
 /// The main file abstraction. Contains basic file data, metadata, and computed fields
 ///
-#[derive(Default, Debug, Clone, sqlx::FromRow, SqlxMeta)]
+#[derive(Default, Debug, Clone, Serialize, sqlx::FromRow, SqlxMeta)]
 pub struct File {
     pub id: Option<i32>,
     pub entry_time: String,
@@ -115,9 +529,25 @@ pub struct File {
     pub path: String,
     pub ext: Option<String>,
     pub mode: Option<String>,
+    pub is_suid: Option<bool>,
+    pub is_sgid: Option<bool>,
+    pub is_world_writable: Option<bool>,
     pub is_dir: Option<bool>,
     pub is_file: Option<bool>,
     pub is_symlink: Option<bool>,
+    /// where a symlink leads, as written in it
+    pub symlink_target: Option<String>,
+    pub is_broken_symlink: Option<bool>,
+    /// a symlink leading out of the scanned root
+    pub is_escaping_symlink: Option<bool>,
+    /// the symlink the file was reached through, walking from the root: the file itself, or
+    /// the nearest folder above it that is one (when following symlinks)
+    pub via_symlink: Option<String>,
+    pub is_hidden: Option<bool>,
+    pub is_system: Option<bool>,
+    pub is_readonly: Option<bool>,
+    /// alternate data streams, on Windows, when there are any
+    pub ads: Option<Json<Vec<DataStream>>>,
     pub is_empty: bool,
     pub is_binary: Option<bool>,
     pub size: Option<i64>,
@@ -128,6 +558,14 @@ pub struct File {
     pub atime: Option<chrono::DateTime<Utc>>,
     pub mtime: Option<chrono::DateTime<Utc>>,
     pub ctime: Option<chrono::DateTime<Utc>>,
+    pub inode: Option<i64>,
+    /// the device the inode is on, together they tell hard links of one file apart from copies
+    pub dev: Option<i64>,
+    /// where the filesystem the file is on is mounted, and its type (Linux only)
+    pub mount_point: Option<String>,
+    pub filesystem_type: Option<String>,
+    /// how many hard links the file has
+    pub nlink: Option<i64>,
 
     pub is_archive: Option<bool>,
     pub is_document: Option<bool>,
@@ -142,17 +580,59 @@ pub struct File {
     pub sha512: Option<String>,
     pub md5: Option<String>,
     pub simhash: Option<String>,
+    pub norm_hash: Option<String>,
+    pub minhash: Option<String>,
+    pub strings: Option<String>,
+    pub iocs: Option<Json<Iocs>>,
+    pub crypto_material: Option<Json<CryptoMaterial>>,
+    pub weak_key_perms: Option<bool>,
+    pub archive_entries: Option<Json<ArchiveEntries>>,
+    pub compression_ratio: Option<f64>,
+    pub version_info: Option<Json<VersionInfo>>,
+    pub is_zip_bomb: Option<bool>,
+    pub unpacked: Option<Json<UnpackReport>>,
+    pub parent_archive: Option<String>,
+    pub extracted_path: Option<String>,
+    pub git_remote: Option<String>,
+    pub git_commit: Option<String>,
+    pub s3_etag: Option<String>,
+    pub s3_storage_class: Option<String>,
+    pub git_tracked: Option<bool>,
+    pub git_last_commit: Option<String>,
+    pub git_last_author: Option<String>,
+    pub git_last_commit_time: Option<chrono::DateTime<Utc>>,
+    pub skipped_reason: Option<String>,
+    /// problems computing fields, by field
+    pub errors: Option<Json<BTreeMap<String, String>>>,
+    /// fields computed by registered processors, by processor
+    pub extra: Option<Json<BTreeMap<String, serde_json::Value>>>,
 
+    pub is_known_good: Option<bool>,
+    pub vt_lookup: Option<Json<VtVerdict>>,
+    pub packages: Option<Json<Vec<Package>>>,
+    pub osv_vulns: Option<Json<Vec<OsvVuln>>>,
     pub crc32_match: Option<Json<Match>>,
     pub sha256_match: Option<Json<Match>>,
     pub sha512_match: Option<Json<Match>>,
     pub md5_match: Option<Json<Match>>,
     pub simhash_match: Option<Json<Match>>,
+    pub bazaar_match: Option<Json<Match>>,
     pub path_match: Option<Json<Match>>,
     pub content_match: Option<Json<Match>>,
+    pub literals_match: Option<Json<Match>>,
+    pub bytes_match: Option<Json<Match>>,
     pub yara_match: Option<Json<Match>>,
+    /// lines matching the last `recon grep` pattern
+    pub grep_match: Option<Json<Match>>,
 
     pub computed: Option<bool>,
+    /// how many of `source.stages` the file went through
+    pub stage: Option<i64>,
+    /// milliseconds spent computing fields of the file, added up over the stages it went
+    /// through
+    pub process_ms: Option<i64>,
+    /// with `--sample`, whether the file was picked to compute fields of
+    pub is_sampled: Option<bool>,
 }
 
 impl File {
@@ -163,27 +643,166 @@ impl File {
     /// This function will return an error on IO or processing failure
     ///
     pub(crate) fn from_entry(entry: &DirEntry) -> Result<Self> {
-        let path = entry.path().display().to_string();
-        let m_v = entry.metadata().ok();
-        let m = m_v.as_ref();
-        let (user, group, uid, gid) = m.map_or((None, None, None, None), user_and_group);
+        Ok(Self {
+            abs_path: fs::canonicalize(entry.path())?
+                .to_string_lossy()
+                .to_string(),
+            ..Self::from_metadata(entry.path(), entry.metadata().ok().as_ref())
+        }
+        .with_mount())
+    }
+
+    /// Build a `File` for a folder or a symlink found by walking. A symlink is addressed
+    /// by where it is rather than where it leads.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on IO failure
+    ///
+    pub(crate) fn from_dir_or_link(entry: &DirEntry) -> Result<Self> {
+        let path = entry.path();
+        let abs_path = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) if entry.path_is_symlink() => {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                fs::canonicalize(parent)?.join(name)
+            }
+            _ => fs::canonicalize(path)?,
+        };
+        // a followed link comes with the metadata of where it leads
+        let m = if entry.path_is_symlink() {
+            fs::symlink_metadata(path).ok()
+        } else {
+            entry.metadata().ok()
+        };
+        Ok(Self {
+            abs_path: abs_path.to_string_lossy().to_string(),
+            ..Self::from_metadata(path, m.as_ref())
+        }
+        .with_mount())
+    }
+
+    /// Build a `File` from a path given explicitly rather than found by walking.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on IO failure
+    ///
+    pub(crate) fn from_path(path: &Path) -> Result<Self> {
+        Ok(Self {
+            abs_path: fs::canonicalize(path)?.to_string_lossy().to_string(),
+            ..Self::from_metadata(path, fs::symlink_metadata(path).ok().as_ref())
+        }
+        .with_mount())
+    }
+
+    /// Fill in the mount the file is on, by its `abs_path`
+    fn with_mount(self) -> Self {
+        let (mount_point, filesystem_type) = os::mount(Path::new(&self.abs_path)).unzip();
+        Self {
+            mount_point,
+            filesystem_type,
+            ..self
+        }
+    }
+
+    /// Build a `File` for an archive member extracted to `location`, identified by its
+    /// virtual `archive!/member` path.
+    pub(crate) fn from_member(
+        location: &Path,
+        path: String,
+        abs_path: String,
+        parent_archive: String,
+    ) -> Self {
+        Self {
+            path,
+            abs_path,
+            parent_archive: Some(parent_archive),
+            extracted_path: Some(location.display().to_string()),
+            ..Self::from_metadata(location, fs::metadata(location).ok().as_ref())
+        }
+    }
+
+    /// Build a `File` for an object storage object downloaded to `location`, identified
+    /// by its `s3://bucket/key` URL. Size and times come from the object rather than the download.
+    pub(crate) fn from_object(location: &Path, url: String, object: &Object) -> Self {
+        let size = object.size.try_into().ok();
+        Self {
+            path: url.clone(),
+            abs_path: url,
+            extracted_path: Some(location.display().to_string()),
+            mode: None,
+            is_suid: None,
+            is_sgid: None,
+            is_world_writable: None,
+            is_empty: size == Some(0),
+            size,
+            user: None,
+            group: None,
+            uid: None,
+            gid: None,
+            atime: None,
+            ctime: None,
+            mtime: chrono::DateTime::parse_from_rfc3339(&object.last_modified)
+                .ok()
+                .map(|t| t.with_timezone(&Utc)),
+            s3_etag: object
+                .e_tag
+                .as_ref()
+                .map(|e| e.trim_matches('"').to_string()),
+            s3_storage_class: object.storage_class.clone(),
+            ..Self::from_metadata(location, fs::metadata(location).ok().as_ref())
+        }
+    }
+
+    fn from_metadata(entry_path: &Path, m: Option<&fs::Metadata>) -> Self {
+        let path = entry_path.display().to_string();
+        let (user, group, uid, gid) =
+            m.map_or((None, None, None, None), |m| user_and_group(entry_path, m));
+        let (is_hidden, is_system) = m.map_or((None, None), |m| hidden_and_system(entry_path, m));
+        let ads = data_streams(entry_path)
+            .filter(|streams| !streams.is_empty())
+            .map(|streams| {
+                Json(
+                    streams
+                        .into_iter()
+                        .map(|(name, size)| DataStream { name, size })
+                        .collect(),
+                )
+            });
         let size = m.and_then(|m| m.len().try_into().ok());
         let is_file = m.map(fs::Metadata::is_file);
+        let is_symlink = m.map(fs::Metadata::is_symlink);
+        let mode = m.map(os::mode);
+        let symlink_target = is_symlink
+            .unwrap_or(false)
+            .then(|| fs::read_link(entry_path).ok())
+            .flatten();
 
-        Ok(Self {
+        Self {
             entry_time: chrono::Utc::now().to_rfc3339(),
             path,
-            ext: entry
-                .path()
+            ext: entry_path
                 .extension()
                 .map(|s| s.to_string_lossy().to_string()),
-            abs_path: fs::canonicalize(entry.path())?
-                .to_string_lossy()
-                .to_string(),
-            mode: m.map(|m| unix_mode::to_string(os::mode(m))),
+            mode: mode.map(unix_mode::to_string),
+            is_suid: mode.map(|m| m & 0o4000 != 0),
+            is_sgid: mode.map(|m| m & 0o2000 != 0),
+            // symlinks always carry 0777, which says nothing about their target
+            is_world_writable: mode.map(|m| m & 0o002 != 0 && !is_symlink.unwrap_or(false)),
             is_dir: m.map(fs::Metadata::is_dir),
             is_file,
-            is_symlink: m.map(fs::Metadata::is_symlink),
+            is_symlink,
+            symlink_target: symlink_target.map(|target| target.display().to_string()),
+            // `exists` follows the link
+            is_broken_symlink: is_symlink.map(|is_symlink| is_symlink && !entry_path.exists()),
+            is_hidden,
+            is_system,
+            is_readonly: m.map(|m| m.permissions().readonly()),
+            ads,
             is_empty: size.map_or(false, |s| s == 0),
             size,
             user,
@@ -193,13 +812,130 @@ impl File {
             atime: m.and_then(|m| ftime(m.accessed())),
             mtime: m.and_then(|m| ftime(m.modified())),
             ctime: m.and_then(|m| ftime(m.created())),
+            inode: m.and_then(os::inode),
+            dev: m.and_then(os::dev),
+            nlink: m.and_then(os::nlink),
             ..Self::default()
-        })
+        }
+    }
+
+    /// Mark whether this is a symlink leading out of `root`, given canonical. A broken
+    /// link is judged by where its target would be.
+    pub(crate) fn check_escape(&mut self, root: &Path) {
+        let link = Path::new(&self.path);
+        self.is_escaping_symlink = self.is_symlink.map(|is_symlink| {
+            is_symlink
+                && self.symlink_target.as_ref().is_some_and(|target| {
+                    !link_destination(link, Path::new(target)).starts_with(root)
+                })
+        });
+    }
+
+    /// Address a file walked in a repository cloned to `root`, given canonical, by
+    /// `<remote>@<commit>/<relative path>`, reading its content from the clone. The times,
+    /// inode and mount of a fresh checkout change with every clone, so they're dropped.
+    pub(crate) fn in_clone(self, root: &Path, cloned: &git::Cloned) -> Self {
+        let rel = Path::new(&self.abs_path).strip_prefix(root).map_or_else(
+            |_| self.abs_path.clone(),
+            |rel| rel.to_string_lossy().to_string(),
+        );
+        let abs_path = format!("{}@{}/{}", cloned.remote, cloned.commit, rel);
+        Self {
+            path: abs_path.clone(),
+            extracted_path: Some(self.abs_path.clone()),
+            abs_path,
+            git_remote: Some(cloned.remote.clone()),
+            git_commit: Some(cloned.commit.clone()),
+            atime: None,
+            mtime: None,
+            ctime: None,
+            inode: None,
+            dev: None,
+            mount_point: None,
+            filesystem_type: None,
+            ..self
+        }
+    }
+
+    /// Where the content of this file can be read from: the file itself, or the
+    /// location it was extracted to if it lives inside an archive
+    #[must_use]
+    pub fn content_path(&self) -> &Path {
+        Path::new(self.extracted_path.as_deref().unwrap_or(&self.abs_path))
+    }
+
+    /// Whether there's content to compute fields from: folders and symlinks stored as
+    /// their own rows have none
+    #[must_use]
+    pub fn has_content(&self) -> bool {
+        self.is_dir != Some(true) && !Path::new(&self.abs_path).is_symlink()
+    }
+
+    /// Whether `field` was left out for this file, as recorded in `skipped_reason` or
+    /// `errors`
+    #[must_use]
+    pub fn skipped(&self, field: &str) -> bool {
+        self.skipped_reason.as_deref().is_some_and(|reason| {
+            reason
+                .split("; ")
+                .any(|skipped| skipped.split(':').next() == Some(field))
+        }) || self
+            .errors
+            .as_ref()
+            .is_some_and(|errors| errors.contains_key(field))
     }
 
     pub(crate) fn process_fields(&self, fields: &ComputedFields) -> Result<Self> {
         compute_fields(self, fields)
     }
+
+    /// The `*_match` fields that matched, by column
+    #[must_use]
+    pub fn matches(&self) -> Vec<(&'static str, &Match)> {
+        [
+            ("crc32_match", &self.crc32_match),
+            ("sha256_match", &self.sha256_match),
+            ("sha512_match", &self.sha512_match),
+            ("md5_match", &self.md5_match),
+            ("simhash_match", &self.simhash_match),
+            ("bazaar_match", &self.bazaar_match),
+            ("path_match", &self.path_match),
+            ("content_match", &self.content_match),
+            ("literals_match", &self.literals_match),
+            ("bytes_match", &self.bytes_match),
+            ("yara_match", &self.yara_match),
+            ("grep_match", &self.grep_match),
+        ]
+        .into_iter()
+        .filter_map(|(column, m)| Some((column, &m.as_ref()?.0)))
+        .filter(|(_, m)| m.is_match)
+        .collect()
+    }
+}
+
+/// Where the symlink at `link` leads, all links along the way resolved. When it's
+/// broken, where `target` would be.
+fn link_destination(link: &Path, target: &Path) -> PathBuf {
+    fs::canonicalize(link).unwrap_or_else(|_| {
+        let parent = link
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        fs::canonicalize(parent)
+            .unwrap_or_default()
+            .join(target)
+            .components()
+            .fold(PathBuf::new(), |mut dest, component| {
+                match component {
+                    Component::ParentDir => {
+                        dest.pop();
+                    }
+                    Component::CurDir => {}
+                    component => dest.push(component),
+                }
+                dest
+            })
+    })
 }
 
 /// Compute all on-demand fields as configured in `ComputedFields`.
@@ -210,30 +946,210 @@ impl File {
 #[tracing::instrument(level = "trace", skip_all, err)]
 pub fn compute_fields(file: &File, fields: &ComputedFields) -> Result<File> {
     let mut f = file.clone();
+    if !f.has_content() {
+        return Ok(f);
+    }
+    let started = Instant::now();
+    let mut skipped = vec![];
+    // keeping what failed in an earlier stage
+    let mut errors = f.errors.take().map(|errors| errors.0).unwrap_or_default();
 
-    process_content!(is_archive, fields, f);
-    process_content!(is_document, fields, f);
-    process_content!(is_media, fields, f);
-    process_content!(is_code, fields, f);
-    process_content!(is_ignored, fields, f);
-
-    process_content!(bytes_type, fields, f);
-    process_content!(is_binary, fields, f);
-    process_content!(file_magic, fields, f);
-    process_content!(crc32, fields, f);
-    process_content!(sha256, fields, f);
-    process_content!(sha512, fields, f);
-    process_content!(md5, fields, f);
-    process_content!(simhash, fields, f);
-
-    process_match!(yara_match, fields, f);
-    process_match!(crc32_match, fields, f);
-    process_match!(sha256_match, fields, f);
-    process_match!(sha512_match, fields, f);
-    process_match!(md5_match, fields, f);
-    process_match!(simhash_match, fields, f);
-    process_match!(path_match, fields, f);
-    process_match!(content_match, fields, f);
+    let process = fields.process_if.as_ref().map_or(Ok(true), |process_if| {
+        process_if
+            .ast()
+            .and_then(|ast| scripting::predicate(ast, &f))
+    });
+    let process = match process {
+        Ok(process) => process,
+        Err(err) if fields.strict => return Err(err.context(format!("process_if on '{}'", f.path))),
+        Err(err) => {
+            errors.insert("process_if".to_string(), format!("{:#}", err));
+            false
+        }
+    };
+    if !process {
+        f.errors = Some(Json(errors)).filter(|errors| !errors.is_empty());
+        return Ok(f);
+    }
+
+    process_content!(is_archive, fields, f, errors);
+    process_content!(is_document, fields, f, errors);
+    process_content!(is_media, fields, f, errors);
+    process_content!(is_code, fields, f, errors);
+    process_content!(is_ignored, fields, f, errors);
+
+    process_content!(bytes_type, fields, f, errors);
+    process_content!(is_binary, fields, f, errors);
+    process_sized!(file_magic, fields, f, skipped, errors, Into::into);
+    process_sized!(crc32, fields, f, skipped, errors, Into::into);
+    process_sized!(sha256, fields, f, skipped, errors, Into::into);
+    process_sized!(sha512, fields, f, skipped, errors, Into::into);
+    process_sized!(md5, fields, f, skipped, errors, Into::into);
+    process_sized!(simhash, fields, f, skipped, errors, Into::into);
+    process_sized!(norm_hash, fields, f, skipped, errors, Into::into);
+    process_sized!(minhash, fields, f, skipped, errors, Into::into);
+    process_timed!(strings, fields, f, errors, Into::into);
+    process_sized!(iocs, fields, f, skipped, errors, Json);
+    process_sized!(crypto_material, fields, f, skipped, errors, Json);
+    process_sized!(version_info, fields, f, skipped, errors, Json);
+    if !skipped.is_empty() {
+        f.skipped_reason = Some(skipped.join("; "));
+    }
+    process_content!(weak_key_perms, fields, f, errors);
+    process_json!(archive_entries, fields, f, errors);
+    process_content!(compression_ratio, fields, f, errors);
+    process_content!(is_zip_bomb, fields, f, errors);
+    process_content!(git_tracked, fields, f, errors);
+    process_content!(git_last_commit, fields, f, errors);
+    process_content!(git_last_author, fields, f, errors);
+    process_content!(git_last_commit_time, fields, f, errors);
+    if fields.packages.unwrap_or_default() || fields.osv_vulns.is_some() {
+        store_field!(packages, packages(&f, &true), fields, f, errors, Json);
+    }
+
+    if let Some(allowlist) = &fields.allowlist {
+        store_field!(
+            is_known_good,
+            is_known_good(&f, allowlist),
+            fields,
+            f,
+            errors,
+            Into::into
+        );
+    }
+    let excluded = f.is_known_good == Some(true)
+        && fields
+            .allowlist
+            .as_ref()
+            .is_some_and(AllowlistOptions::exclude_matches);
+    if !excluded {
+        process_timed!(yara_match, fields, f, errors, Json);
+        process_json!(crc32_match, fields, f, errors);
+        process_json!(sha256_match, fields, f, errors);
+        process_json!(sha512_match, fields, f, errors);
+        process_json!(md5_match, fields, f, errors);
+        process_json!(simhash_match, fields, f, errors);
+        process_json!(bazaar_match, fields, f, errors);
+        process_json!(path_match, fields, f, errors);
+        process_timed!(content_match, fields, f, errors, Json);
+        if let (Some(redaction), Some(patterns), Some(m)) = (
+            fields.redact,
+            &fields.content_match,
+            f.content_match.as_mut(),
+        ) {
+            redact_snippets(m, patterns, redaction);
+        }
+        process_json!(literals_match, fields, f, errors);
+        process_timed!(bytes_match, fields, f, errors, Json);
+    }
+    // what content_match is after isn't kept in other fields holding text either
+    if let (Some(redaction), Some(patterns)) = (fields.redact, &fields.content_match) {
+        f.strings = f
+            .strings
+            .map(|strings| redact_text(&strings, patterns, redaction));
+        if let Some(Json(iocs)) = f.iocs.as_mut() {
+            for ioc in iocs
+                .urls
+                .iter_mut()
+                .chain(&mut iocs.ips)
+                .chain(&mut iocs.domains)
+                .chain(&mut iocs.emails)
+            {
+                *ioc = redact_text(ioc, patterns, redaction);
+            }
+        }
+    }
+
+    // custom fields go last, so they can build on all others
+    let processed = fields
+        .extra
+        .iter()
+        .flatten()
+        .filter(|(_, options)| **options != serde_yaml::Value::Bool(false))
+        .map(|(name, options)| (name, processor::compute(name, &f, options)));
+    let scripted = fields
+        .scripted_fields
+        .iter()
+        .flatten()
+        .map(|(name, script)| (name, script.ast().and_then(|ast| scripting::field(ast, &f))));
+    for (name, res) in processed.chain(scripted).collect::<Vec<_>>() {
+        match res {
+            Ok(val) => {
+                let extra = &mut f.extra.get_or_insert_with(Json::default).0;
+                match val {
+                    Some(val) => extra.insert(name.clone(), val),
+                    None => extra.remove(name),
+                };
+            }
+            Err(err) if fields.strict => {
+                return Err(err.context(format!("{} on '{}'", name, f.path)))
+            }
+            Err(err) => {
+                errors.insert(name.clone(), format!("{:#}", err));
+            }
+        }
+    }
 
+    f.errors = Some(Json(errors)).filter(|errors| !errors.is_empty());
+    let elapsed = i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX);
+    f.process_ms = Some(f.process_ms.unwrap_or_default().saturating_add(elapsed));
     Ok(f)
 }
+
+/// most threads running timed fields at once, counting those left running after timing out
+const MAX_TIMED_THREADS: usize = 64;
+
+/// threads running timed fields now
+static TIMED_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// A thread counted in `TIMED_THREADS`, counted out once dropped, even by a panic
+struct TimedThread;
+
+impl Drop for TimedThread {
+    fn drop(&mut self) {
+        TIMED_THREADS.fetch_sub(1, AtomicOrdering::SeqCst);
+    }
+}
+
+/// Run `process` on a thread, giving up on it after `secs`: the timeout is recorded in
+/// `errors` under `name`, and the field is left out. A thread can't be stopped, so the
+/// process is left to finish in the background. Those threads are bounded: while
+/// `MAX_TIMED_THREADS` are running, timed fields are skipped, which is recorded in
+/// `errors` as well.
+fn timed<T, F>(
+    name: &str,
+    secs: u64,
+    errors: &mut BTreeMap<String, String>,
+    process: F,
+) -> Result<Option<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<Option<T>> + Send + 'static,
+{
+    if TIMED_THREADS
+        .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |running| {
+            (running < MAX_TIMED_THREADS).then_some(running + 1)
+        })
+        .is_err()
+    {
+        errors.insert(
+            name.to_string(),
+            format!("skipped, {} timed fields still running", MAX_TIMED_THREADS),
+        );
+        return Ok(None);
+    }
+    let running = TimedThread;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _running = running;
+        let _res = tx.send(process());
+    });
+    match rx.recv_timeout(Duration::from_secs(secs)) {
+        Ok(res) => res,
+        Err(RecvTimeoutError::Timeout) => {
+            errors.insert(name.to_string(), format!("timed out after {}s", secs));
+            Ok(None)
+        }
+        Err(RecvTimeoutError::Disconnected) => bail!("{} failed", name),
+    }
+}
@@ -4,18 +4,49 @@ use lazy_static::lazy_static;
 use serde_json::json;
 use sqlx::{
     pool::PoolConnection,
-    sqlite::{SqliteColumn, SqliteRow},
-    Column, Pool, Row, Sqlite, SqlitePool, TypeInfo, Value, ValueRef,
+    sqlite::{
+        SqliteColumn, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow,
+        SqliteSynchronous,
+    },
+    types::Json,
+    Column, Pool, Row, Sqlite, TypeInfo, Value, ValueRef,
 };
 use sqlx_meta::{Binds, Schema};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tracing::info;
 
-use crate::data::{File, ValuesTable};
+use crate::config::DbOptions;
+use crate::data::{File, Match, OsvVuln, Package, ValuesTable, VtVerdict};
+use crate::functions;
+use crate::out::jsonl_line;
+use crate::processing::Chunk;
+
+/// Tells copies of a file apart, while its hard links are the same copy: a file's
+/// device and inode, or its id where they're unknown
+pub(crate) const COPY_SQL: &str = "coalesce(dev || ':' || inode, 'id:' || id)";
 
 lazy_static! {
     static ref INSERT_SQL: String = {
-        let cols = &File::columns()[1..];
-
-        let holders = (0..cols.len()).map(|_| "?").collect::<Vec<_>>().join(", ");
+        // `update_binds` binds every field but the id, then the id. Files also carry the
+        // run that last saw them and when, which are not part of `File`, bound after that.
+        let binds = File::columns()[1..]
+            .iter()
+            .chain(&["id", "run_id", "last_seen"])
+            .collect::<Vec<_>>();
+        // the id is left to the DB, and each column takes the value bound for it, by its
+        // place among the binds
+        let (cols, holders): (Vec<&str>, Vec<String>) = binds
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| ***c != "id")
+            .map(|(nth, c)| (*c, format!("?{}", nth + 1)))
+            .unzip();
+        let holders = holders.join(", ");
 
         let excludes = cols
             .iter()
@@ -41,25 +72,129 @@ lazy_static! {
     };
 }
 
+/// Pool connections come with recon's SQL functions (`REGEXP`, `hamming`), and the ones
+/// reading files on disk (`file_sha256`...) when `with_files`. Connections for reading
+/// only refuse any write, whatever the DB url says.
+fn pool_options(with_files: bool, read_only: bool) -> SqlitePoolOptions {
+    SqlitePoolOptions::new().after_connect(move |conn, _meta| {
+        Box::pin(async move {
+            if read_only {
+                sqlx::query("PRAGMA query_only=1")
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            let mut handle = conn.lock_handle().await?;
+            functions::register(handle.as_raw_handle(), with_files).map_err(sqlx::Error::Protocol)
+        })
+    })
+}
+
+/// Have `SQLCipher` encrypt the DB with the key of `opts`, if there's one, and tell if so
+fn with_key(
+    connect_opts: SqliteConnectOptions,
+    opts: &DbOptions,
+) -> Result<(SqliteConnectOptions, bool)> {
+    match opts.key()? {
+        // the key goes first on every connection, before anything reads the DB
+        Some(key) if cfg!(feature = "sqlcipher") => Ok((
+            connect_opts.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+            true,
+        )),
+        Some(_) => anyhow::bail!(
+            "a DB key is set, but this recon is built without the sqlcipher feature to encrypt with it"
+        ),
+        None => Ok((connect_opts, false)),
+    }
+}
+
+const fn connect_error(keyed: bool) -> &'static str {
+    if keyed {
+        "cannot connect, check the DB key"
+    } else {
+        "cannot connect"
+    }
+}
+
+/// What the files under a folder add up to, for `dirs`
+#[derive(Default)]
+struct DirStats {
+    size: i64,
+    files: i64,
+    newest_mtime: Option<String>,
+    matches: i64,
+}
+
 pub struct Db {
     pool: Pool<Sqlite>,
+    run_id: Option<i64>,
+    file: Option<PathBuf>,
 }
 
 impl Db {
-    /// Connect to a adb
+    /// Connect to a adb, tuned with the given options
     ///
     /// # Errors
     ///
-    /// This function will return an error if I/O error happened
-    pub async fn connect(db_url: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(db_url)
+    /// This function will return an error if I/O error happened, or options are invalid
+    pub async fn connect(db_url: &str, opts: &DbOptions) -> Result<Self> {
+        let connect_opts = SqliteConnectOptions::from_str(db_url)
+            .context("invalid db url")?
+            .journal_mode(
+                SqliteJournalMode::from_str(opts.journal_mode()).context("invalid journal_mode")?,
+            )
+            .synchronous(
+                SqliteSynchronous::from_str(opts.synchronous()).context("invalid synchronous")?,
+            )
+            .pragma("mmap_size", opts.mmap_size().to_string())
+            .busy_timeout(Duration::from_millis(opts.busy_timeout_ms()));
+        let (connect_opts, keyed) = with_key(connect_opts, opts)?;
+        let pool = pool_options(true, false)
+            .connect_with(connect_opts)
             .await
-            .context("cannot connect")?;
+            .context(connect_error(keyed))?;
         sqlx::migrate!()
             .run(&pool)
             .await
             .context("cannot run migrations")?; // embeds ./migrations
-        Ok(Self { pool })
+        let file: String =
+            sqlx::query_scalar("select file from pragma_database_list where name='main'")
+                .fetch_one(&pool)
+                .await?;
+        Ok(Self {
+            pool,
+            run_id: None,
+            // empty for in memory DBs
+            file: Some(PathBuf::from(file)).filter(|f| f.is_absolute()),
+        })
+    }
+
+    /// Where the DB is stored, if on disk
+    #[must_use]
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// Connect to an existing db for reading only, without running migrations, whatever
+    /// the url asks for. Queries can't read files through it, as it may be serving others.
+    /// Only the key of the options is used.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if I/O error happened
+    pub async fn connect_readonly(db_url: &str, opts: &DbOptions) -> Result<Self> {
+        let connect_opts = SqliteConnectOptions::from_str(db_url)
+            .context("invalid db url")?
+            .read_only(true);
+        let (connect_opts, keyed) = with_key(connect_opts, opts)?;
+        let pool = pool_options(false, true)
+            .connect_with(connect_opts)
+            .await
+            .context(connect_error(keyed))?;
+        Ok(Self {
+            pool,
+            run_id: None,
+            file: None,
+        })
     }
 
     #[tracing::instrument(level = "trace", skip_all, err)]
@@ -75,25 +210,588 @@ impl Db {
         Ok(())
     }
 
+    /// Check if a file is already indexed and unchanged since (same size, modification
+    /// time and inode). Such a file is marked as seen now, and when a run is in progress, counted
+    /// as seen by it, so it's part of the run's snapshot.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
     #[tracing::instrument(level = "trace", skip_all, err)]
     pub async fn exists(&self, f: &File) -> anyhow::Result<bool> {
         let mut conn = self.pool.acquire().await?;
 
-        let total_rows: u32 = sqlx::query_scalar("select count(*) from files where abs_path=?")
-            .bind(&f.abs_path)
-            .fetch_one(&mut conn)
-            .await?;
-        Ok(total_rows != 0)
+        let res = sqlx::query(
+            "update files set run_id=coalesce(?, run_id), last_seen=? where abs_path=? and size is ? and mtime is ? and inode is ?",
+        )
+        .bind(self.run_id)
+        .bind(Utc::now())
+        .bind(&f.abs_path)
+        .bind(f.size)
+        .bind(f.mtime)
+        .bind(f.inode)
+        .execute(&mut conn)
+        .await?;
+        Ok(res.rows_affected() != 0)
     }
 
     #[tracing::instrument(level = "trace", skip_all, err)]
     pub(crate) async fn insert_one(&self, f: &File) -> anyhow::Result<()> {
         let mut conn = self.pool.acquire().await?;
         let q = sqlx::query_as::<_, File>(&INSERT_SQL);
-        f.update_binds(q).fetch_optional(&mut conn).await?;
+        f.update_binds(q)
+            .bind(self.run_id)
+            .bind(Utc::now())
+            .fetch_optional(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Start recording a run. Files stored from now on are stamped with its id.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn start_run(&mut self, root: &str) -> anyhow::Result<i64> {
+        let mut conn = self.pool.acquire().await?;
+        let id = sqlx::query("insert into runs (started_at, root) values (?, ?)")
+            .bind(Utc::now())
+            .bind(root)
+            .execute(&mut conn)
+            .await?
+            .last_insert_rowid();
+        self.run_id = Some(id);
+        Ok(id)
+    }
+
+    /// Finish the current run, keeping a snapshot of every file it has seen
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn finish_run(&self) -> anyhow::Result<()> {
+        let Some(run_id) = self.run_id else {
+            return Ok(());
+        };
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+        INSERT OR REPLACE INTO snapshots
+        SELECT run_id, abs_path, path, size, mode, user, "group", uid, gid, mtime, ctime,
+          crc32, md5, sha256, sha512
+        FROM files WHERE run_id=?
+      "#,
+        )
+        .bind(run_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query("update runs set finished_at=?, files=(select count(*) from snapshots where run_id=?) where id=?")
+            .bind(Utc::now())
+            .bind(run_id)
+            .bind(run_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Finish the current run as partial, without a snapshot, since it hasn't seen every file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn interrupt_run(&self) -> anyhow::Result<()> {
+        let Some(run_id) = self.run_id else {
+            return Ok(());
+        };
+        sqlx::query("update runs set finished_at=?, partial=1, files=(select count(*) from files where run_id=?) where id=?")
+            .bind(Utc::now())
+            .bind(run_id)
+            .bind(run_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record a file the current run couldn't scan
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn store_scan_error(&self, path: &str, error: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            "insert into scan_errors (run_id, path, error, created_at) values (?, ?, ?, ?)",
+        )
+        .bind(self.run_id)
+        .bind(path)
+        .bind(error)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// How many files the current run couldn't scan
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    pub(crate) async fn scan_errors(&self) -> anyhow::Result<i64> {
+        Ok(
+            sqlx::query_scalar("select count(*) from scan_errors where run_id is ?")
+                .bind(self.run_id)
+                .fetch_one(&self.pool)
+                .await?,
+        )
+    }
+
+    /// Replace the integrity baseline with the files seen by the latest run, returning
+    /// how many files are in it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn create_baseline(&self) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("delete from baseline").execute(&mut tx).await?;
+        let res = sqlx::query(
+            r#"
+        INSERT INTO baseline
+        SELECT abs_path, path, size, mode, user, "group", uid, gid, sha256, ?
+        FROM files WHERE run_id=(select max(id) from runs)
+      "#,
+        )
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(res.rows_affected())
+    }
+
+    /// Is there an integrity baseline to verify against
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn has_baseline(&self) -> anyhow::Result<bool> {
+        let total_rows: u32 = sqlx::query_scalar("select count(*) from baseline")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(total_rows != 0)
+    }
+
+    /// Remove a file, or a folder along with everything stored under it, returning
+    /// how many rows were removed
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn delete(&self, abs_path: &str) -> anyhow::Result<u64> {
+        let mut conn = self.pool.acquire().await?;
+        let under = format!(
+            "{}/%",
+            abs_path
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let res = sqlx::query("delete from files where abs_path=? or abs_path like ? escape '\\'")
+            .bind(abs_path)
+            .bind(under)
+            .execute(&mut conn)
+            .await?;
+        Ok(res.rows_affected())
+    }
+
+    /// Keep the content of a file in the full text index, or record that it has none
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self, content), err)]
+    pub(crate) async fn store_content(&self, id: i32, content: Option<&str>) -> anyhow::Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("insert into contents (rowid, content) values (?, ?)")
+            .bind(id)
+            .bind(content)
+            .execute(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Replace the chunks of a file, and record how many it has
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self, chunks), err)]
+    pub(crate) async fn store_chunks(&self, id: i32, chunks: &[Chunk]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("delete from chunks where file_id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        for chunk in chunks {
+            sqlx::query("insert into chunks (file_id, offset, size, hash) values (?, ?, ?, ?)")
+                .bind(id)
+                .bind(i64::try_from(chunk.offset).unwrap_or(i64::MAX))
+                .bind(i64::try_from(chunk.size).unwrap_or(i64::MAX))
+                .bind(&chunk.hash)
+                .execute(&mut tx)
+                .await?;
+        }
+        sqlx::query("update files set chunks = ? where id = ?")
+            .bind(i64::try_from(chunks.len()).unwrap_or(i64::MAX))
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replace the `column` match of a file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self, m), err)]
+    pub(crate) async fn update_match(
+        &self,
+        id: i64,
+        column: &str,
+        m: &Match,
+    ) -> anyhow::Result<()> {
+        sqlx::query(&format!("update files set {column} = ? where id = ?"))
+            .bind(Json(m))
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The `*_match` columns of `files`, in order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn match_columns(&self) -> anyhow::Result<Vec<String>> {
+        Ok(sqlx::query_scalar(
+            "select name from pragma_table_info('files') where name like '%\\_match' escape '\\' order by cid",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Rebuild `dirs` from the files stored: each folder with the size, count, newest
+    /// mtime and matching files of the files under it at any depth. Archive members are
+    /// left to their archive. Returns how many folders there are.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn aggregate_dirs(&self) -> anyhow::Result<usize> {
+        let matched = self
+            .match_columns()
+            .await?
+            .iter()
+            .map(|col| format!("coalesce(json_extract({col}, '$.is_match'), 0)"))
+            .chain(["0".to_string()])
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let files: Vec<(String, Option<i64>, Option<String>, bool)> = sqlx::query_as(&format!(
+            "select path, size, mtime, ({matched}) > 0 from files
+where parent_archive is null and coalesce(is_dir, 0) = 0"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut dirs: BTreeMap<&Path, DirStats> = BTreeMap::new();
+        for (path, size, mtime, matched) in &files {
+            for dir in Path::new(path).ancestors().skip(1) {
+                if dir.as_os_str().is_empty() {
+                    break;
+                }
+                let stats = dirs.entry(dir).or_default();
+                stats.size += size.unwrap_or_default();
+                stats.files += 1;
+                stats.matches += i64::from(*matched);
+                if mtime.as_ref() > stats.newest_mtime.as_ref() {
+                    stats.newest_mtime.clone_from(mtime);
+                }
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("delete from dirs").execute(&mut tx).await?;
+        for (dir, stats) in &dirs {
+            let depth = dir
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .count();
+            sqlx::query(
+                "insert into dirs (path, depth, size, files, newest_mtime, matches) values (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(dir.to_string_lossy())
+            .bind(i64::try_from(depth).unwrap_or(i64::MAX))
+            .bind(stats.size)
+            .bind(stats.files)
+            .bind(&stats.newest_mtime)
+            .bind(stats.matches)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(dirs.len())
+    }
+
+    /// Create an index on `files` over the given comma separated columns, unless it
+    /// already exists
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a column is unknown, or on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn create_index(&self, columns: &str) -> anyhow::Result<()> {
+        let known = File::columns();
+        let columns = columns.split(',').map(str::trim).collect::<Vec<_>>();
+        if let Some(col) = columns.iter().find(|col| !known.contains(col)) {
+            anyhow::bail!("cannot index unknown column '{}'", col);
+        }
+        let sql = format!(
+            "CREATE INDEX IF NOT EXISTS 'files_{}' ON files ({})",
+            columns.join("_"),
+            columns
+                .iter()
+                .map(|c| format!("'{}'", c))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        sqlx::query(&sql).execute(&self.pool).await?;
         Ok(())
     }
 
+    /// Mark files picked at random as sampled, and the rest as not. `size` tells how many
+    /// to pick of how many there are, leaving folders out. Returns how many were picked.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn sample(&self, size: impl FnOnce(u64) -> u64 + Send) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let total: i64 =
+            sqlx::query_scalar("select count(*) from files where not coalesce(is_dir, 0)")
+                .fetch_one(&mut tx)
+                .await?;
+        let picked = size(u64::try_from(total).unwrap_or_default());
+        sqlx::query(
+            "update files set is_sampled = id in (select id from files where not coalesce(is_dir, 0) order by random() limit ?)",
+        )
+        .bind(i64::try_from(picked).unwrap_or(i64::MAX))
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(picked)
+    }
+
+    /// Forget which files an earlier scan sampled, once a scan computes fields of all
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn clear_sample(&self) -> anyhow::Result<()> {
+        sqlx::query("update files set is_sampled = null where is_sampled is not null")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Mark files missing a value in `column` as not computed, so the compute stage
+    /// fills it in
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn recompute_missing(&self, column: &str) -> anyhow::Result<u64> {
+        let res = sqlx::query(&format!(
+            "update files set computed=0 where \"{}\" is null",
+            column
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+
+    /// Files of the current run sharing a value in `column` with other files of the same
+    /// size, as (value, absolute path, copy), grouped by value and ordered by path within a
+    /// group. Hard links of one file are the same copy, and a set of nothing but those
+    /// isn't one. Files only earlier runs have seen may be gone from disk, so they don't count.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn duplicates(
+        &self,
+        column: &str,
+        min_size: u64,
+    ) -> anyhow::Result<Vec<(String, String, String)>> {
+        let sql = format!(
+            r#"select f."{col}", f.abs_path, {copy} as copy from files f
+join (
+  select "{col}", size from files where "{col}" is not null and size >= ? and run_id = ?
+  group by "{col}", size having count(distinct {copy}) > 1
+) d on d."{col}" = f."{col}" and d.size = f.size
+where f.run_id = ?
+order by f."{col}", f.path"#,
+            col = column,
+            copy = COPY_SQL
+        );
+        Ok(sqlx::query_as(&sql)
+            .bind(i64::try_from(min_size).unwrap_or(i64::MAX))
+            .bind(self.run_id)
+            .bind(self.run_id)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    /// Paths and `minhash` signatures of files which have one, ordered by path
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn minhashes(&self) -> anyhow::Result<Vec<(String, String)>> {
+        Ok(sqlx::query_as(
+            "select path, minhash from files where minhash is not null order by path",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Distinct sha256 digests of files of the current run without a VT verdict yet
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn missing_vt_lookups(&self) -> anyhow::Result<Vec<String>> {
+        Ok(sqlx::query_scalar(
+            "select distinct sha256 from files where sha256 is not null and vt_lookup is null and run_id is ?",
+        )
+        .bind(self.run_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// A cached VT verdict for `sha256`, if fetched within `max_age`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn cached_vt_verdict(
+        &self,
+        sha256: &str,
+        max_age: chrono::Duration,
+    ) -> anyhow::Result<Option<Json<VtVerdict>>> {
+        Ok(
+            sqlx::query_scalar("select verdict from vt_cache where sha256 = ? and fetched_at > ?")
+                .bind(sha256)
+                .bind(Utc::now() - max_age)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    /// Set the VT verdict of all files with `sha256`, and cache it for later runs
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self, verdict), err)]
+    pub(crate) async fn store_vt_verdict(
+        &self,
+        sha256: &str,
+        verdict: &Json<VtVerdict>,
+        cache: bool,
+    ) -> anyhow::Result<()> {
+        if cache {
+            sqlx::query(
+                "insert or replace into vt_cache (sha256, verdict, fetched_at) values (?, ?, ?)",
+            )
+            .bind(sha256)
+            .bind(verdict)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+        }
+        sqlx::query("update files set vt_lookup = ? where sha256 = ?")
+            .bind(verdict)
+            .bind(sha256)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Lockfiles and manifests with packages, but without `osv_vulns` yet, by absolute path
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn missing_osv_vulns(
+        &self,
+    ) -> anyhow::Result<Vec<(String, Json<Vec<Package>>)>> {
+        Ok(sqlx::query_as(
+            "select abs_path, packages from files where packages is not null and osv_vulns is null",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Set the known vulnerabilities of a file's packages
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self, vulns), err)]
+    pub(crate) async fn store_osv_vulns(
+        &self,
+        abs_path: &str,
+        vulns: &Json<Vec<OsvVuln>>,
+    ) -> anyhow::Result<()> {
+        sqlx::query("update files set osv_vulns = ? where abs_path = ?")
+            .bind(vulns)
+            .bind(abs_path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// All indexed paths
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn abs_paths(&self) -> anyhow::Result<Vec<String>> {
+        Ok(sqlx::query_scalar("select abs_path from files")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
     /// Query into a `Vec` of files, materialized, for dealing with native `File`s.
     ///
     /// # Errors
@@ -107,7 +805,25 @@ impl Db {
 
     #[tracing::instrument(level = "trace", skip_all, err)]
     pub(crate) async fn query_table(&self, q: &str) -> anyhow::Result<ValuesTable> {
-        let res = sqlx::query(q).fetch_all(&self.pool).await?;
+        self.query_table_with(q, &[]).await
+    }
+
+    /// Query into a table, binding `args` to the query's placeholders in order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn query_table_with(
+        &self,
+        q: &str,
+        args: &[String],
+    ) -> anyhow::Result<ValuesTable> {
+        let res = args
+            .iter()
+            .fold(sqlx::query(q), sqlx::query::Query::bind)
+            .fetch_all(&self.pool)
+            .await?;
         let total_rows: u32 = sqlx::query_scalar("select count(*) from files")
             .fetch_one(&self.pool)
             .await?;
@@ -136,11 +852,177 @@ impl Db {
                     columns,
                     rows,
                     total_rows,
+                    incomplete: false,
                 })
             },
         )
     }
 
+    /// Query, writing each row to `out` as a JSON line as soon as it's read rather than
+    /// collecting the rows first. Returns how many rows were written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db or write failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn query_jsonl(
+        &self,
+        q: &str,
+        out: &mut (dyn Write + Send),
+    ) -> anyhow::Result<usize> {
+        let mut rows = sqlx::query(q).fetch(&self.pool);
+        let mut columns = None;
+        let mut count = 0;
+        while let Some(row) = rows.next().await {
+            let row = row.context("error while performing query")?;
+            let columns = columns.get_or_insert_with(|| {
+                row.columns()
+                    .iter()
+                    .map(|c| c.name().to_string())
+                    .collect::<Vec<_>>()
+            });
+            let values = row.columns().iter().map(|col| repr_col(&row, col));
+            out.write_all(jsonl_line(columns, values)?.as_bytes())?;
+            count += 1;
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    /// The declared column names and types of `table`, in order
+    async fn table_columns(&self, table: &str) -> anyhow::Result<Vec<(String, String)>> {
+        Ok(
+            sqlx::query_as("select name, type from pragma_table_info(?) order by cid")
+                .bind(table)
+                .fetch_all(&self.pool)
+                .await?,
+        )
+    }
+
+    /// Every row of `table`, by its column names. Values of `JSON` columns are parsed, and
+    /// the full text index comes with the `rowid` tying it to its file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub(crate) async fn dump(&self, table: &str) -> anyhow::Result<ValuesTable> {
+        let mut columns = self.table_columns(table).await?;
+        if table == "contents" {
+            columns.insert(0, ("rowid".to_string(), "INTEGER".to_string()));
+        }
+        let select = columns
+            .iter()
+            .map(|(name, _)| format!("\"{name}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let rows = sqlx::query(&format!("select {select} from \"{table}\""))
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                row.columns()
+                    .iter()
+                    .zip(&columns)
+                    .map(|(col, (_, ty))| match repr_col(row, col) {
+                        serde_json::Value::String(s) if ty.eq_ignore_ascii_case("JSON") => {
+                            serde_json::from_str(&s).unwrap_or(serde_json::Value::String(s))
+                        }
+                        value => value,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        Ok(ValuesTable {
+            columns: columns.into_iter().map(|(name, _)| name).collect(),
+            total_rows: u32::try_from(rows.len()).unwrap_or(u32::MAX),
+            rows,
+            incomplete: false,
+        })
+    }
+
+    /// Replace what `tables` hold with the given rows, all at once, by table and column
+    /// names: tables and columns this DB doesn't have are left out, and columns it has
+    /// that the rows don't are null. Returns the rows stored in each table.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on db failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    pub(crate) async fn restore(
+        &self,
+        tables: &[(&str, &ValuesTable)],
+    ) -> anyhow::Result<Vec<(String, u64)>> {
+        let mut targets = vec![];
+        for (table, vt) in tables {
+            let mut columns = self.table_columns(table).await?;
+            if columns.is_empty() {
+                info!("skipping table '{}', which this db doesn't have", table);
+                continue;
+            }
+            if *table == "contents" {
+                columns.insert(0, ("rowid".to_string(), "INTEGER".to_string()));
+            }
+            // the column of each value of a row that this db has, and if it's `JSON`
+            let picked = vt
+                .columns
+                .iter()
+                .enumerate()
+                .filter_map(|(nth, name)| {
+                    let found = columns.iter().find(|(col, _)| col == name);
+                    if found.is_none() {
+                        info!("skipping column '{}.{}'", table, name);
+                    }
+                    found.map(|(col, ty)| (nth, col.clone(), ty.eq_ignore_ascii_case("JSON")))
+                })
+                .collect::<Vec<_>>();
+            targets.push((*table, *vt, picked));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        // clear every table before filling any, as removing files also removes their
+        // contents and chunks
+        for (table, _, _) in &targets {
+            sqlx::query(&format!("delete from \"{table}\""))
+                .execute(&mut tx)
+                .await?;
+        }
+        let mut stored = vec![];
+        for (table, vt, picked) in targets {
+            let insert = format!(
+                "insert into \"{}\" ({}) values ({})",
+                table,
+                picked
+                    .iter()
+                    .map(|(_, col, _)| format!("\"{col}\""))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                picked.iter().map(|_| "?").collect::<Vec<_>>().join(", ")
+            );
+            for row in &vt.rows {
+                let mut query = sqlx::query(&insert);
+                for (nth, _, is_json) in &picked {
+                    let value = row.get(*nth).unwrap_or(&serde_json::Value::Null);
+                    query = match value {
+                        serde_json::Value::Null => query.bind(None::<String>),
+                        value if *is_json => query.bind(value.to_string()),
+                        serde_json::Value::Bool(b) => query.bind(b),
+                        serde_json::Value::Number(n) => match n.as_i64() {
+                            Some(n) => query.bind(n),
+                            None => query.bind(n.as_f64()),
+                        },
+                        serde_json::Value::String(s) => query.bind(s),
+                        value => query.bind(value.to_string()),
+                    };
+                }
+                query.execute(&mut tx).await?;
+            }
+            stored.push((table.to_string(), vt.rows.len() as u64));
+        }
+        tx.commit().await?;
+        Ok(stored)
+    }
+
     /// Gives out an opaque holder of a connection
     ///
     /// # Errors
@@ -178,7 +1060,7 @@ pub fn repr_col(row: &SqliteRow, col: &SqliteColumn) -> serde_json::Value {
                 val.try_decode::<i64>().map(|t| serde_json::json!(t))
             }
             "FLOAT" => val.try_decode::<f32>().map(|t| serde_json::json!(t)),
-            "DOUBLE" => val.try_decode::<f64>().map(|t| serde_json::json!(t)),
+            "DOUBLE" | "REAL" => val.try_decode::<f64>().map(|t| serde_json::json!(t)),
             "NULL" => Ok(json!("NULL")),
             "DATE" => val
                 .try_decode::<DateTime<Utc>>()
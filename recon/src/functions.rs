@@ -0,0 +1,241 @@
+//!
+//! SQL functions added to every DB connection:
+//!
+//! * `x REGEXP pattern` (or `regexp(pattern, x)`): does `x` match the regular expression
+//! * `hamming(a, b)` (alias `hamming_distance`): how many bits differ between two hex
+//!   hashes, e.g. `simhash`, to find near duplicates
+//!
+//! Both return `NULL` if any argument is `NULL`.
+//!
+//! Connections which may write to the DB also get functions computing fields of the file
+//! at a path on demand, so only the rows a query narrows down to are read, e.g.
+//! `select path, file_sha256(abs_path) from files where size > 1000000`:
+//!
+//! * `file_sha256(path)`, `file_sha512(path)`, `file_md5(path)`, `file_crc32(path)`
+//! * `file_magic(path)`: the file type, as told by `file`
+//! * `file_entropy(path)`: Shannon entropy of the content, in bits per byte
+//!
+//! These return `NULL` for paths which are not files on disk, such as archive members,
+//! and for files they fail on (logged), so a query isn't lost to one unreadable file.
+//!
+use crate::data::File;
+use crate::matching::hamming_distance;
+use crate::processing;
+use libsqlite3_sys::{
+    sqlite3, sqlite3_context, sqlite3_create_function_v2, sqlite3_get_auxdata,
+    sqlite3_result_double, sqlite3_result_error, sqlite3_result_int64, sqlite3_result_null,
+    sqlite3_result_text, sqlite3_set_auxdata, sqlite3_value, sqlite3_value_bytes,
+    sqlite3_value_text, sqlite3_value_type, SQLITE_DETERMINISTIC, SQLITE_NULL, SQLITE_OK,
+    SQLITE_TRANSIENT, SQLITE_UTF8,
+};
+use regex::Regex;
+use std::ffi::{c_int, c_void, CString};
+use std::path::Path;
+use std::ptr::NonNull;
+use tracing::info;
+
+type ScalarFn = unsafe extern "C" fn(*mut sqlite3_context, c_int, *mut *mut sqlite3_value);
+
+/// Register all functions on a connection, including the `file_*` ones when `with_files`
+///
+/// # Errors
+///
+/// This function will return an error if `SQLite` refused a function
+pub fn register(db: NonNull<sqlite3>, with_files: bool) -> Result<(), String> {
+    let functions: [(&str, c_int, c_int, ScalarFn); 3] = [
+        ("regexp", 2, SQLITE_DETERMINISTIC, regexp),
+        ("hamming", 2, SQLITE_DETERMINISTIC, hamming),
+        ("hamming_distance", 2, SQLITE_DETERMINISTIC, hamming),
+    ];
+    // files change on disk, so these aren't deterministic
+    let file_functions: [(&str, c_int, c_int, ScalarFn); 6] = [
+        ("file_sha256", 1, 0, file_sha256),
+        ("file_sha512", 1, 0, file_sha512),
+        ("file_md5", 1, 0, file_md5),
+        ("file_crc32", 1, 0, file_crc32),
+        ("file_magic", 1, 0, file_magic),
+        ("file_entropy", 1, 0, file_entropy),
+    ];
+    let file_functions = if with_files { &file_functions[..] } else { &[] };
+    for &(name, args, flags, func) in functions.iter().chain(file_functions) {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        // SAFETY: the handle is locked by the caller for the duration of this call,
+        // and SQLite copies the name
+        let rc = unsafe {
+            sqlite3_create_function_v2(
+                db.as_ptr(),
+                c_name.as_ptr(),
+                args,
+                SQLITE_UTF8 | flags,
+                std::ptr::null_mut(),
+                Some(func),
+                None,
+                None,
+                None,
+            )
+        };
+        if rc != SQLITE_OK {
+            return Err(format!("cannot register SQL function '{}': {}", name, rc));
+        }
+    }
+    Ok(())
+}
+
+/// Read argument `i` as text, `None` when it is `NULL`
+///
+/// # Safety
+///
+/// `argv` must hold at least `i + 1` values, as given by `SQLite` to a function
+unsafe fn text_arg<'a>(argv: *mut *mut sqlite3_value, i: usize) -> Option<&'a str> {
+    let value = *argv.add(i);
+    if sqlite3_value_type(value) == SQLITE_NULL {
+        return None;
+    }
+    let text = sqlite3_value_text(value);
+    let len = usize::try_from(sqlite3_value_bytes(value)).unwrap_or_default();
+    if text.is_null() {
+        return Some("");
+    }
+    std::str::from_utf8(std::slice::from_raw_parts(text, len)).ok()
+}
+
+/// Report an error as the result of a function call
+unsafe fn result_error(ctx: *mut sqlite3_context, msg: &str) {
+    let len = c_int::try_from(msg.len()).unwrap_or(c_int::MAX);
+    sqlite3_result_error(ctx, msg.as_ptr().cast(), len);
+}
+
+unsafe extern "C" fn drop_regex(re: *mut c_void) {
+    drop(Box::from_raw(re.cast::<Regex>()));
+}
+
+unsafe extern "C" fn regexp(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let (Some(pattern), Some(text)) = (text_arg(argv, 0), text_arg(argv, 1)) else {
+        sqlite3_result_null(ctx);
+        return;
+    };
+
+    // the pattern is compiled once per statement, and kept by SQLite between rows
+    let cached = sqlite3_get_auxdata(ctx, 0).cast::<Regex>();
+    if let Some(re) = cached.as_ref() {
+        sqlite3_result_int64(ctx, i64::from(re.is_match(text)));
+        return;
+    }
+    match Regex::new(pattern) {
+        Ok(re) => {
+            sqlite3_result_int64(ctx, i64::from(re.is_match(text)));
+            // SQLite may drop the regex right away, so it's not used after handing it over
+            sqlite3_set_auxdata(
+                ctx,
+                0,
+                Box::into_raw(Box::new(re)).cast::<c_void>(),
+                Some(drop_regex),
+            );
+        }
+        Err(err) => result_error(ctx, &err.to_string()),
+    }
+}
+
+unsafe extern "C" fn hamming(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let (Some(a), Some(b)) = (text_arg(argv, 0), text_arg(argv, 1)) else {
+        sqlite3_result_null(ctx);
+        return;
+    };
+    match hamming_distance(a, b) {
+        Some(distance) => sqlite3_result_int64(ctx, i64::from(distance)),
+        None => result_error(ctx, "hamming: expected two hex hashes"),
+    }
+}
+
+/// Compute a field of the file at the path given as argument 0. `None` when it's `NULL`,
+/// not a file on disk, or computing failed
+///
+/// # Safety
+///
+/// `argv` must hold at least one value, as given by `SQLite` to a function
+unsafe fn file_field<T>(
+    name: &str,
+    argv: *mut *mut sqlite3_value,
+    compute: fn(&File, &bool) -> anyhow::Result<Option<T>>,
+) -> Option<T> {
+    let path = Path::new(text_arg(argv, 0)?);
+    if !path.is_file() {
+        return None;
+    }
+    File::from_path(path)
+        .and_then(|file| compute(&file, &true))
+        .unwrap_or_else(|err| {
+            info!("{} on '{}': {:#}", name, path.display(), err);
+            None
+        })
+}
+
+/// Report a computed text field as the result of a function call
+unsafe fn result_text(ctx: *mut sqlite3_context, text: Option<String>) {
+    let Some(text) = text else {
+        sqlite3_result_null(ctx);
+        return;
+    };
+    let len = c_int::try_from(text.len()).unwrap_or(c_int::MAX);
+    // SQLite copies the text before it's dropped
+    sqlite3_result_text(ctx, text.as_ptr().cast(), len, SQLITE_TRANSIENT());
+}
+
+unsafe extern "C" fn file_sha256(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    result_text(ctx, file_field("file_sha256", argv, processing::sha256));
+}
+
+unsafe extern "C" fn file_sha512(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    result_text(ctx, file_field("file_sha512", argv, processing::sha512));
+}
+
+unsafe extern "C" fn file_md5(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    result_text(ctx, file_field("file_md5", argv, processing::md5));
+}
+
+unsafe extern "C" fn file_crc32(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    result_text(ctx, file_field("file_crc32", argv, processing::crc32));
+}
+
+unsafe extern "C" fn file_magic(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    result_text(ctx, file_field("file_magic", argv, processing::file_magic));
+}
+
+unsafe extern "C" fn file_entropy(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    match file_field("file_entropy", argv, processing::entropy) {
+        Some(entropy) => sqlite3_result_double(ctx, entropy),
+        None => sqlite3_result_null(ctx),
+    }
+}
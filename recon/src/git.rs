@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use git2::{
+    build::RepoBuilder, Commit, FetchOptions, ObjectType, Repository, Sort, TreeWalkMode,
+    TreeWalkResult,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+///
+/// A repository cloned into a temporary folder
+///
+pub struct Cloned {
+    pub dir: TempDir,
+    pub remote: String,
+    pub commit: String,
+}
+
+/// Is this root a git remote rather than a local folder
+#[must_use]
+pub fn is_remote(root: &str) -> bool {
+    ["https://", "http://", "git://", "ssh://", "file://", "git@"]
+        .iter()
+        .any(|scheme| root.starts_with(scheme))
+}
+
+/// Shallow clone a repository (latest commit only) into a temporary folder
+///
+/// # Errors
+///
+/// This function will return an error if the temporary folder cannot be created,
+/// or cloning failed
+pub fn clone(url: &str) -> Result<Cloned> {
+    let dir = tempfile::Builder::new().prefix("recon-git").tempdir()?;
+    let mut fetch = FetchOptions::new();
+    fetch.depth(1);
+    let repo = RepoBuilder::new()
+        .fetch_options(fetch)
+        .clone(url, dir.path())
+        .with_context(|| format!("cannot clone '{}'", url))?;
+    let commit = repo.head()?.peel_to_commit()?.id().to_string();
+    drop(repo);
+    Ok(Cloned {
+        dir,
+        remote: url.to_string(),
+        commit,
+    })
+}
+
+///
+/// The latest commit that changed a file
+///
+#[derive(Clone)]
+pub struct LastCommit {
+    pub id: String,
+    pub author: String,
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl From<&Commit<'_>> for LastCommit {
+    fn from(commit: &Commit<'_>) -> Self {
+        Self {
+            id: commit.id().to_string(),
+            author: commit.author().to_string(),
+            time: Utc.timestamp_opt(commit.time().seconds(), 0).single(),
+        }
+    }
+}
+
+/// Open the repository containing `path`, along with `path` relative to its work tree.
+/// Returns `None` when `path` is not inside a repository work tree.
+fn open(path: &Path) -> Result<Option<(Repository, PathBuf)>> {
+    let path = fs::canonicalize(path)?;
+    let Ok(repo) = Repository::discover(path.parent().unwrap_or(&path)) else {
+        return Ok(None);
+    };
+    let Some(workdir) = repo.workdir() else {
+        return Ok(None);
+    };
+    let Ok(rel) = path
+        .strip_prefix(fs::canonicalize(workdir)?)
+        .map(Path::to_path_buf)
+    else {
+        return Ok(None);
+    };
+    Ok(Some((repo, rel)))
+}
+
+/// Is `path` in the index of its repository
+///
+/// # Errors
+///
+/// This function will return an error if the repository index cannot be read
+pub fn is_tracked(path: &Path) -> Result<Option<bool>> {
+    let Some((repo, rel)) = open(path)? else {
+        return Ok(None);
+    };
+    let tracked = repo.index()?.get_path(&rel, 0).is_some();
+    Ok(Some(tracked))
+}
+
+/// Histories walked this run, by the git folder of their repository
+static HISTORIES: Mutex<BTreeMap<PathBuf, Arc<History>>> = Mutex::new(BTreeMap::new());
+
+///
+/// The latest commit that changed each file of a repository's HEAD, found in a single
+/// walk of its history
+///
+struct History {
+    workdir: PathBuf,
+    last_commits: HashMap<PathBuf, LastCommit>,
+}
+
+impl History {
+    /// Walk history from HEAD, newest first, giving each file the first commit whose
+    /// blob for it differs from that of every parent
+    fn walk(repo: &Repository) -> Result<Self> {
+        let workdir = repo
+            .workdir()
+            .map(fs::canonicalize)
+            .transpose()?
+            .unwrap_or_default();
+        let mut last_commits = HashMap::new();
+        let Ok(head) = repo.head().and_then(|h| h.peel_to_tree()) else {
+            // unborn branch, nothing committed yet
+            return Ok(Self {
+                workdir,
+                last_commits,
+            });
+        };
+        let mut pending = HashSet::new();
+        head.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                pending.insert(Path::new(dir).join(entry.name().unwrap_or_default()));
+            }
+            TreeWalkResult::Ok
+        })?;
+
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+        walk.set_sorting(Sort::TIME)?;
+        for oid in walk {
+            if pending.is_empty() {
+                break;
+            }
+            let commit = repo.find_commit(oid?)?;
+            let tree = commit.tree()?;
+            // changed against every parent, or everything for a root commit
+            let mut changed: Option<HashSet<PathBuf>> = None;
+            for parent in commit.parents() {
+                let diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&tree), None)?;
+                let paths = diff
+                    .deltas()
+                    .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+                    .collect::<HashSet<_>>();
+                changed = Some(match changed {
+                    Some(changed) => &changed & &paths,
+                    None => paths,
+                });
+            }
+            let changed: Vec<PathBuf> = changed.map_or_else(
+                || pending.iter().cloned().collect(),
+                |changed| changed.into_iter().collect(),
+            );
+            for path in changed {
+                if tree.get_path(&path).is_ok() && pending.remove(&path) {
+                    last_commits.insert(path, LastCommit::from(&commit));
+                }
+            }
+        }
+        Ok(Self {
+            workdir,
+            last_commits,
+        })
+    }
+}
+
+/// Forget histories walked so far, so the next run sees commits made since
+pub fn forget_history() {
+    HISTORIES.lock().unwrap().clear();
+}
+
+/// Find the latest commit reachable from HEAD that changed `path`. The history of a
+/// repository is walked once per run, for all of its files.
+///
+/// # Errors
+///
+/// This function will return an error if the repository history cannot be read
+pub fn last_commit(path: &Path) -> Result<Option<LastCommit>> {
+    let path = fs::canonicalize(path)?;
+    let Ok(git_dir) = Repository::discover_path(path.parent().unwrap_or(&path), None::<&str>)
+    else {
+        return Ok(None);
+    };
+    let history = {
+        let mut histories = HISTORIES.lock().unwrap();
+        if let Some(history) = histories.get(&git_dir) {
+            history.clone()
+        } else {
+            let history = Arc::new(History::walk(&Repository::open(&git_dir)?)?);
+            histories.insert(git_dir, history.clone());
+            history
+        }
+    };
+    Ok(path
+        .strip_prefix(&history.workdir)
+        .ok()
+        .and_then(|rel| history.last_commits.get(rel))
+        .cloned())
+}
@@ -5,13 +5,35 @@
 #![allow(clippy::similar_names)]
 #![allow(clippy::too_many_lines)]
 #![allow(clippy::uninlined_format_args)]
+pub use builder::{Recon, ReconBuilder};
 pub use data::DB_FILE;
+pub use tokio_util::sync::CancellationToken;
 
+mod archive;
+mod builder;
+mod bundle;
 pub mod config;
 pub mod data;
 pub mod db;
+mod functions;
+mod git;
 mod matching;
+pub mod metrics;
+mod minhash;
+pub mod observer;
 pub mod os;
+mod osv;
 pub mod out;
+mod packages;
+mod pe;
 mod processing;
+pub mod processor;
+mod s3;
+pub mod schedule;
+mod scripting;
+mod server;
+pub mod suppress;
+pub mod syslog;
+mod vt;
 pub mod workflow;
+mod yara_engine;
@@ -1,100 +1,519 @@
+use crate::config::{
+    AllowlistOptions, BazaarMatchOptions, ByteSignature, DigestSet, LiteralsMatchOptions, Patterns,
+    Redaction, RuleInfo, SimhashMatchOptions, YaraMatchOptions,
+};
 use crate::data::File;
 use crate::data::Match;
+use crate::yara_engine::Rules;
 use anyhow::bail;
 use anyhow::Result;
 use regex::Regex;
-use std::collections::HashMap;
+use serde_json::json;
+use sha2::Digest;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File as FsFile;
 use std::io::Read;
-use std::path::Path;
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn yara_match(file: &File, rules: &str) -> Result<Option<Match>> {
-    let path = Path::new(&file.abs_path);
-    let mut f = FsFile::open(path)?;
-    let mut data = Vec::new();
-    f.read_to_end(&mut data)?;
+pub fn yara_match(file: &File, opts: &YaraMatchOptions) -> Result<Option<Match>> {
+    let path = file.content_path();
+    let skipped = if opts.skip_archives.unwrap_or(false) && file.is_archive == Some(true) {
+        Some("archive".to_string())
+    } else if opts.skip_media.unwrap_or(false) && file.is_media == Some(true) {
+        Some("media".to_string())
+    } else {
+        match (opts.max_size, std::fs::metadata(path)) {
+            (Some(max_size), Ok(meta)) if meta.len() > max_size => {
+                Some(format!("larger than {} bytes", max_size))
+            }
+            _ => None,
+        }
+    };
+    if let Some(reason) = skipped {
+        return Ok(Some(yara_failure(file, json!({ "skipped": reason }))));
+    }
 
-    let compiler = yara::Compiler::new()?;
-    let compiler = compiler.add_rules_str(rules)?;
-    let rules = compiler.compile_rules()?;
+    let rules = Rules::compile(file, &opts.rules)?;
 
-    let res = rules.scan_mem(&data[..], 5)?;
+    // a file that can't be read or scanned in time is recorded, and doesn't stop the run
+    let res = match std::fs::read(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| rules.scan(&data, opts.timeout()))
+    {
+        Ok(res) => res,
+        Err(err) => {
+            return Ok(Some(yara_failure(
+                file,
+                json!({ "error": err.to_string() }),
+            )))
+        }
+    };
     // parse out matches into a kind of bit map
     let by = res
         .iter()
-        .map(|r| (r.identifier.to_string(), true))
+        .filter_map(|r| r["identifier"].as_str())
+        .map(|identifier| (identifier.to_string(), true))
         .collect::<HashMap<String, bool>>();
 
     Ok(Some(Match {
         is_match: !res.is_empty(),
         on: file.abs_path.to_string(),
         by,
-        details: Some(serde_json::to_value(&res)?),
+        rules: set_rules(!res.is_empty(), &opts.info, "yara"),
+        details: Some(serde_json::Value::Array(res)),
+        ..Default::default()
     }))
 }
 
+/// A yara match that didn't scan the file, with the reason in `details`
+/// The info of a whole rule set (yara rules, a digest set) when it matched and has any
+fn set_rules(is_match: bool, info: &RuleInfo, default_name: &str) -> Vec<RuleInfo> {
+    if is_match && !info.is_empty() {
+        vec![info.named(default_name)]
+    } else {
+        vec![]
+    }
+}
+
+/// The info of each named pattern that matched, for those that have any
+fn named_rules<T>(by: &HashMap<String, bool>, patterns: &Patterns<T>) -> Vec<RuleInfo> {
+    let mut rules = by
+        .iter()
+        .filter(|(_, matched)| **matched)
+        .filter_map(|(name, _)| {
+            patterns
+                .info(name)
+                .filter(|info| !info.is_empty())
+                .map(|info| info.named(name))
+        })
+        .collect::<Vec<_>>();
+    rules.sort_by(|a, b| a.name.cmp(&b.name));
+    rules
+}
+
+fn yara_failure(file: &File, details: serde_json::Value) -> Match {
+    Match {
+        is_match: false,
+        on: file.abs_path.to_string(),
+        details: Some(details),
+        ..Default::default()
+    }
+}
+
 pub fn value_match(
-    on: &str,
+    file: &File,
     name: &str,
     val: Option<&String>,
-    vals: &[String],
+    vals: &DigestSet,
 ) -> Result<Option<Match>> {
     if let Some(val) = val {
+        let is_match = vals.contains(val)?;
         Ok(Some(Match {
-            is_match: vals.iter().any(|v| val.eq(v)),
-            on: on.to_string(),
+            is_match,
+            on: file.abs_path.to_string(),
             by: HashMap::from([(name.to_string(), true)]),
+            rules: set_rules(is_match, &vals.info, name),
             ..Default::default()
         }))
+    } else if file.skipped(name) {
+        Ok(None)
     } else {
         bail!(format!("{} value was not computed", name))
     }
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn crc32_match(file: &File, vals: &[String]) -> Result<Option<Match>> {
-    value_match(&file.abs_path, "crc32", file.crc32.as_ref(), vals)
+pub fn crc32_match(file: &File, vals: &DigestSet) -> Result<Option<Match>> {
+    value_match(file, "crc32", file.crc32.as_ref(), vals)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn sha256_match(file: &File, vals: &[String]) -> Result<Option<Match>> {
-    value_match(&file.abs_path, "sha256", file.sha256.as_ref(), vals)
+pub fn sha256_match(file: &File, vals: &DigestSet) -> Result<Option<Match>> {
+    value_match(file, "sha256", file.sha256.as_ref(), vals)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn sha512_match(file: &File, vals: &[String]) -> Result<Option<Match>> {
-    value_match(&file.abs_path, "sha512", file.sha512.as_ref(), vals)
+pub fn sha512_match(file: &File, vals: &DigestSet) -> Result<Option<Match>> {
+    value_match(file, "sha512", file.sha512.as_ref(), vals)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn md5_match(file: &File, vals: &[String]) -> Result<Option<Match>> {
-    value_match(&file.abs_path, "md5", file.md5.as_ref(), vals)
+pub fn md5_match(file: &File, vals: &DigestSet) -> Result<Option<Match>> {
+    value_match(file, "md5", file.md5.as_ref(), vals)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn simhash_match(file: &File, vals: &[String]) -> Result<Option<Match>> {
-    value_match(&file.abs_path, "simhash", file.simhash.as_ref(), vals)
+pub fn bazaar_match(file: &File, opts: &BazaarMatchOptions) -> Result<Option<Match>> {
+    if file.skipped("sha256") {
+        return Ok(None);
+    }
+    value_match(file, "bazaar", file.sha256.as_ref(), opts.digests()?)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn path_match(file: &File, re: &Regex) -> Result<Option<Match>> {
+pub fn is_known_good(file: &File, allowlist: &AllowlistOptions) -> Result<Option<bool>> {
+    let sets = [
+        ("crc32", &allowlist.crc32, &file.crc32),
+        ("sha256", &allowlist.sha256, &file.sha256),
+        ("sha512", &allowlist.sha512, &file.sha512),
+        ("md5", &allowlist.md5, &file.md5),
+    ];
+    for (name, set, val) in sets {
+        let Some(set) = set else { continue };
+        let Some(val) = val else {
+            if file.skipped(name) {
+                continue;
+            }
+            bail!("{} value was not computed", name)
+        };
+        if set.contains(val)? {
+            return Ok(Some(true));
+        }
+    }
+    Ok(Some(false))
+}
+
+/// Number of bits that differ between two hex encoded hashes
+#[must_use]
+pub fn hamming_distance(a: &str, b: &str) -> Option<u32> {
+    let a = u64::from_str_radix(a, 16).ok()?;
+    let b = u64::from_str_radix(b, 16).ok()?;
+    Some((a ^ b).count_ones())
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn simhash_match(file: &File, opts: &SimhashMatchOptions) -> Result<Option<Match>> {
+    let Some(simhash) = file.simhash.as_ref() else {
+        if file.skipped("simhash") {
+            return Ok(None);
+        }
+        bail!("simhash value was not computed")
+    };
+    let closest = opts
+        .hashes
+        .iter()
+        .map(|hash| match hamming_distance(simhash, hash) {
+            Some(distance) => Ok((hash, distance)),
+            None => bail!("invalid simhash '{}'", hash),
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .min_by_key(|(_, distance)| *distance);
+    Ok(Some(Match {
+        is_match: closest.is_some_and(|(_, d)| d <= opts.max_distance()),
+        on: file.abs_path.to_string(),
+        by: HashMap::from([("simhash".to_string(), true)]),
+        details: closest.map(|(hash, distance)| json!({ "hash": hash, "distance": distance })),
+        ..Default::default()
+    }))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn path_match(file: &File, patterns: &Patterns<Regex>) -> Result<Option<Match>> {
+    let (is_match, by) = match patterns {
+        Patterns::One(re) => (
+            re.is_match(&file.abs_path),
+            HashMap::from([("path".to_string(), true)]),
+        ),
+        Patterns::Named(_) => {
+            let by = patterns
+                .named("path")
+                .into_iter()
+                .map(|(name, re)| (name.to_string(), re.is_match(&file.abs_path)))
+                .collect::<HashMap<_, _>>();
+            (by.values().any(|matched| *matched), by)
+        }
+    };
     Ok(Some(Match {
-        is_match: re.is_match(&file.abs_path),
+        is_match,
         on: file.abs_path.to_string(),
-        by: HashMap::from([("path".to_string(), true)]),
+        rules: named_rules(&by, patterns),
+        by,
         ..Default::default()
     }))
 }
 
+/// stop recording content matches of a pattern after this many
+const MAX_CONTENT_MATCHES: usize = 100;
+/// bytes of the matching line to keep as a snippet
+const MAX_SNIPPET_LEN: usize = 120;
+
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn content_match(file: &File, re: &regex::bytes::Regex) -> Result<Option<Match>> {
-    let path = Path::new(&file.abs_path);
+pub fn content_match(
+    file: &File,
+    patterns: &Patterns<regex::bytes::Regex>,
+) -> Result<Option<Match>> {
+    let path = file.content_path();
     let content = std::fs::read(path)?;
+
+    let mut by = HashMap::new();
+    let mut matches = vec![];
+    let mut truncated = false;
+    match patterns {
+        Patterns::One(re) => {
+            by.insert("content".to_string(), true);
+            truncated |= find_content(&content, re, None, &mut matches);
+        }
+        Patterns::Named(_) => {
+            for (name, re) in patterns.named("content") {
+                let before = matches.len();
+                truncated |= find_content(&content, re, Some(name), &mut matches);
+                by.insert(name.to_string(), matches.len() > before);
+            }
+        }
+    }
+
     Ok(Some(Match {
-        is_match: re.is_match(&content),
+        is_match: !matches.is_empty(),
         on: file.abs_path.to_string(),
-        by: HashMap::from([("content".to_string(), true)]),
+        rules: named_rules(&by, patterns),
+        by,
+        details: (!matches.is_empty())
+            .then(|| json!({ "matches": matches, "truncated": truncated })),
         ..Default::default()
     }))
 }
+
+/// Record where `re` matches in `content` with the offset, line and a snippet of each
+/// match, tagged with `name` when given. Returns whether matches were left out, over the cap.
+fn find_content(
+    content: &[u8],
+    re: &regex::bytes::Regex,
+    name: Option<&str>,
+    matches: &mut Vec<serde_json::Value>,
+) -> bool {
+    let found = re
+        .find_iter(content)
+        .take(MAX_CONTENT_MATCHES + 1)
+        .collect::<Vec<_>>();
+
+    // lines are counted as we go, each match continues from the previous one
+    let (mut line, mut counted) = (1, 0);
+    for m in found.iter().take(MAX_CONTENT_MATCHES) {
+        line += count_lines(&content[counted..m.start()]);
+        counted = m.start();
+
+        let line_start = content[..m.start()]
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line_end = content[m.start()..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(content.len(), |i| m.start() + i);
+        // long lines (minified files) are cut around the match
+        let start = line_start.max(m.start().saturating_sub(MAX_SNIPPET_LEN / 4));
+        let snippet = &content[start..line_end.min(start + MAX_SNIPPET_LEN)];
+
+        let mut found = json!({
+            "offset": m.start(),
+            "line": line,
+            "snippet": String::from_utf8_lossy(snippet).trim(),
+        });
+        if let Some(name) = name {
+            found["name"] = json!(name);
+        }
+        matches.push(found);
+    }
+    found.len() > MAX_CONTENT_MATCHES
+}
+
+/// characters kept at each end of masked text, when it has at least `MASK_MIN_LEN`
+const MASK_KEEP: usize = 2;
+const MASK_MIN_LEN: usize = 8;
+/// hex digits of the sha256 that stands for hashed text
+const HASH_LEN: usize = 12;
+
+fn redacted(text: &str, redaction: Redaction) -> String {
+    match redaction {
+        Redaction::Mask => {
+            let len = text.chars().count();
+            let keep = if len >= MASK_MIN_LEN { MASK_KEEP } else { 0 };
+            text.chars()
+                .enumerate()
+                .map(|(nth, c)| {
+                    if nth < keep || nth >= len - keep {
+                        c
+                    } else {
+                        '*'
+                    }
+                })
+                .collect()
+        }
+        Redaction::Hash | Redaction::Drop => {
+            let hash = format!("{:x}", sha2::Sha256::digest(text.as_bytes()));
+            format!("[sha256:{}]", &hash[..HASH_LEN])
+        }
+    }
+}
+
+/// Redact what `re` matches in `snippet`, or all of it when `re` doesn't match the snippet
+/// on its own, such as for a match cut short or found through an anchor
+fn redact_snippet(snippet: &str, re: Option<&regex::bytes::Regex>, redaction: Redaction) -> String {
+    let bytes = snippet.as_bytes();
+    let (mut out, mut last) = (String::new(), 0);
+    for m in re
+        .into_iter()
+        .flat_map(|re| re.find_iter(bytes))
+        .filter(|m| !m.as_bytes().is_empty())
+    {
+        out.push_str(&String::from_utf8_lossy(&bytes[last..m.start()]));
+        out.push_str(&redacted(&String::from_utf8_lossy(m.as_bytes()), redaction));
+        last = m.end();
+    }
+    if out.is_empty() {
+        return redacted(snippet, redaction);
+    }
+    out.push_str(&String::from_utf8_lossy(&bytes[last..]));
+    out
+}
+
+/// Keep the text `patterns` found out of the snippets of a content match, as `redaction`
+/// says
+pub fn redact_snippets(
+    m: &mut Match,
+    patterns: &Patterns<regex::bytes::Regex>,
+    redaction: Redaction,
+) {
+    let Some(found) = m
+        .details
+        .as_mut()
+        .and_then(|details| details.get_mut("matches"))
+        .and_then(serde_json::Value::as_array_mut)
+    else {
+        return;
+    };
+    let named = patterns.named("content");
+    for found in found
+        .iter_mut()
+        .filter_map(serde_json::Value::as_object_mut)
+    {
+        if redaction == Redaction::Drop {
+            found.remove("snippet");
+            continue;
+        }
+        let Some(snippet) = found.get("snippet").and_then(serde_json::Value::as_str) else {
+            continue;
+        };
+        let name = found
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("content");
+        let re = named
+            .iter()
+            .find(|(pattern, _)| *pattern == name)
+            .map(|(_, re)| *re);
+        let snippet = redact_snippet(snippet, re, redaction);
+        found.insert("snippet".to_string(), json!(snippet));
+    }
+}
+
+/// Redact what any of `patterns` finds in `text`, as `redaction` says. Dropping text
+/// found leaves its hash in its place, so the text around it still reads.
+#[must_use]
+pub fn redact_text(
+    text: &str,
+    patterns: &Patterns<regex::bytes::Regex>,
+    redaction: Redaction,
+) -> String {
+    patterns
+        .named("content")
+        .into_iter()
+        .fold(text.to_string(), |text, (_, re)| {
+            let bytes = text.as_bytes();
+            let (mut out, mut last) = (String::new(), 0);
+            for m in re.find_iter(bytes).filter(|m| !m.as_bytes().is_empty()) {
+                out.push_str(&String::from_utf8_lossy(&bytes[last..m.start()]));
+                out.push_str(&redacted(&String::from_utf8_lossy(m.as_bytes()), redaction));
+                last = m.end();
+            }
+            out.push_str(&String::from_utf8_lossy(&bytes[last..]));
+            out
+        })
+}
+
+/// stop recording literals found in a file after this many distinct ones
+const MAX_LITERALS: usize = 100;
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn literals_match(file: &File, opts: &LiteralsMatchOptions) -> Result<Option<Match>> {
+    let (automaton, literals) = opts.matcher()?;
+    let content = std::fs::read(file.content_path())?;
+
+    let mut found = BTreeSet::new();
+    for m in automaton.find_overlapping_iter(&content) {
+        found.insert(m.pattern());
+        if found.len() > MAX_LITERALS {
+            break;
+        }
+    }
+    let truncated = found.len() > MAX_LITERALS;
+    let found = found
+        .into_iter()
+        .take(MAX_LITERALS)
+        .map(|id| literals[id].as_str())
+        .collect::<Vec<_>>();
+
+    Ok(Some(Match {
+        is_match: !found.is_empty(),
+        on: file.abs_path.to_string(),
+        by: HashMap::from([("literals".to_string(), true)]),
+        details: (!found.is_empty()).then(|| json!({ "literals": found, "truncated": truncated })),
+        ..Default::default()
+    }))
+}
+
+/// stop recording offsets of a byte signature after this many
+const MAX_SIGNATURE_OFFSETS: usize = 100;
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn bytes_match(file: &File, sigs: &BTreeMap<String, ByteSignature>) -> Result<Option<Match>> {
+    // signatures anchored at an offset only need the start of the file
+    let needed = sigs
+        .values()
+        .map(|sig| {
+            sig.offset
+                .map(|offset| offset.saturating_add(sig.bytes.len()))
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|ends| ends.into_iter().max().unwrap_or_default());
+    let content = match needed {
+        Some(needed) => {
+            let mut content = vec![];
+            FsFile::open(file.content_path())?
+                .take(u64::try_from(needed)?)
+                .read_to_end(&mut content)?;
+            content
+        }
+        None => std::fs::read(file.content_path())?,
+    };
+
+    let mut by = HashMap::new();
+    let mut offsets = serde_json::Map::new();
+    for (name, sig) in sigs {
+        let starts = sig
+            .offset
+            .map_or(0..content.len(), |offset| offset..offset.saturating_add(1));
+        let found = starts
+            .filter(|at| sig.matches_at(&content, *at))
+            .take(MAX_SIGNATURE_OFFSETS)
+            .collect::<Vec<_>>();
+        by.insert(name.clone(), !found.is_empty());
+        if !found.is_empty() {
+            offsets.insert(name.clone(), json!(found));
+        }
+    }
+
+    Ok(Some(Match {
+        is_match: !offsets.is_empty(),
+        on: file.abs_path.to_string(),
+        by,
+        details: (!offsets.is_empty()).then(|| json!({ "offsets": offsets })),
+        ..Default::default()
+    }))
+}
+
+/// Number of line breaks in `bytes`
+fn count_lines(bytes: &[u8]) -> usize {
+    memchr::memchr_iter(b'\n', bytes).count()
+}
@@ -0,0 +1,344 @@
+use crate::data::{File, Match};
+use crate::db::Db;
+use crate::observer::Observer;
+use crate::workflow::stats_matches_sql;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of the file processing histogram, in seconds
+const FILE_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+/// Upper bounds of the buckets of the scan duration histogram, in seconds
+const SCAN_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0];
+
+/// Observations counted in buckets, as Prometheus histograms are
+struct Histogram {
+    bounds: &'static [f64],
+    /// observations of each bucket alone, cumulated when rendering
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        header(out, name, help, "histogram");
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _res = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let _res = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _res = writeln!(out, "{name}_sum {}", self.sum);
+        let _res = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _res = writeln!(out, "# HELP {name} {help}");
+    let _res = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn sample(out: &mut String, name: &str, help: &str, kind: &str, value: impl std::fmt::Display) {
+    header(out, name, help, kind);
+    let _res = writeln!(out, "{name} {value}");
+}
+
+/// Escape a label value, see the Prometheus text format
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn matches(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    kind: &str,
+    counts: &BTreeMap<(String, String), u64>,
+) {
+    header(out, name, help, kind);
+    for ((field, rule), count) in counts {
+        let _res = writeln!(
+            out,
+            "{name}{{field=\"{}\",rule=\"{}\"}} {count}",
+            label(field),
+            label(rule)
+        );
+    }
+}
+
+/// What was counted of a file, which is done once per pass over it: walking, computing
+/// fields, and each stage
+#[derive(Default)]
+struct Seen {
+    hashed: bool,
+    errored: bool,
+    matched: BTreeSet<(String, String)>,
+    process_ms: i64,
+}
+
+struct Counts {
+    /// files of the scan going on, by `abs_path`
+    seen: HashMap<String, Seen>,
+    files: u64,
+    bytes_hashed: u64,
+    /// by field and rule
+    matches: BTreeMap<(String, String), u64>,
+    scan_errors: u64,
+    field_errors: u64,
+    file_seconds: Histogram,
+    scan_seconds: Histogram,
+}
+
+impl Default for Counts {
+    fn default() -> Self {
+        Self {
+            seen: HashMap::new(),
+            files: 0,
+            bytes_hashed: 0,
+            matches: BTreeMap::new(),
+            scan_errors: 0,
+            field_errors: 0,
+            file_seconds: Histogram::new(FILE_BUCKETS),
+            scan_seconds: Histogram::new(SCAN_BUCKETS),
+        }
+    }
+}
+
+///
+/// Counts what scans went through, to be scraped by Prometheus while watching.
+/// See `render` for the metrics.
+///
+#[derive(Default)]
+pub struct Metrics {
+    counts: Mutex<Counts>,
+}
+
+impl Metrics {
+    /// A scan, or a batch of changes when watching, is over and took `elapsed`. Files seen
+    /// after are counted again.
+    pub fn scanned(&self, elapsed: Duration) {
+        if let Ok(mut counts) = self.counts.lock() {
+            counts.scan_seconds.observe(elapsed.as_secs_f64());
+            for (_, seen) in std::mem::take(&mut counts.seen) {
+                #[allow(clippy::cast_precision_loss)]
+                counts.file_seconds.observe(seen.process_ms as f64 / 1000.0);
+            }
+        }
+    }
+
+    /// The metrics in the Prometheus text format:
+    ///
+    /// * `recon_files_scanned_total`, `recon_bytes_hashed_total`
+    /// * `recon_matches_total` by `field` and `rule`
+    /// * `recon_scan_errors_total` for files that couldn't be scanned, and
+    ///   `recon_field_errors_total` for files with fields that failed
+    /// * `recon_file_process_seconds` and `recon_scan_duration_seconds` histograms
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Ok(counts) = self.counts.lock() else {
+            return out;
+        };
+        sample(
+            &mut out,
+            "recon_files_scanned_total",
+            "Files scanned",
+            "counter",
+            counts.files,
+        );
+        sample(
+            &mut out,
+            "recon_bytes_hashed_total",
+            "Bytes of files scanned with a digest",
+            "counter",
+            counts.bytes_hashed,
+        );
+        matches(
+            &mut out,
+            "recon_matches_total",
+            "Files matched, by field and rule",
+            "counter",
+            &counts.matches,
+        );
+        sample(
+            &mut out,
+            "recon_scan_errors_total",
+            "Files that could not be scanned",
+            "counter",
+            counts.scan_errors,
+        );
+        sample(
+            &mut out,
+            "recon_field_errors_total",
+            "Files scanned with fields that failed",
+            "counter",
+            counts.field_errors,
+        );
+        counts.file_seconds.render(
+            &mut out,
+            "recon_file_process_seconds",
+            "Time spent computing fields of a file",
+        );
+        counts.scan_seconds.render(
+            &mut out,
+            "recon_scan_duration_seconds",
+            "Duration of scans, and of updates while watching",
+        );
+        out
+    }
+}
+
+impl Observer for Metrics {
+    fn on_file_done(&self, file: &File) {
+        let Ok(mut counts) = self.counts.lock() else {
+            return;
+        };
+        let counts = &mut *counts;
+        if !counts.seen.contains_key(&file.abs_path) {
+            counts.files += 1;
+        }
+        let seen = counts.seen.entry(file.abs_path.clone()).or_default();
+        let hashed = file.crc32.is_some()
+            || file.md5.is_some()
+            || file.sha256.is_some()
+            || file.sha512.is_some();
+        if hashed && !seen.hashed {
+            seen.hashed = true;
+            counts.bytes_hashed += file
+                .size
+                .and_then(|size| u64::try_from(size).ok())
+                .unwrap_or_default();
+        }
+        let errored = file
+            .errors
+            .as_ref()
+            .is_some_and(|errors| !errors.is_empty());
+        if errored && !seen.errored {
+            seen.errored = true;
+            counts.field_errors += 1;
+        }
+        seen.process_ms = file.process_ms.unwrap_or_default();
+    }
+
+    fn on_match(&self, file: &File, field: &str, m: &Match) {
+        if !m.is_match {
+            return;
+        }
+        let Ok(mut counts) = self.counts.lock() else {
+            return;
+        };
+        let counts = &mut *counts;
+        let seen = counts.seen.entry(file.abs_path.clone()).or_default();
+        for (rule, _) in m.by.iter().filter(|(_, matched)| **matched) {
+            let key = (field.to_string(), rule.clone());
+            if seen.matched.insert(key.clone()) {
+                *counts.matches.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    fn on_error(&self, _path: &str, _error: &anyhow::Error) {
+        if let Ok(mut counts) = self.counts.lock() {
+            counts.scan_errors += 1;
+        }
+    }
+}
+
+const DB_TOTALS_SQL: &str = r"select count(*) as files,
+  coalesce(sum(case when coalesce(crc32, md5, sha256, sha512) is not null then size end), 0) as bytes_hashed,
+  (select count(*) from scan_errors where run_id is (select max(id) from runs)) as scan_errors
+from files";
+
+const DB_RUN_SECONDS_SQL: &str = r"select (julianday(finished_at) - julianday(started_at)) * 86400 as seconds
+from runs where finished_at is not null and partial is not 1";
+
+/// The state of a DB in the Prometheus text format, for serving it: gauges of what scans
+/// stored, in place of the counters of `Metrics`, and a histogram of the duration of
+/// finished runs
+///
+/// # Errors
+///
+/// This function will return an error on db failure
+pub(crate) async fn from_db(db: &Db) -> Result<String> {
+    let mut out = String::new();
+    let totals = db.query_table(DB_TOTALS_SQL).await?;
+    let total = |nth: usize| {
+        totals
+            .rows
+            .first()
+            .and_then(|row| row.get(nth))
+            .and_then(serde_json::Value::as_i64)
+            .unwrap_or_default()
+    };
+    sample(&mut out, "recon_files", "Files stored", "gauge", total(0));
+    sample(
+        &mut out,
+        "recon_bytes_hashed",
+        "Bytes of stored files with a digest",
+        "gauge",
+        total(1),
+    );
+    let per_rule = db
+        .query_table(&stats_matches_sql(&db.match_columns().await?))
+        .await?;
+    let counts = per_rule
+        .rows
+        .iter()
+        .filter_map(|row| match row.as_slice() {
+            [field, rule, files] => Some((
+                (field.as_str()?.to_string(), rule.as_str()?.to_string()),
+                files.as_u64()?,
+            )),
+            _ => None,
+        })
+        .collect();
+    matches(
+        &mut out,
+        "recon_matches",
+        "Files matched, by field and rule",
+        "gauge",
+        &counts,
+    );
+    sample(
+        &mut out,
+        "recon_scan_errors",
+        "Files the latest run could not scan",
+        "gauge",
+        total(2),
+    );
+    let mut scans = Histogram::new(SCAN_BUCKETS);
+    for row in db.query_table(DB_RUN_SECONDS_SQL).await?.rows {
+        if let Some(seconds) = row.first().and_then(serde_json::Value::as_f64) {
+            scans.observe(seconds);
+        }
+    }
+    scans.render(
+        &mut out,
+        "recon_scan_duration_seconds",
+        "Duration of finished scans",
+    );
+    Ok(out)
+}
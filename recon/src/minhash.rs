@@ -0,0 +1,163 @@
+//!
+//! `MinHash` signatures of text, estimating how similar two texts are by the share of
+//! word shingles they have in common (Jaccard similarity), and clustering files by them
+//! with locality sensitive hashing so not every pair has to be compared.
+//!
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// values in a signature, each the smallest hash of the shingles under one hash function
+pub const SIGNATURE_LEN: usize = 64;
+/// words per shingle
+const SHINGLE_WORDS: usize = 3;
+
+/// 64 bit FNV-1a
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Stable pseudo random numbers for the hash functions, so signatures can be compared
+/// across runs and versions
+const fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The signature of a text, `None` when it has no words. Words are runs of letters and
+/// digits, compared case insensitively, so formatting doesn't count.
+#[must_use]
+pub fn signature(text: &str) -> Option<Vec<u32>> {
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return None;
+    }
+    // a text shorter than a shingle is a shingle of its own
+    let shingles = words
+        .windows(SHINGLE_WORDS.min(words.len()))
+        .map(|shingle| fnv1a(shingle.join(" ").into_bytes()));
+
+    let mut sig = vec![u32::MAX; SIGNATURE_LEN];
+    let seeds = (0..SIGNATURE_LEN as u64)
+        .map(|i| (splitmix64(2 * i) | 1, splitmix64(2 * i + 1)))
+        .collect::<Vec<_>>();
+    for shingle in shingles {
+        for (min, (a, b)) in sig.iter_mut().zip(&seeds) {
+            // the high bits are the well mixed ones
+            let h = (a.wrapping_mul(shingle).wrapping_add(*b) >> 32) as u32;
+            *min = (*min).min(h);
+        }
+    }
+    Some(sig)
+}
+
+/// A signature as hex, to store
+#[must_use]
+pub fn to_hex(sig: &[u32]) -> String {
+    sig.iter().fold(String::new(), |mut hex, v| {
+        let _res = write!(hex, "{:08x}", v);
+        hex
+    })
+}
+
+/// A signature back from hex, `None` unless it's a whole one
+#[must_use]
+pub fn from_hex(hex: &str) -> Option<Vec<u32>> {
+    if hex.len() != SIGNATURE_LEN * 8 || !hex.is_ascii() {
+        return None;
+    }
+    (0..SIGNATURE_LEN)
+        .map(|i| u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16).ok())
+        .collect()
+}
+
+/// Estimated Jaccard similarity of the texts behind two signatures, from 0 to 1
+#[must_use]
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let same = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    #[allow(clippy::cast_precision_loss)]
+    let similarity = same as f64 / SIGNATURE_LEN as f64;
+    similarity
+}
+
+/// Values per band for locality sensitive hashing: the most that still puts a pair as
+/// similar as `threshold` in a shared band 95% of the time. More values per band means
+/// fewer pairs to compare.
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn band_rows(threshold: f64) -> usize {
+    [64, 32, 16, 8, 4, 2]
+        .into_iter()
+        .find(|&rows| {
+            let bands = (SIGNATURE_LEN / rows) as i32;
+            let rows = rows as i32;
+            1.0 - (1.0 - threshold.powi(rows)).powi(bands) >= 0.95
+        })
+        .unwrap_or(1)
+}
+
+/// The set `i` is in, as the item representing it
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Sets of at least two items whose signatures are at least `threshold` similar,
+/// transitively, each with the lowest similarity among the pairs that joined it.
+/// Items are given by index, in order within a set.
+#[must_use]
+pub fn clusters(signatures: &[Vec<u32>], threshold: f64) -> Vec<(Vec<usize>, f64)> {
+    let rows = band_rows(threshold);
+    let mut buckets: BTreeMap<(usize, &[u32]), Vec<usize>> = BTreeMap::new();
+    for (i, sig) in signatures.iter().enumerate() {
+        for (band, values) in sig.chunks(rows).enumerate() {
+            buckets.entry((band, values)).or_default().push(i);
+        }
+    }
+
+    // union-find over the items, joining pairs that share a band and are similar enough
+    let mut parent = (0..signatures.len()).collect::<Vec<_>>();
+    let mut lowest: HashMap<usize, f64> = HashMap::new();
+    for items in buckets.values().filter(|items| items.len() > 1) {
+        for (n, &a) in items.iter().enumerate() {
+            for &b in &items[n + 1..] {
+                let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                if ra == rb {
+                    continue;
+                }
+                let sim = similarity(&signatures[a], &signatures[b]);
+                if sim < threshold {
+                    continue;
+                }
+                let low = [lowest.remove(&ra), lowest.remove(&rb)]
+                    .into_iter()
+                    .flatten()
+                    .fold(sim, f64::min);
+                parent[rb] = ra;
+                lowest.insert(ra, low);
+            }
+        }
+    }
+
+    let mut sets: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..signatures.len() {
+        let r = root(&mut parent, i);
+        sets.entry(r).or_default().push(i);
+    }
+    let mut sets = sets
+        .into_iter()
+        .filter(|(_, items)| items.len() > 1)
+        .map(|(r, items)| (items, lowest.get(&r).copied().unwrap_or(1.0)))
+        .collect::<Vec<_>>();
+    sets.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+    sets
+}
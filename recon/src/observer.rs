@@ -0,0 +1,266 @@
+use crate::data::{File, Match};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where a scan is at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// walking files: `files` seen so far, of which `cached` were already stored and unchanged
+    Walking { files: u64, cached: u64 },
+    /// computing fields: `done` of `total` files
+    Computing { done: u64, total: u64 },
+    /// the current stage is over
+    Finished,
+}
+
+///
+/// Receives the events of a scan, to show progress or act on matches as they come.
+/// Callbacks do nothing unless implemented.
+///
+pub trait Observer: Send + Sync {
+    /// A file is about to be processed
+    fn on_file_start(&self, _path: &str) {}
+
+    /// A file was processed and stored. Fields that failed on it are in its `errors`
+    fn on_file_done(&self, _file: &File) {}
+
+    /// A processed file matched, in the `field` column
+    fn on_match(&self, _file: &File, _field: &str, _m: &Match) {}
+
+    /// A file couldn't be scanned, and was recorded in `scan_errors`
+    fn on_error(&self, _path: &str, _error: &anyhow::Error) {}
+
+    /// The scan moved on
+    fn on_progress(&self, _progress: Progress) {}
+
+    /// Something worth telling about the run which doesn't stop it, such as an expired
+    /// suppression or files that couldn't be scanned
+    fn on_warning(&self, _message: &str) {}
+}
+
+/// Shared observers, so their owner can look at what they collected while or after scanning
+impl<T: Observer + ?Sized> Observer for Arc<T> {
+    fn on_file_start(&self, path: &str) {
+        (**self).on_file_start(path);
+    }
+
+    fn on_file_done(&self, file: &File) {
+        (**self).on_file_done(file);
+    }
+
+    fn on_match(&self, file: &File, field: &str, m: &Match) {
+        (**self).on_match(file, field, m);
+    }
+
+    fn on_error(&self, path: &str, error: &anyhow::Error) {
+        (**self).on_error(path, error);
+    }
+
+    fn on_progress(&self, progress: Progress) {
+        (**self).on_progress(progress);
+    }
+
+    fn on_warning(&self, message: &str) {
+        (**self).on_warning(message);
+    }
+}
+
+///
+/// Ignores all events
+///
+pub struct Silent;
+
+impl Observer for Silent {}
+
+///
+/// Shows progress on the terminal: a spinner while walking, and a bar while computing fields.
+/// Warnings are printed to stderr, above the bar
+///
+#[derive(Default)]
+pub struct Spinner {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl Observer for Spinner {
+    fn on_progress(&self, progress: Progress) {
+        let Ok(mut bar) = self.bar.lock() else {
+            return;
+        };
+        match progress {
+            Progress::Walking { files, cached } => {
+                let pb = bar.get_or_insert_with(|| {
+                    let pb = ProgressBar::new_spinner();
+                    pb.set_style(
+                        ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}")
+                            .unwrap(),
+                    );
+                    pb.enable_steady_tick(Duration::from_millis(120));
+                    pb
+                });
+                if cached > 0 {
+                    pb.set_message(format!("{} files ({} cached)", files, cached));
+                } else {
+                    pb.set_message(format!("{} files", files));
+                }
+            }
+            Progress::Computing { done, total } => {
+                let pb = bar.get_or_insert_with(|| {
+                    let pb = ProgressBar::new(total);
+                    pb.set_style(
+                        ProgressStyle::with_template(
+                            "[{elapsed_precise}] {bar:16.cyan/blue} {pos:>7}/{len:7} {msg}",
+                        )
+                        .unwrap(),
+                    );
+                    pb.set_message("Computing fields");
+                    pb
+                });
+                pb.set_position(done);
+            }
+            Progress::Finished => {
+                if let Some(pb) = bar.take() {
+                    pb.finish_and_clear();
+                }
+            }
+        }
+    }
+
+    fn on_warning(&self, message: &str) {
+        let bar = self.bar.lock().ok().and_then(|bar| bar.clone());
+        match bar {
+            Some(pb) => pb.suspend(|| eprintln!("{}", message)),
+            None => eprintln!("{}", message),
+        }
+    }
+}
+
+///
+/// Writes progress to stderr as JSON lines, for CI systems and wrappers
+///
+/// Files walked or computed so far, with the rate and the file at hand, are written at
+/// most once an `interval`, along with every error and the end of every stage.
+///
+pub struct JsonProgress {
+    interval: Duration,
+    state: Mutex<JsonState>,
+}
+
+struct JsonState {
+    started: Instant,
+    /// when the current stage started
+    stage: Instant,
+    /// when progress was last written, in this stage
+    last: Option<Instant>,
+    /// progress held back since, written once the stage finishes
+    pending: Option<serde_json::Value>,
+    path: Option<String>,
+    errors: u64,
+}
+
+impl Default for JsonProgress {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+impl JsonProgress {
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            state: Mutex::new(JsonState {
+                started: now,
+                stage: now,
+                last: None,
+                pending: None,
+                path: None,
+                errors: 0,
+            }),
+        }
+    }
+
+    fn write(event: &serde_json::Value) {
+        let _res = writeln!(io::stderr().lock(), "{}", event);
+    }
+}
+
+impl Observer for JsonProgress {
+    fn on_file_start(&self, path: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.path = Some(path.to_string());
+        }
+    }
+
+    fn on_error(&self, path: &str, error: &anyhow::Error) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.errors += 1;
+        Self::write(&json!({
+            "event": "error",
+            "path": path,
+            "error": format!("{:#}", error),
+            "errors": state.errors,
+        }));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn on_progress(&self, progress: Progress) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed_ms =
+            u64::try_from(now.duration_since(state.started).as_millis()).unwrap_or(u64::MAX);
+        let (mut event, count, last) = match progress {
+            Progress::Walking { files, cached } => (
+                json!({ "event": "walking", "files": files, "cached": cached }),
+                files,
+                false,
+            ),
+            Progress::Computing { done, total } => (
+                json!({ "event": "computing", "done": done, "total": total }),
+                done,
+                done == total,
+            ),
+            Progress::Finished => {
+                if let Some(pending) = state.pending.take() {
+                    Self::write(&pending);
+                }
+                Self::write(&json!({
+                    "event": "finished",
+                    "errors": state.errors,
+                    "elapsed_ms": elapsed_ms,
+                }));
+                state.stage = now;
+                state.last = None;
+                return;
+            }
+        };
+        let secs = now.duration_since(state.stage).as_secs_f64();
+        let rate = if secs > 0.0 { count as f64 / secs } else { 0.0 };
+        event["errors"] = state.errors.into();
+        event["path"] = state.path.clone().into();
+        event["elapsed_ms"] = elapsed_ms.into();
+        event["rate"] = ((rate * 10.0).round() / 10.0).into();
+        if !last
+            && state
+                .last
+                .is_some_and(|at| now.duration_since(at) < self.interval)
+        {
+            state.pending = Some(event);
+            return;
+        }
+        state.last = Some(now);
+        state.pending = None;
+        Self::write(&event);
+    }
+
+    fn on_warning(&self, message: &str) {
+        Self::write(&json!({ "event": "warning", "message": message }));
+    }
+}
@@ -1,9 +1,16 @@
 use chrono::{DateTime, Local, Utc};
-use std::{fs::Metadata, io, time::SystemTime};
+use lazy_static::lazy_static;
+use std::{fs::Metadata, io, path::Path, time::SystemTime};
+
+lazy_static! {
+    /// Mount points and their filesystem types, read once per process
+    static ref MOUNTS: Vec<(String, String)> = mounts();
+}
 
 #[cfg(unix)]
 #[must_use]
 pub fn user_and_group(
+    _path: &Path,
     meta: &Metadata,
 ) -> (Option<String>, Option<String>, Option<u32>, Option<u32>) {
     use std::os::unix::fs::MetadataExt;
@@ -17,12 +24,178 @@ pub fn user_and_group(
     (user, group, Some(uid), Some(gid))
 }
 
-#[cfg(not(unix))]
+/// The owner and group from the file's security descriptor, as `DOMAIN\name`. There are
+/// no numeric ids.
+#[cfg(windows)]
+#[must_use]
+pub fn user_and_group(
+    path: &Path,
+    _meta: &Metadata,
+) -> (Option<String>, Option<String>, Option<u32>, Option<u32>) {
+    use windows_sys::Win32::Foundation::{LocalFree, ERROR_SUCCESS, PSID};
+    use windows_sys::Win32::Security::Authorization::{GetNamedSecurityInfoW, SE_FILE_OBJECT};
+    use windows_sys::Win32::Security::{
+        GROUP_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+    };
+
+    let path = wide(path);
+    let mut owner: PSID = std::ptr::null_mut();
+    let mut group: PSID = std::ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+    // SAFETY: the path is NUL terminated, and the SIDs point into the descriptor, which
+    // is freed only once they're looked up
+    unsafe {
+        let res = GetNamedSecurityInfoW(
+            path.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION,
+            &mut owner,
+            &mut group,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut descriptor,
+        );
+        if res != ERROR_SUCCESS {
+            return (None, None, None, None);
+        }
+        let names = (account_name(owner), account_name(group));
+        LocalFree(descriptor);
+        (names.0, names.1, None, None)
+    }
+}
+
+/// The account a SID stands for, `None` when it can't be looked up (e.g. a deleted user)
+///
+/// # Safety
+///
+/// `sid` must be null or point to a valid SID
+#[cfg(windows)]
+unsafe fn account_name(sid: windows_sys::Win32::Foundation::PSID) -> Option<String> {
+    use windows_sys::Win32::Security::{LookupAccountSidW, SID_NAME_USE};
+
+    if sid.is_null() {
+        return None;
+    }
+    let (mut name, mut domain) = ([0u16; 256], [0u16; 256]);
+    let (mut name_len, mut domain_len) = (256u32, 256u32);
+    let mut kind: SID_NAME_USE = 0;
+    let found = LookupAccountSidW(
+        std::ptr::null(),
+        sid,
+        name.as_mut_ptr(),
+        &mut name_len,
+        domain.as_mut_ptr(),
+        &mut domain_len,
+        &mut kind,
+    );
+    if found == 0 {
+        return None;
+    }
+    let name = String::from_utf16_lossy(&name[..name_len as usize]);
+    let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+    Some(if domain.is_empty() {
+        name
+    } else {
+        format!("{}\\{}", domain, name)
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
 #[must_use]
-pub fn user_and_group(m: &Metadata) -> (Option<String>, Option<String>, Option<u32>, Option<u32>) {
+pub fn user_and_group(
+    _path: &Path,
+    _meta: &Metadata,
+) -> (Option<String>, Option<String>, Option<u32>, Option<u32>) {
     (None, None, None, None)
 }
 
+/// A path as a NUL terminated wide string, for Windows APIs
+#[cfg(windows)]
+fn wide(path: &Path) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str().encode_wide().chain(Some(0)).collect()
+}
+
+/// Whether a file is hidden and whether it's a system file, by its attributes
+#[cfg(windows)]
+#[must_use]
+pub fn hidden_and_system(_path: &Path, m: &Metadata) -> (Option<bool>, Option<bool>) {
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM};
+
+    let attributes = m.file_attributes();
+    (
+        Some(attributes & FILE_ATTRIBUTE_HIDDEN != 0),
+        Some(attributes & FILE_ATTRIBUTE_SYSTEM != 0),
+    )
+}
+
+/// Whether a file is hidden, by a leading dot in its name. There are no system files.
+#[cfg(not(windows))]
+#[must_use]
+pub fn hidden_and_system(path: &Path, _m: &Metadata) -> (Option<bool>, Option<bool>) {
+    let is_hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    (Some(is_hidden), None)
+}
+
+/// Alternate data streams of a file, as (name, size), without the unnamed one that's
+/// the content itself. `None` when they can't be listed.
+#[cfg(windows)]
+#[must_use]
+pub fn data_streams(path: &Path) -> Option<Vec<(String, u64)>> {
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    let path = wide(path);
+    let mut streams = vec![];
+    // SAFETY: the path is NUL terminated, a zeroed WIN32_FIND_STREAM_DATA is valid, and
+    // the handle is closed once done
+    unsafe {
+        let mut data: WIN32_FIND_STREAM_DATA = std::mem::zeroed();
+        let handle = FindFirstStreamW(
+            path.as_ptr(),
+            FindStreamInfoStandard,
+            std::ptr::addr_of_mut!(data).cast(),
+            0,
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        loop {
+            let len = data
+                .cStreamName
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(data.cStreamName.len());
+            // named `:name:$DATA`, and the content is `::$DATA`
+            let name = String::from_utf16_lossy(&data.cStreamName[..len]);
+            let name = name.trim_start_matches(':').trim_end_matches(":$DATA");
+            if !name.is_empty() && name != "$DATA" {
+                streams.push((
+                    name.to_string(),
+                    u64::try_from(data.StreamSize).unwrap_or(0),
+                ));
+            }
+            if FindNextStreamW(handle, std::ptr::addr_of_mut!(data).cast()) == 0 {
+                break;
+            }
+        }
+        FindClose(handle);
+    }
+    Some(streams)
+}
+
+#[cfg(not(windows))]
+#[must_use]
+pub const fn data_streams(_path: &Path) -> Option<Vec<(String, u64)>> {
+    None
+}
+
 pub fn ftime(t: io::Result<SystemTime>) -> Option<DateTime<Utc>> {
     t.ok()
         .map(DateTime::<Local>::from)
@@ -41,3 +214,83 @@ pub fn mode(m: &Metadata) -> u32 {
 pub fn mode(_m: &Metadata) -> u32 {
     0
 }
+
+#[cfg(unix)]
+#[must_use]
+pub fn inode(m: &Metadata) -> Option<i64> {
+    use std::os::unix::prelude::MetadataExt;
+    m.ino().try_into().ok()
+}
+
+#[cfg(not(unix))]
+#[must_use]
+pub fn inode(_m: &Metadata) -> Option<i64> {
+    None
+}
+
+/// Mounts from `/proc/self/mountinfo`, as (mount point, filesystem type), in the order
+/// they were mounted
+#[cfg(target_os = "linux")]
+fn mounts() -> Vec<(String, String)> {
+    // `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw`
+    let unescape = |field: &str| {
+        field
+            .replace("\\040", " ")
+            .replace("\\011", "\t")
+            .replace("\\012", "\n")
+            .replace("\\134", "\\")
+    };
+    std::fs::read_to_string("/proc/self/mountinfo")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            Some((
+                unescape(mount.split(' ').nth(4)?),
+                fs.split(' ').next()?.to_string(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mounts() -> Vec<(String, String)> {
+    vec![]
+}
+
+/// The mount point `abs_path` is under, and the type of its filesystem. A later mount
+/// over the same point hides the earlier one.
+#[must_use]
+pub fn mount(abs_path: &Path) -> Option<(String, String)> {
+    MOUNTS
+        .iter()
+        .filter(|(mount_point, _)| abs_path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .cloned()
+}
+
+#[cfg(unix)]
+#[must_use]
+pub fn dev(m: &Metadata) -> Option<i64> {
+    use std::os::unix::prelude::MetadataExt;
+    m.dev().try_into().ok()
+}
+
+#[cfg(not(unix))]
+#[must_use]
+pub fn dev(_m: &Metadata) -> Option<i64> {
+    None
+}
+
+#[cfg(unix)]
+#[must_use]
+pub fn nlink(m: &Metadata) -> Option<i64> {
+    use std::os::unix::prelude::MetadataExt;
+    m.nlink().try_into().ok()
+}
+
+#[cfg(not(unix))]
+#[must_use]
+pub fn nlink(_m: &Metadata) -> Option<i64> {
+    None
+}
@@ -0,0 +1,88 @@
+use crate::config::OsvVulnsOptions;
+use crate::data::{OsvVuln, Package};
+use crate::db::Db;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use sqlx::types::Json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// most queries OSV takes in one batch
+const MAX_BATCH: usize = 1000;
+
+/// Known vulnerability ids of each package, asked in batches
+async fn vulns(
+    opts: &OsvVulnsOptions,
+    packages: &BTreeSet<&Package>,
+) -> Result<BTreeMap<Package, Vec<String>>> {
+    let client = reqwest::Client::new();
+    let mut found = BTreeMap::new();
+    let packages = packages.iter().collect::<Vec<_>>();
+    for batch in packages.chunks(MAX_BATCH) {
+        let queries = batch
+            .iter()
+            .map(|p| {
+                json!({
+                    "package": { "name": p.name, "ecosystem": p.ecosystem },
+                    "version": p.version,
+                })
+            })
+            .collect::<Vec<_>>();
+        let res = client
+            .post(format!("{}/querybatch", opts.url().trim_end_matches('/')))
+            .json(&json!({ "queries": queries }))
+            .send()
+            .await
+            .context("cannot reach OSV")?;
+        if !res.status().is_success() {
+            bail!("OSV query failed: {}", res.status());
+        }
+        let res = res.json::<Value>().await?;
+        let results = res["results"].as_array().map_or(&[][..], Vec::as_slice);
+        for (package, result) in batch.iter().zip(results) {
+            let ids = result["vulns"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v["id"].as_str().map(ToString::to_string))
+                .collect::<Vec<_>>();
+            if !ids.is_empty() {
+                found.insert((**package).clone(), ids);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Look up the packages of manifests without `osv_vulns` yet, and store what's found
+/// (an empty list when nothing is). Returns how many files were looked up.
+///
+/// # Errors
+///
+/// This function will return an error on db or lookup failure
+pub async fn lookup_and_store(opts: &OsvVulnsOptions, db: &Db) -> Result<usize> {
+    let missing = db.missing_osv_vulns().await?;
+    if missing.is_empty() {
+        return Ok(0);
+    }
+    let packages = missing
+        .iter()
+        .flat_map(|(_, packages)| packages.iter())
+        .collect::<BTreeSet<_>>();
+    let found = vulns(opts, &packages).await?;
+
+    for (abs_path, packages) in &missing {
+        let vulns = packages
+            .iter()
+            .filter_map(|p| {
+                found.get(p).map(|ids| OsvVuln {
+                    ecosystem: p.ecosystem.clone(),
+                    package: p.name.clone(),
+                    version: p.version.clone(),
+                    ids: ids.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        db.store_osv_vulns(abs_path, &Json(vulns)).await?;
+    }
+    Ok(missing.len())
+}
@@ -1,6 +1,10 @@
-use crate::data::ValuesTable;
+use crate::data::{Match, Package, ValuesTable};
 use anyhow::{Context, Result};
-use csv::Writer;
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use csv::{QuoteStyle, WriterBuilder};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
 
 /// Represent a value as string
 fn repr(col: &serde_json::Value) -> String {
@@ -8,13 +12,69 @@ fn repr(col: &serde_json::Value) -> String {
         .map_or_else(|| col.to_string(), ToString::to_string)
 }
 
-/// Export as csv
+///
+/// How values are quoted in CSV
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvQuote {
+    /// only values with delimiters, quotes or newlines
+    #[default]
+    Necessary,
+    Always,
+    /// all but numbers
+    NonNumeric,
+    /// no quoting, values with delimiters break the record
+    Never,
+}
+
+///
+/// Options for CSV export
+///
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: CsvQuote,
+    /// column names as the first record
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: CsvQuote::default(),
+            header: true,
+        }
+    }
+}
+
+/// Export as csv, comma delimited with a header
 ///
 /// # Errors
 ///
 /// This function will return an error on I/O failure
 pub fn to_csv(vt: &ValuesTable) -> Result<String> {
-    let mut wtr = Writer::from_writer(vec![]);
+    to_csv_with(vt, &CsvOptions::default())
+}
+
+/// Export as csv, with the given delimiter, quoting and header
+///
+/// # Errors
+///
+/// This function will return an error on I/O failure
+pub fn to_csv_with(vt: &ValuesTable, opts: &CsvOptions) -> Result<String> {
+    let mut wtr = WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .quote_style(match opts.quote {
+            CsvQuote::Necessary => QuoteStyle::Necessary,
+            CsvQuote::Always => QuoteStyle::Always,
+            CsvQuote::NonNumeric => QuoteStyle::NonNumeric,
+            CsvQuote::Never => QuoteStyle::Never,
+        })
+        .from_writer(vec![]);
+    if opts.header && !vt.columns.is_empty() {
+        wtr.write_record(&vt.columns)?;
+    }
     for row in &vt.rows {
         wtr.write_record(row.iter().map(repr))?;
     }
@@ -31,6 +91,37 @@ pub fn to_json(vt: &ValuesTable) -> Result<String> {
     Ok(format!("{}\n", r))
 }
 
+/// Export as JSON lines, an object of column values per row
+///
+/// # Errors
+///
+/// This function will return an error on serialization failure
+pub fn to_jsonl(vt: &ValuesTable) -> Result<String> {
+    let mut out = String::new();
+    for row in &vt.rows {
+        out.push_str(&jsonl_line(&vt.columns, row.iter().cloned())?);
+    }
+    Ok(out)
+}
+
+/// A row as a JSON object of column values, on a line of its own
+///
+/// # Errors
+///
+/// This function will return an error on serialization failure
+pub(crate) fn jsonl_line(
+    columns: &[String],
+    row: impl IntoIterator<Item = serde_json::Value>,
+) -> Result<String> {
+    let record = columns
+        .iter()
+        .cloned()
+        .zip(row)
+        .collect::<serde_json::Map<_, _>>();
+    let line = serde_json::to_string(&record).context("could not convert to json")?;
+    Ok(format!("{}\n", line))
+}
+
 /// Returns an ASCII drawn table
 ///
 /// # Errors
@@ -50,6 +141,99 @@ pub fn to_table(vt: &ValuesTable) -> Result<String> {
     Ok(format!("{}\n", table))
 }
 
+/// Longest path shown by a human table, longer ones keep their end
+const HUMAN_PATH_LEN: usize = 48;
+/// Hex digits shown of a hash by a human table
+const HUMAN_HASH_LEN: usize = 12;
+
+/// Sizes in binary units, e.g. `12.3 MiB`
+fn human_size(size: i64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    #[allow(clippy::cast_precision_loss)]
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// A timestamp relative to now, e.g. `3 hours ago`
+fn human_time(time: DateTime<FixedOffset>) -> String {
+    let delta = Utc::now().signed_duration_since(time);
+    let secs = delta.num_seconds().abs();
+    let (n, unit) = match secs {
+        0..=59 => (secs, "second"),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86_399 => (secs / 3600, "hour"),
+        86_400..=2_591_999 => (secs / 86_400, "day"),
+        2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    if delta.num_seconds() < 0 {
+        format!("in {} {}{}", n, unit, plural)
+    } else {
+        format!("{} {}{} ago", n, unit, plural)
+    }
+}
+
+/// Represent a value for people: sizes in units, timestamps as relative time, and long
+/// hashes and paths shortened
+fn human_repr(column: &str, col: &serde_json::Value) -> String {
+    if let (true, Some(size)) = (column == "size" || column.ends_with("_size"), col.as_i64()) {
+        return human_size(size);
+    }
+    let Some(text) = col.as_str() else {
+        return repr(col);
+    };
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return human_time(time);
+    }
+    if text.len() > HUMAN_HASH_LEN * 2 && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return format!("{}…", &text[..HUMAN_HASH_LEN]);
+    }
+    let chars = text.chars().count();
+    if (column == "path" || column.ends_with("_path") || column == "parent_archive")
+        && chars > HUMAN_PATH_LEN
+    {
+        let tail = text
+            .chars()
+            .skip(chars - HUMAN_PATH_LEN + 1)
+            .collect::<String>();
+        return format!("…{}", tail);
+    }
+    text.to_string()
+}
+
+/// Returns an ASCII drawn table with values for people (see `human_repr`)
+///
+/// # Errors
+///
+/// This function is not expected to error, just conforms to an interface
+pub fn to_human_table(vt: &ValuesTable) -> Result<String> {
+    let mut builder = tabled::builder::Builder::default();
+
+    builder.set_columns(&vt.columns);
+    for row in &vt.rows {
+        builder.add_record(
+            vt.columns
+                .iter()
+                .zip(row)
+                .map(|(column, col)| human_repr(column, col)),
+        );
+    }
+
+    let mut table = builder.build();
+    table.with(tabled::Style::modern());
+    Ok(format!("{}\n", table))
+}
+
 /// Convert to a xargs-friendly format (a newline separated list of values)
 ///
 /// # Errors
@@ -65,3 +249,365 @@ pub fn to_xargs(vt: &ValuesTable) -> Result<String> {
             .join("\n")
     ))
 }
+
+/// Convert to a `xargs -0` friendly format (NUL terminated values), safe for values
+/// with spaces or newlines
+///
+/// # Errors
+///
+/// This function is not expected to error, just conforms to an interface
+pub fn to_xargs0(vt: &ValuesTable) -> Result<String> {
+    let mut out = String::new();
+    for row in &vt.rows {
+        out.push_str(&row.first().map(repr).unwrap_or_default());
+        out.push('\0');
+    }
+    Ok(out)
+}
+
+///
+/// A standard SBOM document format
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    CycloneDx,
+    Spdx,
+}
+
+/// Digest columns, with their `CycloneDX` and SPDX algorithm names
+const SBOM_DIGESTS: &[(&str, &str, &str)] = &[
+    ("sha256", "SHA-256", "SHA256"),
+    ("sha512", "SHA-512", "SHA512"),
+    ("md5", "MD5", "MD5"),
+];
+
+/// A file digest: its `CycloneDX` and SPDX algorithm names, and value
+type SbomHash<'a> = (&'a str, &'a str, String);
+
+/// Files (by `path`, with their digests) and packages (from `packages`) in query results
+struct SbomItems<'a> {
+    files: Vec<(String, Vec<SbomHash<'a>>)>,
+    packages: BTreeMap<String, Package>,
+}
+
+impl<'a> SbomItems<'a> {
+    fn collect(vt: &'a ValuesTable) -> Self {
+        let col = |name: &str| vt.columns.iter().position(|c| c == name);
+        let path = col("path");
+        let digests = SBOM_DIGESTS
+            .iter()
+            .filter_map(|(name, cdx, spdx)| col(name).map(|i| (i, *cdx, *spdx)))
+            .collect::<Vec<_>>();
+        let packages_col = col("packages");
+
+        let mut files = vec![];
+        let mut packages = BTreeMap::new();
+        for row in &vt.rows {
+            if let Some(path) = path.and_then(|i| row[i].as_str()) {
+                let hashes = digests
+                    .iter()
+                    .filter_map(|(i, cdx, spdx)| {
+                        row[*i].as_str().map(|d| (*cdx, *spdx, d.to_string()))
+                    })
+                    .collect();
+                files.push((path.to_string(), hashes));
+            }
+            // JSON columns come as text
+            let found = packages_col
+                .and_then(|i| row[i].as_str())
+                .and_then(|text| serde_json::from_str::<Vec<Package>>(text).ok());
+            for package in found.into_iter().flatten() {
+                packages.insert(purl(&package), package);
+            }
+        }
+        Self { files, packages }
+    }
+}
+
+/// Package URL (<https://github.com/package-url/purl-spec>) of a package
+fn purl(p: &Package) -> String {
+    match p.ecosystem.as_str() {
+        "crates.io" => format!("pkg:cargo/{}@{}", p.name, p.version),
+        "npm" => format!("pkg:npm/{}@{}", p.name.replace('@', "%40"), p.version),
+        "PyPI" => format!("pkg:pypi/{}@{}", p.name.to_lowercase(), p.version),
+        "Go" => format!("pkg:golang/{}@v{}", p.name, p.version),
+        other => format!("pkg:generic/{}@{}?ecosystem={}", p.name, p.version, other),
+    }
+}
+
+/// Export files and packages in query results as an SBOM
+///
+/// # Errors
+///
+/// This function will return an error on serialization failure
+pub fn to_sbom(vt: &ValuesTable, format: SbomFormat) -> Result<String> {
+    let items = SbomItems::collect(vt);
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let version = env!("CARGO_PKG_VERSION");
+    let doc = match format {
+        SbomFormat::CycloneDx => json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": now,
+                "tools": [{ "vendor": "rusty-ferris-club", "name": "recon", "version": version }],
+            },
+            "components": items.files.iter().map(|(path, hashes)| json!({
+                "type": "file",
+                "bom-ref": format!("file:{}", path),
+                "name": path,
+                "hashes": hashes.iter().map(|(alg, _, content)| json!({ "alg": alg, "content": content })).collect::<Vec<_>>(),
+            })).chain(items.packages.iter().map(|(purl, p)| json!({
+                "type": "library",
+                "bom-ref": purl,
+                "name": p.name,
+                "version": p.version,
+                "purl": purl,
+            }))).collect::<Vec<_>>(),
+        }),
+        SbomFormat::Spdx => json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": "recon",
+            "documentNamespace": format!("https://spdx.org/spdxdocs/recon-{}", now),
+            "creationInfo": { "created": now, "creators": [format!("Tool: recon-{}", version)] },
+            "files": items.files.iter().enumerate().map(|(i, (path, hashes))| json!({
+                "fileName": path,
+                "SPDXID": format!("SPDXRef-File-{}", i + 1),
+                "checksums": hashes.iter().map(|(_, alg, value)| json!({ "algorithm": alg, "checksumValue": value })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "packages": items.packages.iter().enumerate().map(|(i, (purl, p))| json!({
+                "name": p.name,
+                "SPDXID": format!("SPDXRef-Package-{}", i + 1),
+                "versionInfo": p.version,
+                "downloadLocation": "NOASSERTION",
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": purl,
+                }],
+            })).collect::<Vec<_>>(),
+        }),
+    };
+    let r = serde_json::to_string_pretty(&doc).context("could not convert to sbom")?;
+    Ok(format!("{}\n", r))
+}
+
+/// Escape text for HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Most files listed as largest in an HTML report
+const HTML_LARGEST: usize = 10;
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#f0f0f0}td{font-family:monospace;white-space:pre-wrap;word-break:break-all}\
+input{padding:4px;width:30em;margin-bottom:1em}";
+
+const HTML_FILTER: &str = "document.getElementById('filter').addEventListener('input',e=>{\
+const q=e.target.value.toLowerCase();\
+for(const tr of document.querySelectorAll('#results tbody tr')){\
+tr.style.display=tr.textContent.toLowerCase().includes(q)?'':'none'}})";
+
+/// Count matching files per `*_match` column and per what matched (`by`)
+fn match_breakdown(vt: &ValuesTable) -> BTreeMap<(String, String), usize> {
+    let mut counts = BTreeMap::new();
+    for (i, col) in vt.columns.iter().enumerate() {
+        if !col.ends_with("_match") {
+            continue;
+        }
+        for row in &vt.rows {
+            // JSON columns come as text
+            let Some(m) = row[i]
+                .as_str()
+                .and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok())
+            else {
+                continue;
+            };
+            if m["is_match"] != true {
+                continue;
+            }
+            for (by, matched) in m["by"].as_object().into_iter().flatten() {
+                if *matched == true {
+                    *counts.entry((col.clone(), by.clone())).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+    counts
+}
+
+/// Export as a standalone HTML report: summary counts, match breakdown, largest files and a
+/// filterable results table
+///
+/// # Errors
+///
+/// This function is not expected to error, just conforms to an interface
+pub fn to_html(vt: &ValuesTable) -> Result<String> {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>recon report</title>\n\
+         <style>{HTML_STYLE}</style>\n</head>\n<body>\n<h1>recon report</h1>\n\
+         <p>{} of {} files, {}</p>\n",
+        vt.rows.len(),
+        vt.total_rows,
+        now
+    );
+
+    let breakdown = match_breakdown(vt);
+    if !breakdown.is_empty() {
+        html.push_str(
+            "<h2>Matches</h2>\n<table>\n<tr><th>field</th><th>by</th><th>files</th></tr>\n",
+        );
+        for ((col, by), count) in &breakdown {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(col),
+                escape_html(by),
+                count
+            )?;
+        }
+        html.push_str("</table>\n");
+    }
+
+    let col = |name: &str| vt.columns.iter().position(|c| c == name);
+    if let (Some(path), Some(size)) = (col("path"), col("size")) {
+        let mut largest = vt
+            .rows
+            .iter()
+            .filter_map(|row| Some((row[size].as_i64()?, repr(&row[path]))))
+            .collect::<Vec<_>>();
+        largest.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+        html.push_str("<h2>Largest files</h2>\n<table>\n<tr><th>path</th><th>size</th></tr>\n");
+        for (size, path) in largest.iter().take(HTML_LARGEST) {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape_html(path),
+                size
+            )?;
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Results</h2>\n<input id=\"filter\" placeholder=\"filter\">\n");
+    html.push_str("<table id=\"results\">\n<thead><tr>");
+    for col in &vt.columns {
+        write!(html, "<th>{}</th>", escape_html(col))?;
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in &vt.rows {
+        html.push_str("<tr>");
+        for val in row {
+            let val = if val.is_null() {
+                String::new()
+            } else {
+                repr(val)
+            };
+            write!(html, "<td>{}</td>", escape_html(&val))?;
+        }
+        html.push_str("</tr>\n");
+    }
+    write!(
+        html,
+        "</tbody>\n</table>\n<script>{HTML_FILTER}</script>\n</body>\n</html>\n"
+    )?;
+    Ok(html)
+}
+
+/// Files matched by each rule of each `*_match` column, by `path` (or the matched path
+/// when not selected). Rules seen only as not matching have no files, and suppressed
+/// matches are left out.
+fn rule_matches(vt: &ValuesTable) -> BTreeMap<String, BTreeMap<String, Vec<String>>> {
+    let path = vt.columns.iter().position(|c| c == "path");
+    let mut suites = BTreeMap::<String, BTreeMap<String, Vec<String>>>::new();
+    for (i, col) in vt.columns.iter().enumerate() {
+        if !col.ends_with("_match") {
+            continue;
+        }
+        let cases = suites.entry(col.clone()).or_default();
+        for row in &vt.rows {
+            // JSON columns come as text
+            let Some(m) = row[i]
+                .as_str()
+                .and_then(|text| serde_json::from_str::<Match>(text).ok())
+            else {
+                continue;
+            };
+            let file = path.map_or_else(|| m.on.clone(), |p| repr(&row[p]));
+            for (rule, matched) in &m.by {
+                let files = cases.entry(rule.clone()).or_default();
+                if *matched && m.is_match && !m.suppressed {
+                    files.push(file.clone());
+                }
+            }
+        }
+    }
+    suites
+}
+
+/// Export rule matches as a `JUnit` XML report: a test suite per `*_match` column, a test
+/// case per rule, and a failure per file it matched
+///
+/// # Errors
+///
+/// This function is not expected to error, just conforms to an interface
+pub fn to_junit(vt: &ValuesTable) -> Result<String> {
+    let suites = rule_matches(vt);
+    let failed = |cases: &BTreeMap<String, Vec<String>>| {
+        cases.values().filter(|files| !files.is_empty()).count()
+    };
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        xml,
+        "<testsuites name=\"recon\" tests=\"{}\" failures=\"{}\">",
+        suites.values().map(BTreeMap::len).sum::<usize>(),
+        suites.values().map(failed).sum::<usize>()
+    )?;
+    for (col, cases) in &suites {
+        writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+            escape_html(col),
+            cases.len(),
+            failed(cases)
+        )?;
+        for (rule, files) in cases {
+            write!(
+                xml,
+                "    <testcase classname=\"{}\" name=\"{}\"",
+                escape_html(col),
+                escape_html(rule)
+            )?;
+            if files.is_empty() {
+                xml.push_str("/>\n");
+                continue;
+            }
+            xml.push_str(">\n");
+            for file in files {
+                writeln!(
+                    xml,
+                    "      <failure message=\"{}\" type=\"{}\">{} matched by {}</failure>",
+                    escape_html(file),
+                    escape_html(col),
+                    escape_html(file),
+                    escape_html(rule)
+                )?;
+            }
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    Ok(xml)
+}
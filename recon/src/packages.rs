@@ -0,0 +1,119 @@
+use crate::data::Package;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Packages pinned by a lockfile or manifest, by its file name. Returns `None` for other files.
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be read or parsed
+pub fn parse(path: &Path, name: &str) -> Result<Option<Vec<Package>>> {
+    let parse: fn(&str) -> Result<BTreeSet<Package>> = match name {
+        "Cargo.lock" => cargo_lock,
+        "package-lock.json" => package_lock,
+        "requirements.txt" => requirements,
+        "go.sum" => go_sum,
+        _ => return Ok(None),
+    };
+    let text = fs::read_to_string(path)?;
+    Ok(Some(parse(&text)?.into_iter().collect()))
+}
+
+fn package(ecosystem: &str, name: &str, version: &str) -> Package {
+    Package {
+        ecosystem: ecosystem.to_string(),
+        name: name.to_string(),
+        version: version.to_string(),
+    }
+}
+
+/// `[[package]]` tables, read line by line as they're flat. Only packages from a registry
+/// are on crates.io: workspace members have no `source`, and git dependencies aren't published.
+fn cargo_lock(text: &str) -> Result<BTreeSet<Package>> {
+    let mut packages = BTreeSet::new();
+    let (mut name, mut version, mut source) = (None, None, None);
+    for line in text.lines().chain(["[[package]]"]) {
+        let line = line.trim();
+        if line.starts_with('[') {
+            if let (Some(name), Some(version), true) = (
+                name.take(),
+                version.take(),
+                source
+                    .take()
+                    .is_some_and(|source: &str| source.starts_with("registry+")),
+            ) {
+                packages.insert(package("crates.io", name, version));
+            }
+        } else if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().trim_matches('"');
+            match key.trim() {
+                "name" => name = Some(value),
+                "version" => version = Some(value),
+                "source" => source = Some(value),
+                _ => {}
+            }
+        }
+    }
+    Ok(packages)
+}
+
+/// `packages` (lockfile v2 and up) keyed by their `node_modules` path, or `dependencies`
+/// (v1) nested by name
+fn package_lock(text: &str) -> Result<BTreeSet<Package>> {
+    fn dependencies(deps: &Value, packages: &mut BTreeSet<Package>) {
+        for (name, dep) in deps.as_object().into_iter().flatten() {
+            if let Some(version) = dep["version"].as_str() {
+                packages.insert(package("npm", name, version));
+            }
+            dependencies(&dep["dependencies"], packages);
+        }
+    }
+
+    let lock: Value = serde_json::from_str(text)?;
+    let mut packages = BTreeSet::new();
+    if let Some(modules) = lock["packages"].as_object() {
+        for (path, module) in modules {
+            let Some((_, name)) = path.rsplit_once("node_modules/") else {
+                continue;
+            };
+            if let Some(version) = module["version"].as_str() {
+                packages.insert(package("npm", name, version));
+            }
+        }
+    } else {
+        dependencies(&lock["dependencies"], &mut packages);
+    }
+    Ok(packages)
+}
+
+/// Pinned `name==version` requirements, other specifiers don't tell what's installed
+fn requirements(text: &str) -> Result<BTreeSet<Package>> {
+    Ok(text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| line.split_once("=="))
+        .map(|(name, version)| {
+            // drop extras (`name[extra]`) and environment markers (`; python_version...`)
+            let name = name.split('[').next().unwrap_or_default().trim();
+            let version = version.split([';', ' ']).next().unwrap_or_default();
+            package("PyPI", name, version.trim())
+        })
+        .filter(|p| !p.name.is_empty() && !p.version.is_empty())
+        .collect())
+}
+
+/// `module version[/go.mod] hash` lines
+fn go_sum(text: &str) -> Result<BTreeSet<Package>> {
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (name, version) = (fields.next()?, fields.next()?);
+            let version = version.trim_end_matches("/go.mod");
+            Some(package("Go", name, version.trim_start_matches('v')))
+        })
+        .collect())
+}
@@ -0,0 +1,148 @@
+//!
+//! Resources of Portable Executable files (Windows executables and DLLs)
+//!
+use crate::data::VersionInfo;
+use anyhow::Result;
+use object::pe::{ImageNtHeaders32, ImageNtHeaders64, RT_VERSION};
+use object::read::pe::{ImageNtHeaders, PeFile, ResourceDirectoryEntryData, ResourceNameOrId};
+use object::{FileKind, LittleEndian as LE};
+
+/// The `VERSIONINFO` resource of a PE file, `None` when `data` isn't one or it has none
+///
+/// # Errors
+///
+/// This function will return an error if `data` looks like a PE file but is malformed
+pub fn version_info(data: &[u8]) -> Result<Option<VersionInfo>> {
+    let resource = match FileKind::parse(data) {
+        Ok(FileKind::Pe32) => version_resource::<ImageNtHeaders32>(data)?,
+        Ok(FileKind::Pe64) => version_resource::<ImageNtHeaders64>(data)?,
+        _ => return Ok(None),
+    };
+    Ok(resource.and_then(parse_version_info))
+}
+
+/// The data of the version resource, in the first language it comes in
+fn version_resource<Pe: ImageNtHeaders>(data: &[u8]) -> Result<Option<&[u8]>> {
+    let file = PeFile::<Pe>::parse(data)?;
+    let sections = file.section_table();
+    let Some(resources) = file
+        .data_directories()
+        .resource_directory(data, &sections)?
+    else {
+        return Ok(None);
+    };
+    let root = resources.root()?;
+    let Some(mut entry) = root
+        .entries
+        .iter()
+        .find(|entry| matches!(entry.name_or_id(), ResourceNameOrId::Id(RT_VERSION)))
+    else {
+        return Ok(None);
+    };
+    // by type, then by name, then by language
+    for _ in 0..3 {
+        match entry.data(resources)? {
+            ResourceDirectoryEntryData::Table(table) => match table.entries.first() {
+                Some(first) => entry = first,
+                None => return Ok(None),
+            },
+            ResourceDirectoryEntryData::Data(found) => {
+                let size = usize::try_from(found.size.get(LE))?;
+                return Ok(sections
+                    .pe_data_at(data, found.offset_to_data.get(LE))
+                    .and_then(|resource| resource.get(..size)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+///
+/// A block of a version resource: its key, its value, and the blocks nested in it
+///
+struct Block<'a> {
+    key: String,
+    value: &'a [u8],
+    children: &'a [u8],
+}
+
+fn word(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// Blocks start at 32 bit boundaries
+const fn align(at: usize) -> usize {
+    (at + 3) & !3
+}
+
+/// Text as UTF-16 up to its NUL
+fn text(data: &[u8]) -> String {
+    let chars = (0..data.len() / 2)
+        .map_while(|i| word(data, i * 2))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&chars).trim().to_string()
+}
+
+fn parse_block(data: &[u8]) -> Option<Block<'_>> {
+    let len = usize::from(word(data, 0)?).min(data.len());
+    let data = &data[..len];
+    let value_len = usize::from(word(data, 2)?);
+    // text values are measured in characters
+    let value_len = if word(data, 4)? == 1 {
+        value_len * 2
+    } else {
+        value_len
+    };
+    let key = (3..len / 2)
+        .map_while(|i| word(data, i * 2))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    let value_at = align(6 + (key.len() + 1) * 2).min(len);
+    let value_end = (value_at + value_len).min(len);
+    Some(Block {
+        key: String::from_utf16_lossy(&key),
+        value: &data[value_at..value_end],
+        children: &data[align(value_end).min(len)..],
+    })
+}
+
+/// The blocks one after another in `data`
+fn blocks(mut data: &[u8]) -> impl Iterator<Item = Block<'_>> {
+    std::iter::from_fn(move || {
+        let len = usize::from(word(data, 0)?);
+        // a block is at least its header
+        if len < 6 {
+            return None;
+        }
+        let block = parse_block(data)?;
+        data = data.get(align(len)..).unwrap_or_default();
+        Some(block)
+    })
+}
+
+/// The strings of the first string table of a `VS_VERSIONINFO` resource, `None` when
+/// there are none of the known ones
+fn parse_version_info(resource: &[u8]) -> Option<VersionInfo> {
+    let root = parse_block(resource).filter(|root| root.key == "VS_VERSION_INFO")?;
+    let table = blocks(root.children)
+        .filter(|block| block.key == "StringFileInfo")
+        .find_map(|block| blocks(block.children).next())?;
+
+    let mut info = VersionInfo::default();
+    for string in blocks(table.children) {
+        let field = match string.key.as_str() {
+            "CompanyName" => &mut info.company_name,
+            "FileDescription" => &mut info.file_description,
+            "FileVersion" => &mut info.file_version,
+            "InternalName" => &mut info.internal_name,
+            "LegalCopyright" => &mut info.legal_copyright,
+            "OriginalFilename" => &mut info.original_filename,
+            "ProductName" => &mut info.product_name,
+            "ProductVersion" => &mut info.product_version,
+            _ => continue,
+        };
+        *field = Some(text(string.value)).filter(|value| !value.is_empty());
+    }
+    Some(info).filter(|info| *info != VersionInfo::default())
+}
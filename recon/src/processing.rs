@@ -2,14 +2,52 @@
 
 use anyhow::{bail, Result};
 const MAX_PEEK_SIZE: usize = 1024;
-use crate::data::File;
+use crate::archive;
+use crate::config::{ChunksOptions, Extensions, StringsOptions, ZipBombOptions};
+use crate::data::{ArchiveEntries, CryptoItem, CryptoMaterial, File, Iocs, Package, VersionInfo};
+use crate::git;
+use crate::minhash;
+use crate::os;
+use crate::pe;
+use chrono::{DateTime, TimeZone, Utc};
 use ignore::gitignore::GitignoreBuilder;
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
 use sha2::Digest;
+use std::collections::BTreeSet;
 use std::fs::File as FsFile;
 use std::io;
-use std::io::Read;
+use std::io::{BufRead, Read};
+use std::net::Ipv6Addr;
 use std::path::Path;
 use std::process;
+use x509_parser::der_parser::ber::BerObjectContent;
+use x509_parser::der_parser::parse_der;
+use x509_parser::prelude::{parse_x509_certificate, X509Certificate};
+
+/// Cap on the number of indicators kept per kind, so noisy files don't blow up rows
+const MAX_IOCS: usize = 1000;
+
+lazy_static! {
+    static ref URL_RE: Regex =
+        Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s"'<>()\[\]{}\\^`|]+"#).unwrap();
+    static ref IPV4_RE: Regex = Regex::new(
+        r"\b(?:(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])\b"
+    )
+    .unwrap();
+    static ref IPV6_CANDIDATE_RE: Regex = Regex::new(r"(?i)\b[0-9a-f]{0,4}(?::[0-9a-f]{0,4}){2,7}\b").unwrap();
+    static ref EMAIL_RE: Regex =
+        Regex::new(r"(?i)\b[a-z0-9._%+-]+@(?:[a-z0-9-]+\.)+[a-z]{2,24}\b").unwrap();
+    // restrict to well known TLDs, otherwise every `self.path` in source code is a domain.
+    // TLDs which are also common words or member names (`log.in`, `read.me`, `logger.info`)
+    // take a subdomain, such as `user.github.io`
+    static ref DOMAIN_RE: Regex = Regex::new(
+        r"(?i)\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+(?:com|net|org|biz|gov|edu|mil|ru|cn|su|tk|xyz|onion|online|cloud|uk|de|fr|nl|br|jp|kr|ir|ua|pl|ca|au|eu)\b|\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.){2,}(?:info|int|io|co|me|top|site|club|app|dev|in|it|es|us|cc|ws|pw|to)\b"
+    )
+    .unwrap();
+    static ref PEM_RE: Regex =
+        Regex::new(r"(?s)-----BEGIN ([A-Z0-9 ]+)-----(.*?)-----END ([A-Z0-9 ]+)-----").unwrap();
+}
 
 struct CrcDigest(crc32fast::Hasher);
 
@@ -26,30 +64,137 @@ impl std::io::Write for CrcDigest {
     }
 }
 
-fn is_ext_class(file: &File, fval: &[String]) -> Result<Option<bool>> {
-    file.ext
-        .as_ref()
-        .map_or(Ok(None), |ext| Ok(Some(fval.iter().any(|v| ext.eq(v)))))
+/// Counts of each byte value written
+struct ByteCounts([u64; 256]);
+
+impl std::io::Write for ByteCounts {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for b in buf {
+            self.0[usize::from(*b)] += 1;
+        }
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn is_printable(b: u8) -> bool {
+    b == b'\t' || (b' '..=b'~').contains(&b)
+}
+
+///
+/// Runs of printable characters at least `min_len` long, collected a character at a time.
+/// Once `limit` bytes of them are found, further runs are dropped.
+///
+struct Runs {
+    min_len: usize,
+    limit: usize,
+    found: Vec<String>,
+    found_len: usize,
+    current: String,
+    current_len: usize,
+}
+
+impl Runs {
+    const fn new(min_len: usize, limit: usize) -> Self {
+        Self {
+            min_len,
+            limit,
+            found: vec![],
+            found_len: 0,
+            current: String::new(),
+            current_len: 0,
+        }
+    }
+
+    const fn is_full(&self) -> bool {
+        self.found_len >= self.limit
+    }
+
+    /// Extend the current run with a printable character, or end it with `None`
+    fn push(&mut self, printable: Option<u8>) {
+        let Some(b) = printable else {
+            if self.current_len >= self.min_len && !self.is_full() {
+                // joined by newlines
+                self.found_len += self.current.len() + usize::from(!self.found.is_empty());
+                self.found.push(std::mem::take(&mut self.current));
+            }
+            self.current.clear();
+            self.current_len = 0;
+            return;
+        };
+        // a run longer than what's left to find is stored only up to there
+        if self.current.len() < self.limit {
+            self.current.push(char::from(b));
+        }
+        self.current_len += 1;
+    }
+}
+
+/// Built-in `is_archive` extensions
+pub const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "tar", "gz", "tgz", "bz2", "tbz", "tbz2", "xz", "txz", "zst", "lz", "lz4", "lzma", "z",
+    "7z", "rar", "cab", "arj", "cpio", "iso", "dmg", "jar", "war", "ear", "apk", "aab", "ipa",
+    "deb", "rpm", "whl", "egg", "gem", "nupkg", "crx", "xpi", "vsix",
+];
+
+/// Built-in `is_document` extensions
+pub const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "docm", "dot", "dotx", "dotm", "xls", "xlsx", "xlsm", "xlsb", "xlt",
+    "xltx", "ppt", "pptx", "pptm", "pps", "ppsx", "odt", "ods", "odp", "odg", "rtf", "txt", "text",
+    "md", "markdown", "rst", "adoc", "org", "tex", "csv", "tsv", "epub", "mobi", "pages",
+    "numbers", "one", "msg", "eml", "vsd", "vsdx", "xps",
+];
+
+/// Built-in `is_media` extensions
+pub const MEDIA_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff", "webp", "svg", "ico", "heic", "heif",
+    "avif", "psd", "raw", "cr2", "nef", "dng", "mp3", "wav", "flac", "aac", "ogg", "oga", "opus",
+    "m4a", "wma", "aiff", "mid", "midi", "mp4", "m4v", "mkv", "mov", "avi", "wmv", "flv", "webm",
+    "mpg", "mpeg", "3gp", "ttf", "otf", "woff", "woff2",
+];
+
+/// Built-in `is_code` extensions
+pub const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx", "cs", "go", "java", "kt", "kts",
+    "scala", "groovy", "gradle", "swift", "m", "mm", "py", "pyw", "pyi", "rb", "php", "pl", "pm",
+    "lua", "r", "jl", "dart", "js", "mjs", "cjs", "jsx", "ts", "mts", "cts", "tsx", "vue",
+    "svelte", "html", "htm", "css", "scss", "sass", "less", "sh", "bash", "zsh", "fish", "ps1",
+    "psm1", "bat", "cmd", "vbs", "vba", "sql", "hs", "ml", "mli", "fs", "fsx", "ex", "exs", "erl",
+    "hrl", "clj", "cljs", "elm", "zig", "nim", "asm", "s", "tf", "proto", "graphql", "sol",
+];
+
+/// Whether the extension of `file` is one of `fval`, or of `builtin` when not given a list.
+/// Extensions compare regardless of ASCII case.
+fn is_ext_class(file: &File, fval: &Extensions, builtin: &[&str]) -> Result<Option<bool>> {
+    Ok(file.ext.as_ref().map(|ext| match fval {
+        Extensions::Builtin => builtin.iter().any(|v| ext.eq_ignore_ascii_case(v)),
+        Extensions::List(list) => list.iter().any(|v| ext.eq_ignore_ascii_case(v)),
+    }))
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn is_archive(file: &File, fval: &[String]) -> Result<Option<bool>> {
-    is_ext_class(file, fval)
+pub fn is_archive(file: &File, fval: &Extensions) -> Result<Option<bool>> {
+    is_ext_class(file, fval, ARCHIVE_EXTENSIONS)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn is_document(file: &File, fval: &[String]) -> Result<Option<bool>> {
-    is_ext_class(file, fval)
+pub fn is_document(file: &File, fval: &Extensions) -> Result<Option<bool>> {
+    is_ext_class(file, fval, DOCUMENT_EXTENSIONS)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn is_media(file: &File, fval: &[String]) -> Result<Option<bool>> {
-    is_ext_class(file, fval)
+pub fn is_media(file: &File, fval: &Extensions) -> Result<Option<bool>> {
+    is_ext_class(file, fval, MEDIA_EXTENSIONS)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
-pub fn is_code(file: &File, fval: &[String]) -> Result<Option<bool>> {
-    is_ext_class(file, fval)
+pub fn is_code(file: &File, fval: &Extensions) -> Result<Option<bool>> {
+    is_ext_class(file, fval, CODE_EXTENSIONS)
 }
 
 #[tracing::instrument(level = "trace", skip_all, err)]
@@ -75,7 +220,7 @@ pub fn crc32(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let mut file = FsFile::open(path)?;
     let mut hasher = CrcDigest(crc32fast::Hasher::new());
     io::copy(&mut file, &mut hasher)?;
@@ -88,7 +233,7 @@ pub fn simhash(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let text = std::fs::read(path)?;
     let hash = simhash::simhash(&String::from_utf8_lossy(&text[..]));
     Ok(Some(format!("{:x}", hash))) // to convert back  u64::from_str_radix(src, radix)
@@ -99,7 +244,7 @@ pub fn bytes_type(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let file = FsFile::open(path)?;
     let mut buffer: Vec<u8> = vec![];
     file.take(MAX_PEEK_SIZE as u64).read_to_end(&mut buffer)?;
@@ -122,12 +267,50 @@ pub fn is_binary(file: &File, fval: &bool) -> Result<Option<bool>> {
     Ok(None)
 }
 
+/// A piece of a file cut by content, so the same bytes are cut the same way wherever
+/// they are in a file
+pub struct Chunk {
+    pub offset: u64,
+    pub size: usize,
+    /// the first 128 bits of the sha256 of the chunk, in hex
+    pub hash: String,
+}
+
+/// Split the content of a file into content defined chunks with `FastCDC`
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn chunks(file: &File, opts: &ChunksOptions) -> Result<Vec<Chunk>> {
+    let avg = u32::try_from(opts.avg_size()).unwrap_or(u32::MAX);
+    let chunker =
+        fastcdc::v2020::StreamCDC::new(FsFile::open(file.content_path())?, avg / 4, avg, avg * 4);
+    chunker
+        .map(|chunk| {
+            let chunk = chunk?;
+            let hash = sha2::Sha256::digest(&chunk.data);
+            Ok(Chunk {
+                offset: chunk.offset,
+                size: chunk.length,
+                hash: format!("{:x}", hash)[..32].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The content of a text file, `None` if it isn't valid UTF-8 or has NUL bytes
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn text_content(file: &File) -> Result<Option<String>> {
+    let content = std::fs::read(file.content_path())?;
+    if content.contains(&0) {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(content).ok())
+}
+
 #[tracing::instrument(level = "trace", skip_all, err)]
 pub fn sha256(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let mut file = FsFile::open(path)?;
     let mut hasher = sha2::Sha256::new();
     io::copy(&mut file, &mut hasher)?;
@@ -140,7 +323,7 @@ pub fn sha512(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let mut file = FsFile::open(path)?;
     let mut hasher = sha2::Sha512::new();
     io::copy(&mut file, &mut hasher)?;
@@ -153,7 +336,7 @@ pub fn md5(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let mut file = FsFile::open(path)?;
     let mut hasher = md5::Md5::new();
     io::copy(&mut file, &mut hasher)?;
@@ -161,16 +344,457 @@ pub fn md5(file: &File, fval: &bool) -> Result<Option<String>> {
     Ok(Some(format!("{:x}", hash)))
 }
 
+/// sha256 of text content with line endings and trailing whitespace normalized: each line
+/// without trailing whitespace (so `\r\n` too) and ending with `\n`, and no blank lines at the
+/// end. Binary content is hashed as-is.
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn norm_hash(file: &File, fval: &bool) -> Result<Option<String>> {
+    if !fval {
+        return Ok(None);
+    }
+    let path = file.content_path();
+    let mut reader = io::BufReader::new(FsFile::open(path)?);
+    let mut hasher = sha2::Sha256::new();
+    let peek = reader.fill_buf()?;
+    if content_inspector::inspect(&peek[..peek.len().min(MAX_PEEK_SIZE)]).is_binary() {
+        io::copy(&mut reader, &mut hasher)?;
+        return Ok(Some(format!("{:x}", hasher.finalize())));
+    }
+
+    // blank lines are held back until a line with content shows they're not at the end
+    let (mut line, mut blank) = (vec![], 0);
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let len = line
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        if len == 0 {
+            blank += 1;
+        } else {
+            for _ in 0..blank {
+                hasher.update(b"\n");
+            }
+            blank = 0;
+            hasher.update(&line[..len]);
+            hasher.update(b"\n");
+        }
+        line.clear();
+    }
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+/// `MinHash` signature of text content over word shingles, in hex. `None` for binary
+/// content, or text without words.
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn minhash(file: &File, fval: &bool) -> Result<Option<String>> {
+    if !fval {
+        return Ok(None);
+    }
+    let content = std::fs::read(file.content_path())?;
+    if content_inspector::inspect(&content[..content.len().min(MAX_PEEK_SIZE)]).is_binary() {
+        return Ok(None);
+    }
+    Ok(minhash::signature(&String::from_utf8_lossy(&content)).map(|sig| minhash::to_hex(&sig)))
+}
+
+/// Shannon entropy of the content in bits per byte, from 0 to 8. Compressed or encrypted
+/// data is close to 8.
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn entropy(file: &File, fval: &bool) -> Result<Option<f64>> {
+    if !fval {
+        return Ok(None);
+    }
+    let path = file.content_path();
+    let mut file = FsFile::open(path)?;
+    let mut counts = ByteCounts([0; 256]);
+    let total = io::copy(&mut file, &mut counts)?;
+    if total == 0 {
+        return Ok(Some(0.0));
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let entropy = counts
+        .0
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            p * (1.0 / p).log2()
+        })
+        .sum();
+    Ok(Some(entropy))
+}
+
 #[tracing::instrument(level = "trace", skip_all, err)]
 pub fn file_magic(file: &File, fval: &bool) -> Result<Option<String>> {
     if !fval {
         return Ok(None);
     }
-    let path = Path::new(&file.abs_path);
+    let path = file.content_path();
     let out = process::Command::new("file").args([path]).output()?;
     Ok(Some(
         String::from_utf8_lossy(&out.stdout[..])
             .to_string()
-            .replace(&format!("{}: ", &file.abs_path), ""),
+            .replace(&format!("{}: ", path.display()), ""),
     ))
 }
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn strings(file: &File, opts: &StringsOptions) -> Result<Option<String>> {
+    let limit = opts.max_size.unwrap_or(usize::MAX);
+    let mut ascii = Runs::new(opts.min_len(), limit);
+    // UTF-16LE strings can start on an even or an odd offset
+    let mut utf16 = [
+        Runs::new(opts.min_len(), limit),
+        Runs::new(opts.min_len(), limit),
+    ];
+    let mut reader = io::BufReader::new(FsFile::open(file.content_path())?);
+    let mut prev: Option<u8> = None;
+    let mut offset = 0usize;
+    // ASCII strings come first, so reading stops once they fill `max_size`
+    while !ascii.is_full() {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        for &b in buf {
+            ascii.push(is_printable(b).then_some(b));
+            if let (Some(lo), true) = (prev, opts.utf16()) {
+                let printable = b == 0 && is_printable(lo);
+                utf16[(offset - 1) % 2].push(printable.then_some(lo));
+            }
+            prev = Some(b);
+            offset += 1;
+        }
+        let len = buf.len();
+        reader.consume(len);
+    }
+    ascii.push(None);
+
+    let mut found = ascii.found;
+    if opts.utf16() {
+        for mut runs in utf16 {
+            runs.push(None);
+            found.extend(runs.found);
+        }
+    }
+    let mut text = found.join("\n");
+    if let Some(max_size) = opts.max_size {
+        if text.len() > max_size {
+            // all strings are ASCII, so any byte index is a char boundary
+            text.truncate(max_size);
+        }
+    }
+    Ok(Some(text))
+}
+
+/// Distinct matches of `re` which are `valid`, up to `MAX_IOCS` of them
+fn collect_iocs(re: &Regex, content: &[u8], valid: impl Fn(&str) -> bool) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    for m in re.find_iter(content) {
+        if found.len() == MAX_IOCS {
+            break;
+        }
+        let ioc = String::from_utf8_lossy(m.as_bytes());
+        if valid(&ioc) {
+            found.insert(ioc.to_string());
+        }
+    }
+    found
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn iocs(file: &File, fval: &bool) -> Result<Option<Iocs>> {
+    if !fval {
+        return Ok(None);
+    }
+    let path = file.content_path();
+    let content = std::fs::read(path)?;
+
+    let urls = collect_iocs(&URL_RE, &content, |_| true);
+    let emails = collect_iocs(&EMAIL_RE, &content, |_| true);
+    let mut ips = collect_iocs(&IPV4_RE, &content, |_| true);
+    ips.extend(collect_iocs(&IPV6_CANDIDATE_RE, &content, |c| {
+        c.parse::<Ipv6Addr>().is_ok()
+    }));
+    // domains that are part of an email address are reported as emails only
+    let domains = collect_iocs(&DOMAIN_RE, &content, |d| {
+        !emails.iter().any(|e| e.ends_with(&format!("@{}", d)))
+    });
+
+    Ok(Some(Iocs {
+        urls: urls.into_iter().collect(),
+        ips: ips.into_iter().collect(),
+        domains: domains.into_iter().collect(),
+        emails: emails.into_iter().collect(),
+    }))
+}
+
+fn asn1_time(t: &x509_parser::time::ASN1Time) -> Option<String> {
+    Utc.timestamp_opt(t.timestamp(), 0)
+        .single()
+        .map(|t| t.to_rfc3339())
+}
+
+fn certificate_item(cert: &X509Certificate<'_>, label: Option<String>) -> CryptoItem {
+    CryptoItem {
+        kind: "certificate".to_string(),
+        label,
+        subject: Some(cert.subject().to_string()),
+        issuer: Some(cert.issuer().to_string()),
+        not_before: asn1_time(&cert.validity().not_before),
+        not_after: asn1_time(&cert.validity().not_after),
+        ..Default::default()
+    }
+}
+
+/// Returns true if an `openssh-key-v1` blob uses a cipher other than `none`
+fn is_encrypted_openssh_key(der: &[u8]) -> bool {
+    const MAGIC: &[u8] = b"openssh-key-v1\0";
+    der.strip_prefix(MAGIC)
+        .and_then(|rest| {
+            let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+            rest.get(4..4 + len)
+        })
+        .is_some_and(|cipher| cipher != b"none")
+}
+
+/// Returns the PEM label a DER encoded private key spanning all of `der` would have: PKCS#1
+/// `RSAPrivateKey`, SEC1 `ECPrivateKey`, or PKCS#8 `PrivateKeyInfo` and
+/// `EncryptedPrivateKeyInfo`
+fn der_private_key_label(der: &[u8]) -> Option<&'static str> {
+    let (rest, obj) = parse_der(der).ok()?;
+    let seq = obj.as_sequence().ok().filter(|_| rest.is_empty())?;
+    let is_octets = |i: usize| {
+        seq.get(i)
+            .is_some_and(|o| matches!(o.content, BerObjectContent::OctetString(_)))
+    };
+    // an AlgorithmIdentifier: a SEQUENCE starting with an OID
+    let algorithm = |i: usize| {
+        seq.get(i)
+            .and_then(|o| o.as_sequence().ok())
+            .and_then(|alg| alg.first()?.as_oid().ok())
+            .map(ToString::to_string)
+    };
+
+    match seq.first()?.as_u32().ok() {
+        // version, modulus, exponents, primes and coefficient
+        Some(0 | 1)
+            if seq.len() >= 9
+                && seq
+                    .iter()
+                    .all(|o| matches!(o.content, BerObjectContent::Integer(_))) =>
+        {
+            Some("RSA PRIVATE KEY")
+        }
+        Some(0 | 1) if algorithm(1).is_some() && is_octets(2) => Some("PRIVATE KEY"),
+        Some(1) if (2..=4).contains(&seq.len()) && is_octets(1) => Some("EC PRIVATE KEY"),
+        // PBES1, PBES2 (PKCS#5) and PKCS#12 password based encryption
+        None if seq.len() == 2
+            && is_octets(1)
+            && algorithm(0).is_some_and(|oid| {
+                oid.starts_with("1.2.840.113549.1.5.") || oid.starts_with("1.2.840.113549.1.12.1.")
+            }) =>
+        {
+            Some("ENCRYPTED PRIVATE KEY")
+        }
+        _ => None,
+    }
+}
+
+fn pem_item(label: &str, body: &[u8]) -> CryptoItem {
+    // skip RFC 1421 headers (e.g. `Proc-Type: 4,ENCRYPTED`) and keep base64 lines only
+    let text = String::from_utf8_lossy(body);
+    let b64 = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.contains(':'))
+        .collect::<String>();
+    let der = base64::decode(b64).unwrap_or_default();
+
+    let label_s = Some(label.to_string());
+    if label == "CERTIFICATE" || label == "TRUSTED CERTIFICATE" || label == "X509 CERTIFICATE" {
+        if let Ok((_, cert)) = parse_x509_certificate(&der) {
+            return certificate_item(&cert, label_s);
+        }
+    }
+
+    let kind = if label.ends_with("PRIVATE KEY") {
+        "private_key"
+    } else if label.ends_with("PUBLIC KEY") {
+        "public_key"
+    } else if label.contains("CERTIFICATE REQUEST") {
+        "certificate_request"
+    } else if label.contains("CERTIFICATE") {
+        "certificate"
+    } else {
+        "other"
+    };
+    CryptoItem {
+        kind: kind.to_string(),
+        label: label_s,
+        encrypted: label.starts_with("ENCRYPTED")
+            || text.contains("ENCRYPTED")
+            || is_encrypted_openssh_key(&der),
+        ..Default::default()
+    }
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn crypto_material(file: &File, fval: &bool) -> Result<Option<CryptoMaterial>> {
+    if !fval {
+        return Ok(None);
+    }
+    let path = file.content_path();
+    let content = std::fs::read(path)?;
+
+    let mut items = PEM_RE
+        .captures_iter(&content)
+        .filter(|caps| caps[1] == caps[3])
+        .map(|caps| pem_item(&String::from_utf8_lossy(&caps[1]), &caps[2]))
+        .collect::<Vec<_>>();
+
+    // a DER encoded certificate or key is a single ASN.1 SEQUENCE spanning the whole file
+    if items.is_empty() && content.first() == Some(&0x30) {
+        match parse_x509_certificate(&content) {
+            Ok(([], cert)) => items.push(certificate_item(&cert, None)),
+            _ => {
+                if let Some(label) = der_private_key_label(&content) {
+                    items.push(CryptoItem {
+                        kind: "private_key".to_string(),
+                        label: Some(label.to_string()),
+                        encrypted: label.starts_with("ENCRYPTED"),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(Some(CryptoMaterial {
+        has_certificate: items.iter().any(|i| i.kind == "certificate"),
+        has_private_key: items.iter().any(|i| i.kind == "private_key"),
+        items,
+    }))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn weak_key_perms(file: &File, fval: &bool) -> Result<Option<bool>> {
+    if !fval {
+        return Ok(None);
+    }
+
+    // infer based on crypto material, if missing force compute it
+    let has_private_key = if let Some(material) = &file.crypto_material {
+        material.has_private_key
+    } else {
+        crypto_material(file, &true)?.is_some_and(|m| m.has_private_key)
+    };
+    if !has_private_key {
+        return Ok(Some(false));
+    }
+
+    // anything beyond owner read/write (0600) is too permissive for a private key
+    let meta = std::fs::metadata(file.content_path())?;
+    Ok(Some(os::mode(&meta) & 0o177 != 0))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn archive_entries(file: &File, fval: &bool) -> Result<Option<ArchiveEntries>> {
+    // when classified by extension, respect it; otherwise sniff the content
+    if !fval || file.is_archive == Some(false) {
+        return Ok(None);
+    }
+    let path = file.content_path();
+    archive::detect(path)?.map_or(Ok(None), |format| archive::list(path, format).map(Some))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn version_info(file: &File, fval: &bool) -> Result<Option<VersionInfo>> {
+    if !fval {
+        return Ok(None);
+    }
+    // only executables are read whole
+    let mut magic = [0; 2];
+    let mut f = FsFile::open(file.content_path())?;
+    if f.read_exact(&mut magic).is_err() || &magic != b"MZ" {
+        return Ok(None);
+    }
+    pe::version_info(&std::fs::read(file.content_path())?)
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn packages(file: &File, fval: &bool) -> Result<Option<Vec<Package>>> {
+    if !fval {
+        return Ok(None);
+    }
+    let Some(name) = Path::new(&file.path).file_name() else {
+        return Ok(None);
+    };
+    crate::packages::parse(file.content_path(), &name.to_string_lossy())
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn compression_ratio(file: &File, fval: &bool) -> Result<Option<f64>> {
+    if !fval || file.is_archive == Some(false) {
+        return Ok(None);
+    }
+    let path = file.content_path();
+    let Some(format) = archive::detect(path)? else {
+        return Ok(None);
+    };
+    let (size, compressed) = archive::sizes(path, format)?;
+    #[allow(clippy::cast_precision_loss)]
+    Ok(Some(size as f64 / compressed.max(1) as f64))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn is_zip_bomb(file: &File, opts: &ZipBombOptions) -> Result<Option<bool>> {
+    // infer based on compression ratio, if missing force compute it
+    let ratio = if file.compression_ratio.is_some() {
+        file.compression_ratio
+    } else {
+        compression_ratio(file, &true)?
+    };
+    Ok(ratio.map(|r| r > opts.max_ratio()))
+}
+
+/// Resolve the last commit of a file from the history of its repository
+fn git_last_commit_info(file: &File) -> Result<Option<git::LastCommit>> {
+    // archive members are not part of any work tree
+    if file.parent_archive.is_some() {
+        return Ok(None);
+    }
+    git::last_commit(file.content_path())
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn git_tracked(file: &File, fval: &bool) -> Result<Option<bool>> {
+    if !fval || file.parent_archive.is_some() {
+        return Ok(None);
+    }
+    git::is_tracked(file.content_path())
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn git_last_commit(file: &File, fval: &bool) -> Result<Option<String>> {
+    if !fval {
+        return Ok(None);
+    }
+    Ok(git_last_commit_info(file)?.map(|c| c.id))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn git_last_author(file: &File, fval: &bool) -> Result<Option<String>> {
+    if !fval {
+        return Ok(None);
+    }
+    Ok(git_last_commit_info(file)?.map(|c| c.author))
+}
+
+#[tracing::instrument(level = "trace", skip_all, err)]
+pub fn git_last_commit_time(file: &File, fval: &bool) -> Result<Option<DateTime<Utc>>> {
+    if !fval {
+        return Ok(None);
+    }
+    Ok(git_last_commit_info(file)?.and_then(|c| c.time))
+}
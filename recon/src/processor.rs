@@ -0,0 +1,71 @@
+use crate::data::File;
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+///
+/// A custom computed field, stored under its name in the `extra` column of files.
+/// Register it with `register`, then turn it on by name in `extra` of the fields config:
+///
+/// ```yaml
+/// computed_fields:
+///   extra:
+///     line_count: true
+///     entropy: { block_size: 256 }
+/// ```
+///
+pub trait Processor: Send + Sync {
+    /// The name it's turned on by, and its key in `extra`
+    fn name(&self) -> &str;
+
+    /// Compute the field for `file`, given the options it was turned on with (`true` when
+    /// there are none). A null value leaves the field out.
+    ///
+    /// # Errors
+    ///
+    /// A failure is recorded in `errors` under the processor's name, or stops the scan
+    /// with `--strict`
+    fn compute(&self, file: &File, options: &serde_yaml::Value) -> Result<serde_json::Value>;
+}
+
+lazy_static! {
+    static ref PROCESSORS: RwLock<BTreeMap<String, Arc<dyn Processor>>> = RwLock::default();
+}
+
+/// Make a processor available to configs, replacing any registered under the same name
+pub fn register(processor: impl Processor + 'static) {
+    PROCESSORS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(processor.name().to_string(), Arc::new(processor));
+}
+
+/// Whether a processor is registered as `name`
+#[must_use]
+pub fn is_registered(name: &str) -> bool {
+    PROCESSORS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains_key(name)
+}
+
+/// Compute the field of the processor registered as `name`
+///
+/// # Errors
+///
+/// This function will return an error if there's no such processor, or it fails
+pub(crate) fn compute(
+    name: &str,
+    file: &File,
+    options: &serde_yaml::Value,
+) -> Result<Option<serde_json::Value>> {
+    // not holding the lock while computing, a processor may take its time
+    let processor = PROCESSORS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(name)
+        .cloned()
+        .with_context(|| format!("no processor registered as '{}'", name))?;
+    Ok(Some(processor.compute(file, options)?).filter(|val| !val.is_null()))
+}
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use s3::{creds::Credentials, serde_types::Object, Bucket, Region};
+use std::path::Path;
+
+///
+/// A bucket and key prefix to list objects from
+///
+pub struct Location {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl Location {
+    /// Parse an `s3://bucket/prefix` root. Returns `None` for anything else.
+    #[must_use]
+    pub fn parse(root: &str) -> Option<Self> {
+        let rest = root.strip_prefix("s3://")?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return None;
+        }
+        Some(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Connect to the bucket. Region comes from `AWS_REGION` (default: us-east-1), and a
+    /// custom endpoint (e.g. minio) from `AWS_ENDPOINT`. Credentials are looked up in the
+    /// environment, profile and instance metadata, falling back to anonymous access.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the bucket cannot be configured
+    pub fn bucket(&self) -> Result<Bucket> {
+        let region = Region::from_default_env().unwrap_or(Region::UsEast1);
+        let custom = matches!(region, Region::Custom { .. });
+        let credentials = Credentials::default().or_else(|_| Credentials::anonymous())?;
+        let bucket = Bucket::new(&self.bucket, region, credentials)
+            .with_context(|| format!("cannot open bucket '{}'", self.bucket))?;
+        // custom endpoints rarely support virtual-hosted style addressing
+        Ok(if custom {
+            bucket.with_path_style()
+        } else {
+            bucket
+        })
+    }
+}
+
+/// List all objects under a prefix, following continuation
+///
+/// # Errors
+///
+/// This function will return an error if listing failed
+pub async fn list(bucket: &Bucket, prefix: &str) -> Result<Vec<Object>> {
+    let pages = bucket
+        .list(prefix.to_string(), None)
+        .await
+        .with_context(|| format!("cannot list 's3://{}/{}'", bucket.name(), prefix))?;
+    Ok(pages
+        .into_iter()
+        .flat_map(|page| page.contents)
+        // "folder" placeholder objects carry no content
+        .filter(|o| !o.key.ends_with('/'))
+        .collect())
+}
+
+/// Download an object into a local file
+///
+/// # Errors
+///
+/// This function will return an error if the download or writing failed
+pub async fn fetch(bucket: &Bucket, key: &str, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut out = tokio::fs::File::create(dest).await?;
+    let status = bucket
+        .get_object_to_writer(key, &mut out)
+        .await
+        .with_context(|| format!("cannot fetch 's3://{}/{}'", bucket.name(), key))?;
+    anyhow::ensure!(
+        status == 200,
+        "cannot fetch 's3://{}/{}': status {}",
+        bucket.name(),
+        key,
+        status
+    );
+    Ok(())
+}
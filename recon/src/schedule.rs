@@ -0,0 +1,259 @@
+use anyhow::{bail, Context, Result};
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc,
+};
+use serde_derive::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::{error, info};
+
+/// How far ahead to look for the next time a schedule fires before giving up on it
+const HORIZON_DAYS: i64 = 366 * 5;
+
+///
+/// A cron schedule: `minute hour day-of-month month day-of-week`, with `*`, lists, ranges
+/// and steps, such as `*/15 9-17 * * 1-5`, or one of `@hourly`, `@daily`, `@weekly`,
+/// `@monthly` and `@yearly`.
+///
+/// As with cron, times are local, and when both days of the month and of the week are
+/// restricted, a day matching either fires.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// both days of the month and of the week are restricted
+    any_day: bool,
+}
+
+/// The values of a cron field as bits, checking they are within `min..=max`
+fn bits(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().context("invalid step")?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("invalid step 0");
+        }
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else if let Some((from, to)) = range.split_once('-') {
+            (from.parse()?, to.parse()?)
+        } else {
+            let at = range.parse()?;
+            // `5/10` goes from 5 to the end
+            (at, if part.contains('/') { max } else { at })
+        };
+        if from < min || to > max || from > to {
+            bail!("'{}' is out of {}-{}", part, min, max);
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self> {
+        let fields = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            fields => fields,
+        }
+        .split_whitespace()
+        .collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            bail!("expected 5 fields: minute hour day-of-month month day-of-week");
+        };
+        let mut weekday_bits = bits(weekdays, 0, 7).context("invalid day of week")?;
+        // Sunday is both 0 and 7
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        let schedule = Self {
+            expr: expr.trim().to_string(),
+            minutes: bits(minutes, 0, 59).context("invalid minute")?,
+            hours: bits(hours, 0, 23).context("invalid hour")?,
+            days: bits(days, 1, 31).context("invalid day of month")?,
+            months: bits(months, 1, 12).context("invalid month")?,
+            weekdays: weekday_bits,
+            any_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+        };
+        if schedule.next_after(Local::now()).is_none() {
+            bail!("never fires");
+        }
+        Ok(schedule)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+impl Schedule {
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// The first time the schedule fires after `after`, unless it doesn't within years
+    #[must_use]
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let after = after.naive_local();
+        let mut at =
+            after.date().and_hms_opt(after.hour(), after.minute(), 0)? + Duration::minutes(1);
+        let horizon = at + Duration::days(HORIZON_DAYS);
+        while at < horizon {
+            let next_day =
+                || -> Option<NaiveDateTime> { at.date().succ_opt()?.and_hms_opt(0, 0, 0) };
+            if self.months & (1 << at.month()) == 0 {
+                let (year, month) = if at.month() == 12 {
+                    (at.year() + 1, 1)
+                } else {
+                    (at.year(), at.month() + 1)
+                };
+                at = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(at.date()) {
+                at = next_day()?;
+            } else if self.hours & (1 << at.hour()) == 0 {
+                at = at.date().and_hms_opt(at.hour(), 0, 0)? + Duration::hours(1);
+            } else if self.minutes & (1 << at.minute()) == 0 {
+                at += Duration::minutes(1);
+            } else if let Some(local) = Local.from_local_datetime(&at).earliest() {
+                return Some(local);
+            } else {
+                // skipped over by a daylight saving time change
+                at += Duration::minutes(1);
+            }
+        }
+        None
+    }
+}
+
+/// The last scheduled scan
+#[derive(Clone, Debug, Serialize)]
+pub struct LastRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Where scheduled scans are at
+#[derive(Clone, Debug, Serialize)]
+pub struct Status {
+    pub schedule: String,
+    pub next_run: Option<DateTime<Utc>>,
+    /// a scheduled scan is running
+    pub running: bool,
+    /// scans skipped since they were due while the one before was still running
+    pub skipped: u64,
+    pub last_run: Option<LastRun>,
+}
+
+///
+/// Runs scans on a schedule, one at a time, and keeps their status.
+///
+pub struct Scheduler {
+    schedule: Schedule,
+    status: Mutex<Status>,
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new(schedule: Schedule) -> Self {
+        let status = Status {
+            schedule: schedule.to_string(),
+            next_run: None,
+            running: false,
+            skipped: 0,
+            last_run: None,
+        };
+        Self {
+            schedule,
+            status: Mutex::new(status),
+        }
+    }
+
+    /// Where scheduled scans are at
+    #[must_use]
+    pub fn status(&self) -> Status {
+        match self.status.lock() {
+            Ok(status) => status.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Status)) {
+        if let Ok(mut status) = self.status.lock() {
+            f(&mut status);
+        }
+    }
+
+    /// Wait until the next scan is due, forever if never
+    pub async fn due(&self) {
+        let now = Local::now();
+        let next = self.schedule.next_after(now);
+        self.update(|status| status.next_run = next.map(|next| next.with_timezone(&Utc)));
+        match next {
+            Some(next) => tokio::time::sleep((next - now).to_std().unwrap_or_default()).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Run `scan` as the scheduled scan that's due. Scans that came due while it ran
+    /// are skipped, so they never overlap. A failed scan is recorded, and the next one
+    /// is still run.
+    pub async fn run(&self, scan: impl Future<Output = Result<()>>) {
+        info!("scheduled scan ({})", self.schedule);
+        self.update(|status| status.running = true);
+        let started_at = Utc::now();
+        let res = scan.await;
+        let finished_at = Utc::now();
+        if let Err(err) = &res {
+            error!("scheduled scan failed: {:#}", err);
+        }
+
+        let mut skipped = 0;
+        let mut at = started_at.with_timezone(&Local);
+        while let Some(next) = self.schedule.next_after(at) {
+            if next > finished_at {
+                break;
+            }
+            skipped += 1;
+            at = next;
+        }
+        self.update(|status| {
+            status.running = false;
+            status.skipped += skipped;
+            status.last_run = Some(LastRun {
+                started_at,
+                finished_at,
+                ok: res.is_ok(),
+                error: res.err().map(|err| format!("{:#}", err)),
+            });
+        });
+    }
+}
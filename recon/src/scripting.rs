@@ -0,0 +1,65 @@
+use crate::data::File;
+use anyhow::{anyhow, bail, Result};
+use lazy_static::lazy_static;
+use rhai::{Dynamic, Engine, Scope, AST};
+
+/// most operations a script may run on a file, so a runaway loop can't stall a scan
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+lazy_static! {
+    static ref ENGINE: Engine = {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine
+    };
+}
+
+/// Compile a script
+///
+/// # Errors
+///
+/// This function will return an error if the script doesn't compile
+pub fn compile(source: &str) -> Result<AST> {
+    ENGINE
+        .compile(source)
+        .map_err(|err| anyhow!("invalid script: {}", err))
+}
+
+/// Run a script on a file, seen by the script as `file`
+///
+/// # Errors
+///
+/// This function will return an error if the script fails
+fn eval(ast: &AST, file: &File) -> Result<Dynamic> {
+    let mut scope = Scope::new();
+    scope.push_constant("file", rhai::serde::to_dynamic(file)?);
+    ENGINE
+        .eval_ast_with_scope(&mut scope, ast)
+        .map_err(|err| anyhow!("script failed: {}", err))
+}
+
+/// Run a script computing a field, with `()` leaving it out
+///
+/// # Errors
+///
+/// This function will return an error if the script fails
+pub fn field(ast: &AST, file: &File) -> Result<Option<serde_json::Value>> {
+    let val = eval(ast, file)?;
+    if val.is_unit() {
+        return Ok(None);
+    }
+    Ok(Some(rhai::serde::from_dynamic(&val)?))
+}
+
+/// Run a script deciding about a file
+///
+/// # Errors
+///
+/// This function will return an error if the script fails, or doesn't return a bool
+pub fn predicate(ast: &AST, file: &File) -> Result<bool> {
+    let val = eval(ast, file)?;
+    match val.as_bool() {
+        Ok(yes) => Ok(yes),
+        Err(type_name) => bail!("script returned {}, not true or false", type_name),
+    }
+}
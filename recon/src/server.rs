@@ -0,0 +1,195 @@
+use crate::data::{File, ValuesTable};
+use crate::db::Db;
+use crate::metrics::{self, Metrics};
+use crate::schedule::Scheduler;
+use anyhow::{bail, Result};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::json;
+use sqlx_meta::Schema;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::info;
+
+/// Serve a JSON API over a populated DB:
+///
+/// * `POST /query` with SQL as the body (or a `{"sql": "..."}` JSON object)
+/// * `GET /files?col=value&...` for files matching all filters, with optional
+///   `order_by`, `limit` and `offset`
+///
+/// Both respond with a table of `columns`, `rows` and `total_rows`. `GET /metrics` responds
+/// with the state of the DB for Prometheus, and `GET /schedule` with the status of
+/// scheduled scans.
+///
+/// # Errors
+///
+/// This function will return an error if binding to `addr` failed, or the server stopped abnormally
+pub async fn serve(db: Db, addr: SocketAddr, scheduler: Option<Arc<Scheduler>>) -> Result<()> {
+    let db = Arc::new(db);
+    let make_svc = make_service_fn(move |_conn| {
+        let db = db.clone();
+        let scheduler = scheduler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let db = db.clone();
+                let scheduler = scheduler.clone();
+                async move { Ok::<_, Infallible>(handle(&db, scheduler.as_deref(), req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_svc);
+    info!("serving on {}", addr);
+    server.await?;
+    Ok(())
+}
+
+async fn handle(db: &Db, scheduler: Option<&Scheduler>, req: Request<Body>) -> Response<Body> {
+    let res = match (req.method(), req.uri().path()) {
+        (&Method::POST, "/query") => query(db, req).await,
+        (&Method::GET, "/files") => files(db, req.uri().query().unwrap_or_default()).await,
+        (&Method::GET, "/schedule") => return schedule(scheduler),
+        (&Method::GET, "/metrics") => {
+            return match metrics::from_db(db).await {
+                Ok(text) => prometheus(text),
+                Err(err) => respond(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &json!({ "error": format!("{:#}", err) }),
+                ),
+            }
+        }
+        _ => return respond(StatusCode::NOT_FOUND, &json!({ "error": "not found" })),
+    };
+    match res {
+        Ok(vt) => respond(StatusCode::OK, &json!(vt)),
+        Err(err) => respond(
+            StatusCode::BAD_REQUEST,
+            &json!({ "error": format!("{:#}", err) }),
+        ),
+    }
+}
+
+/// Serve `GET /metrics` with what `metrics` counted, and `GET /schedule` with the status of
+/// scheduled scans, until `shutdown` completes
+///
+/// # Errors
+///
+/// This function will return an error if binding to `addr` failed
+pub fn serve_metrics(
+    metrics: Arc<Metrics>,
+    scheduler: Option<Arc<Scheduler>>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<impl Future<Output = ()>> {
+    let make_svc = make_service_fn(move |_conn| {
+        let metrics = metrics.clone();
+        let scheduler = scheduler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let res = match (req.method(), req.uri().path()) {
+                    (&Method::GET, "/metrics") => prometheus(metrics.render()),
+                    (&Method::GET, "/schedule") => schedule(scheduler.as_deref()),
+                    _ => respond(StatusCode::NOT_FOUND, &json!({ "error": "not found" })),
+                };
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown);
+    info!("serving metrics on {}", addr);
+    Ok(async move {
+        if let Err(err) = server.await {
+            info!("metrics server stopped: {}", err);
+        }
+    })
+}
+
+fn schedule(scheduler: Option<&Scheduler>) -> Response<Body> {
+    scheduler.map_or_else(
+        || {
+            respond(
+                StatusCode::NOT_FOUND,
+                &json!({ "error": "no schedule configured" }),
+            )
+        },
+        |scheduler| respond(StatusCode::OK, &json!(scheduler.status())),
+    )
+}
+
+fn prometheus(text: String) -> Response<Body> {
+    let mut res = Response::new(Body::from(text));
+    res.headers_mut()
+        .insert(CONTENT_TYPE, "text/plain; version=0.0.4".parse().unwrap());
+    res
+}
+
+fn respond(status: StatusCode, body: &serde_json::Value) -> Response<Body> {
+    let mut res = Response::new(Body::from(body.to_string()));
+    *res.status_mut() = status;
+    res.headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    res
+}
+
+async fn query(db: &Db, req: Request<Body>) -> Result<ValuesTable> {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let body = String::from_utf8(body.to_vec())?;
+    let sql = match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Object(obj)) => match obj.get("sql") {
+            Some(serde_json::Value::String(sql)) => sql.clone(),
+            _ => bail!("expected a 'sql' string field"),
+        },
+        _ => body,
+    };
+    db.query_table(&sql).await
+}
+
+async fn files(db: &Db, params: &str) -> Result<ValuesTable> {
+    let columns = File::columns();
+    let known = |col: &str| -> Result<()> {
+        if !columns.contains(&col) {
+            bail!("unknown column '{}'", col);
+        }
+        Ok(())
+    };
+
+    let mut filters = vec![];
+    let mut args = vec![];
+    let mut order_by = None;
+    let mut limit = None;
+    let mut offset = None;
+    for (key, value) in form_urlencoded::parse(params.as_bytes()) {
+        match key.as_ref() {
+            "order_by" => {
+                known(&value)?;
+                order_by = Some(value.to_string());
+            }
+            "limit" => limit = Some(value.parse::<u64>()?),
+            "offset" => offset = Some(value.parse::<u64>()?),
+            col => {
+                known(col)?;
+                filters.push(format!("\"{}\"=?", col));
+                args.push(value.to_string());
+            }
+        }
+    }
+
+    let mut sql = vec!["select * from files".to_string()];
+    if !filters.is_empty() {
+        sql.push(format!("where {}", filters.join(" and ")));
+    }
+    if let Some(order_by) = order_by {
+        sql.push(format!("order by \"{}\"", order_by));
+    }
+    if limit.is_some() || offset.is_some() {
+        sql.push(format!("limit {}", limit.map_or(-1, i128::from)));
+    }
+    if let Some(offset) = offset {
+        sql.push(format!("offset {}", offset));
+    }
+    db.query_table_with(&sql.join(" "), &args).await
+}
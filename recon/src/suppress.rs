@@ -0,0 +1,215 @@
+use crate::data::Match;
+use crate::db::Db;
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate};
+use globset::{GlobBuilder, GlobMatcher};
+use serde_derive::Deserialize;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// The suppression file read when the config doesn't name one, if there's one
+pub const SUPPRESS_FILE: &str = ".recon-suppress.yaml";
+
+///
+/// A known finding to accept: matches of `rule` on files at `path` with this `sha256`,
+/// each left out to cover any. Once past `expires`, it no longer applies.
+///
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Suppression {
+    /// a rule, as named in `by` of the match, or a `*_match` field for all of its rules
+    #[serde(default)]
+    rule: Option<String>,
+    /// glob of paths, where `*` stays within a folder and `**` doesn't
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    sha256: Option<String>,
+    /// the last day it applies, as `YYYY-MM-DD`
+    #[serde(default)]
+    expires: Option<NaiveDate>,
+    /// why it's accepted, for whoever reads the file next
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+impl Suppression {
+    fn describe(&self) -> String {
+        [
+            ("rule", &self.rule),
+            ("path", &self.path),
+            ("sha256", &self.sha256),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{}: {}", key, value.as_ref()?)))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SuppressFile {
+    #[serde(default)]
+    suppress: Vec<Suppression>,
+}
+
+///
+/// Findings accepted by a suppression file. Their matches are marked `suppressed`, and
+/// don't count towards `--fail-*` exits.
+///
+#[derive(Default)]
+pub struct Suppressions {
+    active: Vec<(Suppression, Option<GlobMatcher>)>,
+    expired: Vec<Suppression>,
+}
+
+impl Suppressions {
+    /// Load the suppressions of `file`, or of `.recon-suppress.yaml` when not given and
+    /// there's one
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or parsed, or a
+    /// suppression has nothing to go by or an invalid glob
+    pub fn load(file: Option<&str>) -> Result<Self> {
+        let file = match file {
+            Some(file) => file,
+            None if Path::new(SUPPRESS_FILE).exists() => SUPPRESS_FILE,
+            None => return Ok(Self::default()),
+        };
+        let text = fs::read_to_string(file).with_context(|| format!("cannot read '{}'", file))?;
+        let parsed: SuppressFile =
+            serde_yaml::from_str(&text).with_context(|| format!("invalid '{}'", file))?;
+
+        let today = Local::now().date_naive();
+        let mut suppressions = Self::default();
+        for (nth, suppression) in parsed.suppress.into_iter().enumerate() {
+            if suppression.rule.is_none()
+                && suppression.path.is_none()
+                && suppression.sha256.is_none()
+            {
+                bail!(
+                    "suppression {} of '{}' needs a rule, path or sha256",
+                    nth + 1,
+                    file
+                );
+            }
+            if suppression.expires.is_some_and(|expires| expires < today) {
+                suppressions.expired.push(suppression);
+                continue;
+            }
+            let glob = suppression
+                .path
+                .as_deref()
+                .map(|path| {
+                    GlobBuilder::new(path.trim_start_matches("./"))
+                        .literal_separator(true)
+                        .build()
+                        .with_context(|| format!("invalid path glob '{}' in '{}'", path, file))
+                })
+                .transpose()?
+                .map(|glob| glob.compile_matcher());
+            suppressions.active.push((suppression, glob));
+        }
+        Ok(suppressions)
+    }
+
+    /// Suppressions past their expiry date, which no longer apply
+    pub fn expired(&self) -> impl Iterator<Item = String> + '_ {
+        self.expired.iter().map(|suppression| {
+            format!(
+                "suppression ({}) expired on {}{}",
+                suppression.describe(),
+                suppression.expires.unwrap_or_default(),
+                suppression
+                    .reason
+                    .as_ref()
+                    .map(|reason| format!(", it was: {}", reason))
+                    .unwrap_or_default()
+            )
+        })
+    }
+
+    /// Whether every rule `m` matched by on a file is accepted
+    fn covers(
+        &self,
+        column: &str,
+        m: &Match,
+        path: &str,
+        abs_path: &str,
+        sha256: Option<&str>,
+    ) -> bool {
+        let path = path.trim_start_matches("./");
+        let applying = self
+            .active
+            .iter()
+            .filter(|(suppression, glob)| {
+                glob.as_ref()
+                    .is_none_or(|glob| glob.is_match(path) || glob.is_match(abs_path))
+                    && suppression.sha256.as_deref().is_none_or(|hash| {
+                        sha256.is_some_and(|sha256| sha256.eq_ignore_ascii_case(hash))
+                    })
+            })
+            .map(|(suppression, _)| suppression.rule.as_deref())
+            .collect::<Vec<_>>();
+        let mut matched = m.by.iter().filter(|(_, matched)| **matched).peekable();
+        matched.peek().is_some()
+            && matched.all(|(rule, _)| {
+                applying.iter().any(|suppressed| {
+                    suppressed.is_none_or(|suppressed| suppressed == rule || suppressed == column)
+                })
+            })
+    }
+}
+
+/// Mark the matches stored in `db` that `suppressions` accept as `suppressed`, and unmark
+/// those no longer accepted. Returns how many are suppressed.
+///
+/// # Errors
+///
+/// This function will return an error on db failure
+pub(crate) async fn apply(db: &Db, suppressions: &Suppressions) -> Result<u64> {
+    let mut suppressed = 0;
+    for column in db.match_columns().await? {
+        // with nothing to accept, only earlier marks are left to undo
+        let which = if suppressions.active.is_empty() {
+            "suppressed"
+        } else {
+            "is_match"
+        };
+        let rows = db
+            .query_table(&format!(
+                "select id, path, abs_path, sha256, {column} from files where json_extract({column}, '$.{which}') = 1"
+            ))
+            .await?
+            .rows;
+        for row in rows {
+            let [id, path, abs_path, sha256, m] = row.as_slice() else {
+                continue;
+            };
+            let (Some(id), Some(mut m)) = (
+                id.as_i64(),
+                m.as_str()
+                    .and_then(|m| serde_json::from_str::<Match>(m).ok()),
+            ) else {
+                continue;
+            };
+            let covered = suppressions.covers(
+                &column,
+                &m,
+                path.as_str().unwrap_or_default(),
+                abs_path.as_str().unwrap_or_default(),
+                sha256.as_str(),
+            );
+            suppressed += u64::from(covered);
+            if covered != m.suppressed {
+                m.suppressed = covered;
+                db.update_match(id, &column, &m).await?;
+            }
+        }
+    }
+    info!("{} matches suppressed", suppressed);
+    Ok(suppressed)
+}
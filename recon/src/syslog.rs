@@ -0,0 +1,286 @@
+use crate::config::Severity;
+use crate::data::File;
+use crate::observer::Observer;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::info;
+
+/// The local syslog socket
+const DEV_LOG: &str = "/dev/log";
+/// The socket journald takes native records on
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+/// The `SD-ID` of the structured data, under the example private enterprise number
+/// RFC 5424 uses
+const SD_ID: &str = "recon@32473";
+/// `user-level messages`
+const FACILITY: u8 = 1;
+
+/// Where records of matched files go
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// RFC 5424 records to a local datagram socket, such as `/dev/log`
+    Unix(PathBuf),
+    /// RFC 5424 records over UDP, to `host:port`
+    Udp(String),
+    /// journald's native protocol, with each detail as a `RECON_*` field
+    Journald,
+}
+
+/// `journald`, `syslog` for `/dev/log`, a socket path, or a `host:port` to send to over UDP
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "journald" => Self::Journald,
+            "syslog" => Self::Unix(PathBuf::from(DEV_LOG)),
+            _ if s.starts_with('/') => Self::Unix(PathBuf::from(s)),
+            _ if s
+                .rsplit_once(':')
+                .is_some_and(|(_, port)| port.parse::<u16>().is_ok()) =>
+            {
+                Self::Udp(s.to_string())
+            }
+            _ => bail!("expected 'journald', 'syslog', a socket path or host:port"),
+        })
+    }
+}
+
+enum Socket {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+}
+
+impl Socket {
+    fn send(&self, record: &[u8]) -> std::io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Self::Unix(socket) => socket.send(record),
+            Self::Udp(socket) => socket.send(record),
+        }
+    }
+}
+
+/// What a record is about: a matched file, the fields and rules it matched, and how bad
+/// that is by the most severe rule
+struct Record<'a> {
+    file: &'a File,
+    fields: BTreeSet<&'static str>,
+    rules: BTreeSet<String>,
+    severity: Option<Severity>,
+}
+
+impl Record<'_> {
+    /// The syslog severity: a match is a warning unless its rules say otherwise
+    const fn priority(&self) -> u8 {
+        match self.severity {
+            Some(Severity::Critical) => 2,
+            Some(Severity::High) => 3,
+            Some(Severity::Medium) | None => 4,
+            Some(Severity::Low) => 5,
+            Some(Severity::Info) => 6,
+        }
+    }
+
+    fn message(&self) -> String {
+        format!(
+            "{} matched {}",
+            self.file.path,
+            self.fields.iter().copied().collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    /// The details of the record, by field name
+    fn details(&self) -> BTreeMap<&'static str, String> {
+        let mut details = BTreeMap::from([
+            ("path", self.file.path.clone()),
+            ("abs_path", self.file.abs_path.clone()),
+            (
+                "fields",
+                self.fields.iter().copied().collect::<Vec<_>>().join(","),
+            ),
+            (
+                "rules",
+                self.rules.iter().cloned().collect::<Vec<_>>().join(","),
+            ),
+        ]);
+        if let Some(size) = self.file.size {
+            details.insert("size", size.to_string());
+        }
+        if let Some(sha256) = &self.file.sha256 {
+            details.insert("sha256", sha256.clone());
+        }
+        if let Some(severity) = self.severity {
+            details.insert("severity", format!("{:?}", severity).to_lowercase());
+        }
+        details
+    }
+
+    /// RFC 5424: `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG`
+    fn to_syslog(&self, hostname: &str) -> Vec<u8> {
+        let mut record = format!(
+            "<{}>1 {} {} recon {} match [{}",
+            FACILITY * 8 + self.priority(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            hostname,
+            std::process::id(),
+            SD_ID,
+        );
+        for (name, value) in self.details() {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace(']', "\\]");
+            let _res = write!(record, " {}=\"{}\"", name, value);
+        }
+        let _res = write!(record, "] {}", self.message());
+        record.into_bytes()
+    }
+
+    /// journald's native protocol: `KEY=value` lines, and values with newlines as `KEY`,
+    /// their length as a little endian `u64`, and the value
+    fn to_journald(&self) -> Vec<u8> {
+        let mut fields = vec![
+            ("MESSAGE".to_string(), self.message()),
+            ("PRIORITY".to_string(), self.priority().to_string()),
+            ("SYSLOG_IDENTIFIER".to_string(), "recon".to_string()),
+        ];
+        fields.extend(
+            self.details()
+                .into_iter()
+                .map(|(name, value)| (format!("RECON_{}", name.to_uppercase()), value)),
+        );
+        let mut record = vec![];
+        for (name, value) in fields {
+            record.extend_from_slice(name.as_bytes());
+            if value.contains('\n') {
+                record.push(b'\n');
+                record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            } else {
+                record.push(b'=');
+            }
+            record.extend_from_slice(value.as_bytes());
+            record.push(b'\n');
+        }
+        record
+    }
+}
+
+/// The mtime and fields of a file when it was sent
+type Sent = (Option<DateTime<Utc>>, BTreeSet<&'static str>);
+
+///
+/// Sends a record per matched file to syslog or journald, for SIEM agents to collect.
+///
+/// A file matching more fields on a later pass, such as a stage, is sent again with all
+/// of them, and so is a file matching again after it changed.
+///
+pub struct Syslog {
+    socket: Socket,
+    journald: bool,
+    hostname: String,
+    /// the mtime and fields a file was last sent with, by `abs_path`
+    sent: Mutex<HashMap<String, Sent>>,
+}
+
+impl Syslog {
+    /// Connect to `target`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the socket can't be connected to
+    pub fn connect(target: &Target) -> Result<Self> {
+        let socket = match target {
+            Target::Udp(addr) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket
+                    .connect(addr)
+                    .with_context(|| format!("cannot connect to syslog at '{}'", addr))?;
+                Socket::Udp(socket)
+            }
+            #[cfg(unix)]
+            Target::Unix(path) => Socket::Unix(unix_socket(path)?),
+            #[cfg(unix)]
+            Target::Journald => Socket::Unix(unix_socket(&PathBuf::from(JOURNALD_SOCKET))?),
+            #[cfg(not(unix))]
+            Target::Unix(_) | Target::Journald => {
+                bail!("local syslog and journald sockets are only on unix")
+            }
+        };
+        // RFC 5424 goes by the FQDN or the hostname, and `-` when unknown
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+            .map(|name| name.trim().to_string())
+            .ok()
+            .filter(|name| !name.is_empty() && name.is_ascii())
+            .unwrap_or_else(|| "-".to_string());
+        Ok(Self {
+            socket,
+            journald: *target == Target::Journald,
+            hostname,
+            sent: Mutex::default(),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn unix_socket(path: &PathBuf) -> Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket
+        .connect(path)
+        .with_context(|| format!("cannot connect to '{}'", path.display()))?;
+    Ok(socket)
+}
+
+impl Observer for Syslog {
+    fn on_file_done(&self, file: &File) {
+        let mut record = Record {
+            file,
+            fields: BTreeSet::new(),
+            rules: BTreeSet::new(),
+            severity: None,
+        };
+        for (field, m) in file.matches().into_iter().filter(|(_, m)| m.is_match) {
+            record.fields.insert(field);
+            record.rules.extend(
+                m.by.iter()
+                    .filter(|(_, matched)| **matched)
+                    .map(|(rule, _)| rule.clone()),
+            );
+            record.severity = record
+                .severity
+                .max(m.rules.iter().filter_map(|rule| rule.severity).max());
+        }
+        if record.fields.is_empty() {
+            return;
+        }
+        {
+            let Ok(mut sent) = self.sent.lock() else {
+                return;
+            };
+            let (mtime, fields) = sent.entry(file.abs_path.clone()).or_default();
+            if *mtime == file.mtime && record.fields.is_subset(fields) {
+                return;
+            }
+            *mtime = file.mtime;
+            fields.clone_from(&record.fields);
+        }
+        let bytes = if self.journald {
+            record.to_journald()
+        } else {
+            record.to_syslog(&self.hostname)
+        };
+        if let Err(err) = self.socket.send(&bytes) {
+            info!("cannot send match of '{}': {}", file.path, err);
+        }
+    }
+}
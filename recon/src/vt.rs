@@ -0,0 +1,129 @@
+use crate::config::VtLookupOptions;
+use crate::data::VtVerdict;
+use crate::db::Db;
+use anyhow::{bail, Context, Result};
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use sqlx::types::Json;
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+use tracing::{info, warn};
+
+/// `Duration::from_mins` is too recent for the toolchains we build with
+const SECS_PER_MINUTE: u64 = 60;
+
+///
+/// A VT API client, keeping to a request rate
+///
+pub struct Client {
+    http: reqwest::Client,
+    url: String,
+    key: String,
+    interval: Duration,
+    next_request: Instant,
+}
+
+impl Client {
+    /// # Errors
+    ///
+    /// This function will return an error if the API key is not set
+    pub fn new(opts: &VtLookupOptions) -> Result<Self> {
+        let key = std::env::var(opts.api_key_env()).with_context(|| {
+            format!(
+                "vt_lookup needs a VirusTotal API key in '{}'",
+                opts.api_key_env()
+            )
+        })?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url: opts.url().trim_end_matches('/').to_string(),
+            key,
+            interval: Duration::from_secs(SECS_PER_MINUTE) / opts.requests_per_minute(),
+            next_request: Instant::now(),
+        })
+    }
+
+    /// Look up a file by its sha256, waiting as needed to keep to the request rate. Returns
+    /// `None` once the quota is used up.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request failed
+    pub async fn file(&mut self, sha256: &str) -> Result<Option<VtVerdict>> {
+        sleep_until(self.next_request).await;
+        self.next_request = Instant::now() + self.interval;
+
+        let res = self
+            .http
+            .get(format!("{}/files/{}", self.url, sha256))
+            .header("x-apikey", &self.key)
+            .send()
+            .await
+            .context("cannot reach VirusTotal")?;
+        match res.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(Some(VtVerdict::default())),
+            reqwest::StatusCode::TOO_MANY_REQUESTS => Ok(None),
+            status if !status.is_success() => bail!("VirusTotal lookup failed: {}", status),
+            _ => Ok(Some(verdict(sha256, &res.json::<Value>().await?))),
+        }
+    }
+}
+
+/// The verdict from a file report
+fn verdict(sha256: &str, report: &Value) -> VtVerdict {
+    let attrs = &report["data"]["attributes"];
+    let stats = &attrs["last_analysis_stats"];
+    let count = |name: &str| stats[name].as_u64().and_then(|n| u32::try_from(n).ok());
+    VtVerdict {
+        found: true,
+        malicious: count("malicious"),
+        suspicious: count("suspicious"),
+        engines: stats.as_object().map(|stats| {
+            stats
+                .values()
+                .filter_map(Value::as_u64)
+                .map(|n| u32::try_from(n).unwrap_or(u32::MAX))
+                .fold(0, u32::saturating_add)
+        }),
+        first_seen: attrs["first_submission_date"]
+            .as_i64()
+            .and_then(|t| Utc.timestamp_opt(t, 0).single())
+            .map(|t| t.to_rfc3339()),
+        link: Some(format!("https://www.virustotal.com/gui/file/{}", sha256)),
+    }
+}
+
+/// Look up files of the current run without a verdict, from the cache when fresh enough, and
+/// store the verdicts. Returns how many digests were looked up on VT. Once the quota is used
+/// up, the files left are looked up on a later run.
+///
+/// # Errors
+///
+/// This function will return an error on db or lookup failure
+pub async fn lookup_and_store(opts: &VtLookupOptions, db: &Db) -> Result<usize> {
+    let missing = db.missing_vt_lookups().await?;
+    if missing.is_empty() {
+        return Ok(0);
+    }
+    let max_age = chrono::Duration::days(i64::from(opts.cache_days()));
+    let mut client = None;
+    let mut looked_up = 0;
+    for sha256 in missing {
+        if let Some(verdict) = db.cached_vt_verdict(&sha256, max_age).await? {
+            db.store_vt_verdict(&sha256, &verdict, false).await?;
+            continue;
+        }
+        let client = match &mut client {
+            Some(client) => client,
+            None => client.insert(Client::new(opts)?),
+        };
+        info!("vt_lookup: {}", sha256);
+        let Some(verdict) = client.file(&sha256).await? else {
+            warn!("VirusTotal quota exceeded, lookups continue on the next run");
+            break;
+        };
+        db.store_vt_verdict(&sha256, &Json(verdict), true).await?;
+        looked_up += 1;
+    }
+    Ok(looked_up)
+}
@@ -1,64 +1,506 @@
 #![allow(clippy::struct_excessive_bools)]
-use crate::config::ComputedFields;
+use crate::archive;
+use crate::bundle;
+use crate::config::{
+    self, ChunksOptions, ComputedFields, DbOptions, FieldOptions, FullTextOptions, Source, Stage,
+    UnpackOptions,
+};
 use crate::data::File;
-use crate::db::Db;
+use crate::db::{self, Db};
+use crate::git;
+use crate::matching;
+use crate::metrics::Metrics;
+use crate::minhash;
+use crate::observer::{Observer, Progress, Silent, Spinner};
+use crate::osv;
+use crate::processing;
+use crate::s3;
+use crate::schedule::Scheduler;
+use crate::scripting;
+use crate::server;
+use crate::suppress::{self, Suppressions};
+use crate::syslog::{self, Syslog};
+use crate::vt;
 use crate::{config::Config, data};
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use ignore::WalkBuilder;
-use indicatif::{ProgressBar, ProgressStyle};
+use notify::{Event, RecursiveMode, Watcher};
+use sqlx::types::Json;
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::Path;
-use std::time::Duration;
+use std::io::{self, BufRead, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_util::sync::{CancellationToken, DropGuard};
 use tracing::info;
 
 /// Holds options and configuration for a recon run
 pub struct RunOptions {
     pub root: Option<String>,
-    pub config: Option<String>,
+    pub files_from: Option<String>,
+    /// config files or URLs, merged in order with later ones overriding earlier ones
+    pub config: Vec<String>,
+    /// a profile of the config to apply
+    pub profile: Option<String>,
     pub db_url: Option<String>,
     pub db_file: String,
     pub pre_delete: bool,
+    /// whether to walk and query, or only one of them
+    pub mode: Mode,
     pub update: bool,
-    pub all_files: bool,
+    pub ignores: Ignores,
+    /// don't walk into other filesystems mounted under the root
+    pub one_file_system: bool,
+    /// walk into symlinked folders, and read symlinked files
+    pub follow_symlinks: bool,
+    /// compute fields of a random subset of the walked files only
+    pub sample: Option<Sample>,
+    /// report what a scan would go through instead of scanning, see `dry_run`
+    pub dry_run: bool,
+    /// bounds of the walk, overriding the config's
+    pub max_depth: Option<usize>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// globs to leave out of the walk, on top of the config's
+    pub exclude: Vec<String>,
     pub no_spinner: bool,
+    /// receives scan events, in place of the spinner
+    pub observer: Option<Box<dyn Observer>>,
+    /// stops the scan once cancelled, with results marked as incomplete. Without one,
+    /// Ctrl-C does
+    pub cancel: Option<CancellationToken>,
     pub query: Option<String>,
+    pub watch: bool,
+    /// serve Prometheus metrics of the scan and watching on this address
+    pub metrics: Option<SocketAddr>,
+    /// send a record per matched file to syslog or journald
+    pub syslog: Option<syslog::Target>,
+    pub prune: bool,
+    pub force: bool,
+    /// stop at the first file or field that fails, instead of recording it and moving on
+    pub strict: bool,
+    pub baseline: Option<Baseline>,
+    pub dupes: Option<Dupes>,
+    pub similar: Option<Similar>,
+    pub overlap: Option<Overlap>,
+    /// match this regex against the content of text files, and report matching lines
+    pub grep: Option<String>,
+    /// write the rows of the query here as JSON lines as they're read, rather than
+    /// collecting them into the returned table, which is then left without rows
+    pub jsonl: Option<tokio::sync::Mutex<Box<dyn io::Write + Send>>>,
 }
 
+/// What a run does: walk files and update the DB, query it, or both
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Walk on the first run (when there's no DB yet) or with `update`, then query
+    #[default]
+    Auto,
+    /// Walk and update the DB, then report the run instead of querying
+    Scan,
+    /// Query the DB as it is, never walking
+    Query,
+    /// Walk and update the DB, then query
+    Run,
+}
+
+/// Integrity baseline actions. Both walk and hash files before acting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Baseline {
+    /// Trust the current state of files
+    Create,
+    /// Report files which drifted from the trusted state
+    Verify,
+}
+
+/// Digests files can be grouped by to find duplicates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Digest {
+    Sha256,
+    Sha512,
+    Md5,
+    Crc32,
+    /// content with line endings and trailing whitespace normalized, see `norm_hash`
+    NormHash,
+}
+
+impl Digest {
+    #[must_use]
+    pub const fn column(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Md5 => "md5",
+            Self::Crc32 => "crc32",
+            Self::NormHash => "norm_hash",
+        }
+    }
+
+    /// Whether files with the same digest have the same bytes, and so the same size
+    #[must_use]
+    pub const fn is_exact(self) -> bool {
+        !matches!(self, Self::NormHash)
+    }
+
+    /// Whether files with the same digest can be taken as copies to act on: crc32 and md5
+    /// collide too easily, and a normalized hash isn't of the bytes at all
+    #[must_use]
+    pub const fn is_safe_to_act_on(self) -> bool {
+        matches!(self, Self::Sha256 | Self::Sha512)
+    }
+
+    fn enable(self, fields: &mut ComputedFields) {
+        let field = match self {
+            Self::Sha256 => &mut fields.sha256,
+            Self::Sha512 => &mut fields.sha512,
+            Self::Md5 => &mut fields.md5,
+            Self::Crc32 => &mut fields.crc32,
+            Self::NormHash => &mut fields.norm_hash,
+        };
+        *field = Some(FieldOptions::default());
+    }
+}
+
+/// What to do with duplicates, other than the first of each set (by path)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DupesAction {
+    /// Replace duplicates with hard links to the kept file
+    Hardlink,
+    /// Remove duplicates
+    Delete,
+}
+
+/// Finding duplicate files
+#[derive(Clone, Debug)]
+pub struct Dupes {
+    pub by: Digest,
+    /// ignore files smaller than this many bytes
+    pub min_size: u64,
+    pub action: Option<DupesAction>,
+    /// report actions without taking them
+    pub dry_run: bool,
+}
+
+/// Which ignore rules the walk honors, and which files it leaves out on its own.
+/// `.reconignore` files are always honored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ignores {
+    /// `.gitignore` files in the tree
+    pub gitignore: bool,
+    /// the global gitignore, from git's `core.excludesFile`
+    pub global_gitignore: bool,
+    /// `.git/info/exclude` of the repository
+    pub git_exclude: bool,
+    /// leave out `.git` folders
+    pub skip_git_dir: bool,
+    /// walk hidden files and folders
+    pub hidden: bool,
+}
+
+impl Default for Ignores {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            global_gitignore: true,
+            git_exclude: true,
+            skip_git_dir: false,
+            hidden: true,
+        }
+    }
+}
+
+impl Ignores {
+    /// Walk all files, honoring none of git's ignore rules
+    #[must_use]
+    pub fn all() -> Self {
+        Self {
+            gitignore: false,
+            global_gitignore: false,
+            git_exclude: false,
+            ..Self::default()
+        }
+    }
+}
+
+/// A random subset of the walked files to compute fields of, for a quick picture of a
+/// large tree. Every file is still stored with its default fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sample {
+    /// this share of the files, from 0 to 100
+    Percent(f64),
+    /// this many files
+    Count(u64),
+}
+
+impl Sample {
+    /// How many of `total` files to pick, rounding a share up
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn size(self, total: u64) -> u64 {
+        match self {
+            Self::Percent(percent) => ((total as f64 * percent / 100.0).ceil() as u64).min(total),
+            Self::Count(count) => count.min(total),
+        }
+    }
+}
+
+/// Clustering near duplicate text files
+#[derive(Clone, Debug)]
+pub struct Similar {
+    /// how similar files in a cluster are at least, from 0 to 1, as the estimated share
+    /// of word shingles they have in common
+    pub threshold: f64,
+}
+
+/// Finding files which share much of their content, by their chunks
+#[derive(Clone, Debug)]
+pub struct Overlap {
+    /// the share of the smaller file of a pair found in the other, from 0 to 1
+    pub threshold: f64,
+}
+
+/// Files which were added, removed, or drifted in content, mode or ownership since the baseline
+const BASELINE_DRIFT_SQL: &str = r"
+select b.path, 'modified' as change,
+  trim(
+    (case when f.sha256 is not b.sha256 then 'sha256 ' else '' end) ||
+    (case when f.mode is not b.mode then 'mode ' else '' end) ||
+    (case when f.uid is not b.uid or f.gid is not b.gid then 'owner' else '' end)
+  ) as drift
+from baseline b join files f on f.abs_path = b.abs_path
+where f.run_id = (select max(id) from runs)
+  and (f.sha256 is not b.sha256 or f.mode is not b.mode or f.uid is not b.uid or f.gid is not b.gid)
+union all
+select b.path, 'removed', null from baseline b
+where not exists (
+  select 1 from files f where f.abs_path = b.abs_path and f.run_id = (select max(id) from runs)
+)
+union all
+select f.path, 'added', null from files f
+where f.run_id = (select max(id) from runs)
+  and not exists (select 1 from baseline b where b.abs_path = f.abs_path)
+order by 1
+";
+
+/// The latest run, reported by a scan in place of query results
+const LAST_RUN_SQL: &str =
+    "select id as run, root, files, started_at, finished_at from runs order by id desc limit 1";
+
+/// Lines of files matching the `recon grep` pattern, from their `grep_match`. A line
+/// with several matches is shown once, by its first
+const GREP_SQL: &str = r#"
+select path, line, "match" from (
+  select f.path, json_extract(m.value, '$.line') as line,
+    json_extract(m.value, '$.snippet') as "match", min(json_extract(m.value, '$.offset')) as offset
+  from files f, json_each(f.grep_match, '$.details.matches') m
+  where json_extract(f.grep_match, '$.is_match')
+  group by f.path, line
+)
+order by path, offset
+"#;
+
 /// Run a recon workflow with given options
 ///
 /// # Errors
 ///
 /// This function will return an error if db, I/O or processing failures occcured
 pub async fn run(opts: &RunOptions) -> Result<data::ValuesTable> {
-    let config = opts
-        .config
-        .as_ref()
-        .map_or_else(
-            || Ok(Config::default()),
-            |c| Config::from_path(Path::new(&c)),
-        )
-        .context("cannot load configuration")?;
+    let mut table = None;
+    Box::pin(run_with(opts, |vt| {
+        table = Some(vt);
+        Ok(())
+    }))
+    .await?;
+    Ok(table.unwrap_or_default())
+}
+
+/// Run a recon workflow with given options, handing query results to `on_result`.
+/// When watching, keeps running and hands over fresh results after every batch of changes.
+///
+/// # Errors
+///
+/// This function will return an error if db, I/O or processing failures occcured,
+/// or if `on_result` fails
+pub async fn run_with<F>(opts: &RunOptions, on_result: F) -> Result<()>
+where
+    F: FnMut(data::ValuesTable) -> Result<()>,
+{
+    Box::pin(run_inner(opts, None, false, on_result)).await?;
+    Ok(())
+}
+
+/// Run a recon workflow with given options, querying with the config's named queries
+/// (`names` of them, or all of them when empty) for a result set each, by name
+///
+/// # Errors
+///
+/// This function will return an error if a query isn't in the config, if db, I/O or
+/// processing failures occcured, or when watching or on a dry run, which have no named
+/// results
+pub async fn run_queries(
+    opts: &RunOptions,
+    names: &[String],
+) -> Result<Vec<(String, data::ValuesTable)>> {
+    if opts.watch || opts.dry_run {
+        bail!("named queries can't be watched or dry run");
+    }
+    Box::pin(run_inner(opts, Some(names), false, |_| Ok(()))).await
+}
+
+/// A scheduled re-scan while serving: an update, which never deletes the DB first,
+/// nor queries or watches after
+async fn rescan(opts: &RunOptions) -> Result<()> {
+    Box::pin(run_inner(opts, None, true, |_| Ok(()))).await?;
+    Ok(())
+}
+
+/// Runs the workflow, querying with the given named queries in place of one, for their
+/// results by name
+async fn run_inner<F>(
+    opts: &RunOptions,
+    names: Option<&[String]>,
+    rescan: bool,
+    mut on_result: F,
+) -> Result<Vec<(String, data::ValuesTable)>>
+where
+    F: FnMut(data::ValuesTable) -> Result<()>,
+{
+    let update = opts.update || rescan || matches!(opts.mode, Mode::Scan | Mode::Run);
+    let mut config = Config::from_sources(
+        &opts.config.iter().map(String::as_str).collect::<Vec<_>>(),
+        opts.profile.as_deref(),
+    )
+    .context("cannot load configuration")?;
+    let named_queries = names
+        .map(|names| config.source.named_queries(names))
+        .transpose()?;
+    if let Some(dupes) = &opts.dupes {
+        if dupes.action.is_some() && !dupes.by.is_safe_to_act_on() {
+            bail!(
+                "files with the same {} may differ in bytes, they can't be acted on",
+                dupes.by.column()
+            );
+        }
+        dupes.by.enable(
+            config
+                .source
+                .computed_fields
+                .get_or_insert_with(ComputedFields::default),
+        );
+    }
+    if let Some(similar) = &opts.similar {
+        if !(similar.threshold > 0.0 && similar.threshold <= 1.0) {
+            bail!("threshold should be above 0, and at most 1");
+        }
+        config
+            .source
+            .computed_fields
+            .get_or_insert_with(ComputedFields::default)
+            .minhash = Some(FieldOptions::default());
+    }
+    if let Some(overlap) = &opts.overlap {
+        if !(overlap.threshold > 0.0 && overlap.threshold <= 1.0) {
+            bail!("threshold should be above 0, and at most 1");
+        }
+        config
+            .source
+            .computed_fields
+            .get_or_insert_with(ComputedFields::default)
+            .chunks
+            .get_or_insert_with(ChunksOptions::default);
+    }
+    if opts.strict {
+        for fields in [
+            &mut config.source.default_fields,
+            &mut config.source.computed_fields,
+        ] {
+            fields.get_or_insert_with(ComputedFields::default).strict = true;
+        }
+    }
+    config.source.max_depth = opts.max_depth.or(config.source.max_depth);
+    config.source.min_size = opts.min_size.or(config.source.min_size);
+    config.source.max_size = opts.max_size.or(config.source.max_size);
+    if !opts.exclude.is_empty() {
+        config
+            .source
+            .exclude
+            .get_or_insert_with(Vec::new)
+            .extend(opts.exclude.iter().cloned());
+    }
+    if opts.baseline.is_some() {
+        // a baseline is all about content, make sure it's hashed
+        config
+            .source
+            .computed_fields
+            .get_or_insert_with(ComputedFields::default)
+            .sha256 = Some(FieldOptions::default());
+    }
     let default_root = ".".to_string();
     let root = opts
         .root
         .as_ref()
         .or(config.source.root.as_ref())
         .unwrap_or(&default_root);
+
+    // an explicit list of paths replaces walking altogether
+    let paths = opts
+        .files_from
+        .as_ref()
+        .map(|from| read_paths(from))
+        .transpose()
+        .context("cannot read list of files")?;
+
+    if opts.dry_run {
+        on_result(dry_run(root, paths.as_deref(), &config.source, opts)?)?;
+        return Ok(vec![]);
+    }
+
+    let no_db = || !Path::new(&opts.db_file).exists() || opts.db_file == ":memory:";
+    if opts.mode == Mode::Query && opts.db_url.is_none() && no_db() {
+        bail!(
+            "no DB to query at '{}', scan first with `recon scan`",
+            opts.db_file
+        );
+    }
+
     // file or url handling
     // if they give us a complete URL, that wins over all.
     let db_url = opts.db_url.as_ref().map_or_else(
         || {
             // otherwise, get a default file name, and handle a pre-run delete
             let file = &opts.db_file;
-            if opts.pre_delete {
+            if opts.pre_delete && !rescan {
                 info!("removing existing db");
-                let _res = fs::remove_file(file);
+                for suffix in ["", "-wal", "-shm"] {
+                    let _res = fs::remove_file(format!("{}{}", file, suffix));
+                }
             }
             format!("sqlite:{}?mode=rwc", file)
         },
         Clone::clone,
     );
-    let first_run = !Path::new(&opts.db_file).exists() || opts.db_file == ":memory:";
+    // a query only run never walks, even into an empty DB
+    let first_run = opts.mode != Mode::Query && no_db();
+
+    // a git remote is cloned and walked like a local folder, only when we're about to walk
+    let cloned = if (first_run || update) && git::is_remote(root) {
+        info!("cloning {}", root);
+        Some(git::clone(root)?)
+    } else {
+        None
+    };
+    let cloned_root = cloned
+        .as_ref()
+        .map(|c| c.dir.path().to_string_lossy().to_string());
+    let root = cloned_root.as_ref().unwrap_or(root);
 
     /*
     1. fork: consider wrapping under "Db" struct which acquires a pool, etc.
@@ -70,154 +512,1956 @@ pub async fn run(opts: &RunOptions) -> Result<data::ValuesTable> {
 
     2. add a seaorm conn here
     */
-    let db = Db::connect(&db_url).await?;
+    let mut db = Db::connect(&db_url, &config.db.clone().unwrap_or_default()).await?;
 
     let source = &config.source;
 
     info!("db: {}", db_url);
 
+    let suppressions = Suppressions::load(source.suppress_file.as_deref())?;
+
+    // extracted archive members must stay on disk until their fields are computed
+    let mut temp_dirs = vec![];
+    let spinner = Spinner::default();
+    let observer: &dyn Observer = match &opts.observer {
+        Some(observer) => observer.as_ref(),
+        None if opts.no_spinner => &Silent,
+        None => &spinner,
+    };
+    let scheduler = if opts.watch && !rescan {
+        source
+            .schedule()?
+            .map(|schedule| Arc::new(Scheduler::new(schedule)))
+    } else {
+        None
+    };
+    let metrics = if rescan {
+        None
+    } else {
+        opts.metrics
+            .map(|addr| serve_metrics(addr, scheduler.clone()))
+            .transpose()?
+    };
+    let sink = opts.syslog.as_ref().map(Syslog::connect).transpose()?;
+    let observers = Observers(
+        [
+            Some(observer),
+            metrics
+                .as_ref()
+                .map(|(metrics, _)| metrics.as_ref() as &dyn Observer),
+            sink.as_ref().map(|sink| sink as &dyn Observer),
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+    );
+    let observer: &dyn Observer = &observers;
+    if !rescan {
+        for expired in suppressions.expired() {
+            observer.on_warning(&expired);
+        }
+    }
+    let cancel = opts.cancel.clone().unwrap_or_default();
+    let _ctrl_c = (opts.cancel.is_none()
+        && (first_run || update || opts.watch || opts.grep.is_some()))
+    .then(|| cancel_on_ctrl_c(cancel.clone()));
+    let scan = Scan {
+        root,
+        paths: paths.as_deref(),
+        source,
+        // a baseline can't trust cached rows: mode and owner changes leave size and mtime as-is
+        resume: !first_run && !opts.force && opts.baseline.is_none(),
+        // a clone's own history is not part of what was cloned
+        ignores: Ignores {
+            skip_git_dir: opts.ignores.skip_git_dir || cloned.is_some(),
+            ..opts.ignores
+        },
+        one_file_system: opts.one_file_system,
+        follow_symlinks: opts.follow_symlinks,
+        sample: opts.sample,
+        prune: opts.prune || source.prune.unwrap_or_default(),
+        observer,
+        cancel: &cancel,
+        cloned: cloned.as_ref(),
+    };
+
     //
     // prefill stage -----------
     //
-    if first_run {
-        info!("updating data. first run.",);
-        db.clear().await?;
-        let s = spin(opts.no_spinner);
-        walk_and_store(
-            root,
-            &source.default_fields(),
-            false,
-            opts.all_files,
-            &s,
-            &db,
-        )
-        .await?;
-        s.finish_and_clear();
-    } else if opts.update {
-        let s = spin(opts.no_spinner);
-        walk_and_store(
-            root,
-            &source.default_fields(),
-            true,
-            opts.all_files,
-            &s,
-            &db,
-        )
-        .await?;
-        s.finish_and_clear();
+    let started = Instant::now();
+    git::forget_history();
+    if first_run || update {
+        if first_run {
+            info!("updating data. first run.",);
+            db.clear().await?;
+        }
+        let run_id = db
+            .start_run(cloned.as_ref().map_or(root, |c| &c.remote))
+            .await?;
+        info!("run: {}", run_id);
+        temp_dirs = scan.store(&db).await?;
+    }
+
+    // created after walking, so that inserting files doesn't pay for maintaining them
+    for columns in config.db.iter().flat_map(|db| db.indexes.iter().flatten()) {
+        db.create_index(columns).await?;
     }
 
     //
     // query stage -----------
     //
-    if first_run || opts.update {
-        let res: Vec<data::File> = db.query_files(&source.query()).await?;
-        let s = spin(opts.no_spinner);
-        // xxx extract this out to workflow
-        compute_fields_and_store(&res[..], &source.computed_fields(), &s, &db).await?;
-        s.finish_and_clear();
+    let mut incomplete = false;
+    if first_run || update {
+        if !cancel.is_cancelled() {
+            if let Some(dupes) = &opts.dupes {
+                // files indexed before without this digest get it now
+                db.recompute_missing(dupes.by.column()).await?;
+            }
+            if opts.similar.is_some() {
+                db.recompute_missing("minhash").await?;
+            }
+            Box::pin(scan.compute(&db)).await?;
+        }
+        let scan_errors = db.scan_errors().await?;
+        if scan_errors > 0 {
+            observer.on_warning(&format!(
+                "{} files could not be scanned, see the `scan_errors` table",
+                scan_errors
+            ));
+        }
+        incomplete = cancel.is_cancelled();
+        if incomplete {
+            db.interrupt_run().await?;
+        } else {
+            db.finish_run().await?;
+        }
+    }
+    if let Some(pattern) = &opts.grep {
+        if !cancel.is_cancelled() {
+            Box::pin(grep_and_store(
+                pattern,
+                &source.computed_fields(),
+                observer,
+                &cancel,
+                &db,
+            ))
+            .await?;
+        }
+        incomplete |= cancel.is_cancelled();
+    }
+    suppress::apply(&db, &suppressions).await?;
+    if let Some((metrics, _)) = &metrics {
+        if first_run || update || opts.grep.is_some() {
+            metrics.scanned(started.elapsed());
+        }
+    }
+    drop(temp_dirs);
+    if rescan {
+        return Ok(vec![]);
+    }
+    // baselines, duplicates, clusters and grep are wrong when going by some of the files
+    if incomplete
+        && (opts.baseline.is_some()
+            || opts.dupes.is_some()
+            || opts.similar.is_some()
+            || opts.overlap.is_some()
+            || opts.grep.is_some())
+    {
+        bail!(INTERRUPTED);
+    }
+
+    if let Some(named_queries) = named_queries {
+        let mut tables = vec![];
+        for (name, query) in named_queries {
+            let mut table = db.query_table(&query).await?;
+            table.incomplete = incomplete;
+            tables.push((name, table));
+        }
+        return Ok(tables);
     }
 
     let default_query = "select * from files".to_string();
-    let query = config
-        .source
-        .query
+    let dupes_query = opts
+        .dupes
         .as_ref()
-        .or(opts.query.as_ref())
-        .unwrap_or(&default_query);
+        .map(dupes_query)
+        .or_else(|| opts.overlap.as_ref().map(overlap_query));
+    let query = match opts.baseline {
+        Some(Baseline::Create) => {
+            let count = db.create_baseline().await?;
+            info!("baseline: {} files", count);
+            "select path, sha256, mode, user, \"group\" from baseline order by path"
+        }
+        Some(Baseline::Verify) => {
+            if !db.has_baseline().await? {
+                bail!("no baseline found, create one first with `recon baseline create`");
+            }
+            BASELINE_DRIFT_SQL
+        }
+        None if opts.grep.is_some() => GREP_SQL,
+        None if opts.mode == Mode::Scan => LAST_RUN_SQL,
+        // a query given on the command line wins over the config's
+        None => dupes_query
+            .as_ref()
+            .or(opts.query.as_ref())
+            .or(config.source.query.as_ref())
+            .unwrap_or(&default_query),
+    };
+
+    let mut table = match &opts.dupes {
+        Some(
+            dupes @ Dupes {
+                action: Some(action),
+                ..
+            },
+        ) => act_on_dupes(&db, dupes, *action).await?,
+        _ => match (&opts.similar, &opts.jsonl) {
+            (Some(similar), _) => similar_table(&db, similar).await?,
+            // rows of an incomplete scan aren't written, as they wouldn't be returned
+            (None, Some(out)) if !incomplete => {
+                let written = db.query_jsonl(query, &mut **out.lock().await).await?;
+                info!("jsonl: {} rows", written);
+                data::ValuesTable::default()
+            }
+            (None, _) => db.query_table(query).await?,
+        },
+    };
+    table.incomplete = incomplete;
+    on_result(table)?;
+
+    if opts.watch && !incomplete {
+        if cloned.is_some() || paths.is_some() || !Path::new(root).is_dir() {
+            bail!("watching needs a local folder as root");
+        }
+        let mut watch = Watch {
+            root,
+            db_file: &opts.db_file,
+            query,
+            db: &mut db,
+            observer,
+            metrics: metrics.as_ref().map(|(metrics, _)| metrics.as_ref()),
+            scan: Scan {
+                resume: true,
+                ..scan
+            },
+            scheduler: scheduler.as_deref(),
+            suppressions: &suppressions,
+            cancel: &cancel,
+        };
+        Box::pin(watch.run(source, &mut on_result)).await?;
+    }
+    Ok(vec![])
+}
 
-    db.query_table(query).await
+/// The walk and compute stages of a run, shared by the CLI and `Recon`
+pub(crate) struct Scan<'a> {
+    pub root: &'a str,
+    /// store exactly these files, instead of walking `root`
+    pub paths: Option<&'a [String]>,
+    pub source: &'a Source,
+    /// skip files which are already stored and unchanged
+    pub resume: bool,
+    pub ignores: Ignores,
+    pub one_file_system: bool,
+    pub follow_symlinks: bool,
+    pub sample: Option<Sample>,
+    pub prune: bool,
+    pub observer: &'a dyn Observer,
+    /// stops walking and computing after the files in flight
+    pub cancel: &'a CancellationToken,
+    /// the repository `root` was cloned from, to address its files by
+    pub cloned: Option<&'a git::Cloned>,
 }
 
-/// For a given path, walk a directory tree, and for each file
-/// fill in computed fields.
-/// Lastly, store results in DB.
-/// Later, you can query results back to get a vector of `File`s.
+impl Scan<'_> {
+    /// Store files with their default fields, returning the temporary folders archive
+    /// members were extracted to, to be removed when dropped. Stops early once cancelled.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O, processing or db failure
+    pub(crate) async fn store(&self, db: &Db) -> Result<Vec<TempDir>> {
+        let fields = self.source.default_fields();
+        let temp_dirs = if let Some(paths) = self.paths {
+            Box::pin(self.list_and_store(paths, &fields, db)).await?
+        } else {
+            Box::pin(self.walk_and_store(&fields, db)).await?
+        };
+        self.observer.on_progress(Progress::Finished);
+
+        // pruning needs to have seen every file
+        if self.prune && !self.cancel.is_cancelled() {
+            let pruned = prune(db).await?;
+            info!("pruned: {} files", pruned);
+        }
+        if let Some(sample) = self.sample {
+            let sampled = db.sample(|total| sample.size(total)).await?;
+            info!("sampled: {} files", sampled);
+        } else {
+            db.clear_sample().await?;
+        }
+        Ok(temp_dirs)
+    }
+
+    /// Would walking `root` reach `path`, a file under it named the way walking names
+    /// files. Only the folders leading to it are listed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the include or exclude globs are invalid
+    fn walks_to(&self, path: &Path) -> Result<bool> {
+        let mut walk = walk(
+            self.root,
+            self.ignores,
+            self.one_file_system,
+            self.follow_symlinks,
+            self.source,
+            Some(path),
+        )?;
+        Ok(walk.any(|entry| entry.is_ok_and(|entry| entry.path() == path)))
+    }
+
+    /// Compute fields of the files picked by the source query, then those of each stage,
+    /// then run lookups. Stops early once cancelled, leaving lookups to the run which
+    /// completes the scan.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O, processing or db failure
+    pub(crate) async fn compute(&self, db: &Db) -> Result<()> {
+        let res = self.sampled(db.query_files(&self.source.query()).await?);
+        compute_fields_and_store(
+            &res[..],
+            &self.source.computed_fields(),
+            0,
+            self.observer,
+            self.cancel,
+            db,
+        )
+        .await?;
+        self.observer.on_progress(Progress::Finished);
+        for (nth, stage) in (1..).zip(self.source.stages()) {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let res = self.sampled(
+                db.query_files(&stage.query(nth))
+                    .await
+                    .with_context(|| format!("{}.where", stage.label(nth)))?,
+            );
+            compute_fields_and_store(&res[..], &stage.fields, nth, self.observer, self.cancel, db)
+                .await?;
+            self.observer.on_progress(Progress::Finished);
+        }
+        if let Some(full_text) = &self.source.full_text {
+            let res = self.sampled(db.query_files(&full_text.query()).await?);
+            let fields = self.source.computed_fields();
+            index_contents(&res[..], &fields, self.observer, self.cancel, db).await?;
+            self.observer.on_progress(Progress::Finished);
+        }
+        if let Some(chunks) = &self.source.computed_fields().chunks {
+            let res = self.sampled(
+                db.query_files("select * from files where chunks is null and size > 0")
+                    .await?,
+            );
+            chunk_and_store(&res[..], chunks, self.observer, self.cancel, db).await?;
+            self.observer.on_progress(Progress::Finished);
+        }
+        if self.cancel.is_cancelled() {
+            return Ok(());
+        }
+        if let Some(vt_lookup) = &self.source.computed_fields().vt_lookup {
+            // the files left without a verdict are looked up on a later run
+            match vt::lookup_and_store(vt_lookup, db).await {
+                Ok(looked_up) => info!("vt_lookup: {} digests looked up", looked_up),
+                Err(err) => self.observer.on_warning(&format!("vt_lookup: {:#}", err)),
+            }
+        }
+        if let Some(osv_vulns) = &self.source.computed_fields().osv_vulns {
+            // the files left without vulnerabilities are looked up on a later run
+            match osv::lookup_and_store(osv_vulns, db).await {
+                Ok(looked_up) => info!("osv_vulns: {} files looked up", looked_up),
+                Err(err) => self.observer.on_warning(&format!("osv_vulns: {:#}", err)),
+            }
+        }
+        if self.source.dirs.unwrap_or_default() {
+            let dirs = db.aggregate_dirs().await?;
+            info!("dirs: {} folders", dirs);
+        }
+        Ok(())
+    }
+
+    /// The files to compute fields of out of `files`: with a sample, the sampled ones only
+    fn sampled(&self, files: Vec<File>) -> Vec<File> {
+        if self.sample.is_none() {
+            return files;
+        }
+        files
+            .into_iter()
+            .filter(|f| f.is_sampled == Some(true))
+            .collect()
+    }
+
+    /// For a given path, walk a directory tree, and for each file
+    /// fill in computed fields.
+    /// Lastly, store results in DB.
+    /// Later, you can query results back to get a vector of `File`s.
+    /// Archive members are stored as files too, and the temporary folders they
+    /// were extracted to are returned to the caller, to be removed when dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on folder walking I/O failure, data processing, or database access failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    async fn walk_and_store(
+        &self,
+        fields: &ComputedFields,
+        db: &Db,
+    ) -> anyhow::Result<Vec<TempDir>> {
+        let (path, unpack, resume, o) = (
+            self.root,
+            self.source.unpack.as_ref(),
+            self.resume,
+            self.observer,
+        );
+        if let Some(location) = s3::Location::parse(path) {
+            return self.walk_bucket_and_store(&location, fields, db).await;
+        }
+
+        let mut temp_dirs = vec![];
+        let mut count = 0;
+        let mut cached = 0;
+        o.on_progress(Progress::Walking {
+            files: 0,
+            cached: 0,
+        });
+
+        // an archive given as root is scanned in place of a folder
+        let root = Path::new(path);
+        if root.is_file() && archive::detect(root)?.is_some() {
+            let default_unpack = UnpackOptions::default();
+            let unpack = unpack.unwrap_or(&default_unpack);
+            if let Some(mut unpacked) =
+                archive::unpack(root, unpack).with_context(|| format!("unpack on '{}'", path))?
+            {
+                let members = std::mem::take(&mut unpacked.members);
+                let (report, dir) = unpacked.finish(unpack.keep_temp());
+                for err in &report.errors {
+                    info!("unpack: {}", err);
+                }
+                let abs_path = fs::canonicalize(root)?.to_string_lossy().to_string();
+                count += store_members(members, path, &abs_path, fields, resume, o, db).await?;
+                o.on_progress(Progress::Walking {
+                    files: count as u64,
+                    cached: 0,
+                });
+                temp_dirs.extend(dir);
+            }
+            return Ok(temp_dirs);
+        }
+
+        // symlinks are told whether they lead out of here
+        let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let walk = walk(
+            path,
+            self.ignores,
+            self.one_file_system,
+            self.follow_symlinks,
+            self.source,
+            None,
+        )?;
+        for entry in walk {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = error_path(&err).map_or_else(
+                        || path.to_string(),
+                        |path| path.to_string_lossy().to_string(),
+                    );
+                    scan_error(
+                        db,
+                        fields,
+                        o,
+                        &path,
+                        anyhow::Error::new(err).context("cannot list entry"),
+                    )
+                    .await?;
+                    continue;
+                }
+            };
+            if self.source.dirs_and_links.unwrap_or_default()
+                && (entry.path_is_symlink() || entry.path().is_dir())
+            {
+                let mut f = match data::File::from_dir_or_link(&entry) {
+                    Ok(f) => f,
+                    Err(err) => {
+                        scan_error(db, fields, o, &entry.path().to_string_lossy(), err).await?;
+                        continue;
+                    }
+                };
+                f.check_escape(&root);
+                f.via_symlink = via_symlink(&entry, path, self.follow_symlinks);
+                if let Some(cloned) = self.cloned {
+                    f = f.in_clone(&root, cloned);
+                }
+                if resume && db.exists(&f).await? {
+                    cached += 1;
+                } else {
+                    // nothing to unpack
+                    Box::pin(store_file(f, entry.path(), fields, None, o, db)).await?;
+                }
+                count += 1;
+                o.on_progress(Progress::Walking {
+                    files: count as u64,
+                    cached,
+                });
+            } else if entry.path().is_file()
+                && !db.file().is_some_and(|db| is_db_file(entry.path(), db))
+            {
+                let mut f = match data::File::from_entry(&entry) {
+                    Ok(f) => f,
+                    Err(err) => {
+                        scan_error(db, fields, o, &entry.path().to_string_lossy(), err).await?;
+                        continue;
+                    }
+                };
+                f.check_escape(&root);
+                f.via_symlink = via_symlink(&entry, path, self.follow_symlinks);
+                if let Some(cloned) = self.cloned {
+                    f = f.in_clone(&root, cloned);
+                }
+                if resume && db.exists(&f).await? {
+                    cached += 1;
+                } else {
+                    let (members, dir) =
+                        Box::pin(store_file(f, entry.path(), fields, unpack, o, db)).await?;
+                    count += members;
+                    temp_dirs.extend(dir);
+                }
+                count += 1;
+                o.on_progress(Progress::Walking {
+                    files: count as u64,
+                    cached,
+                });
+            }
+        }
+        Ok(temp_dirs)
+    }
+
+    /// Store exactly the given paths, instead of walking a tree. Paths which are missing
+    /// or are not files are skipped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on I/O failure, data processing, or database access failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    async fn list_and_store(
+        &self,
+        paths: &[String],
+        fields: &ComputedFields,
+        db: &Db,
+    ) -> anyhow::Result<Vec<TempDir>> {
+        let (unpack, resume, o) = (self.source.unpack.as_ref(), self.resume, self.observer);
+        let mut temp_dirs = vec![];
+        let mut count = 0;
+        let mut cached = 0;
+        o.on_progress(Progress::Walking {
+            files: 0,
+            cached: 0,
+        });
+        for path in paths {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let path = Path::new(path);
+            if !path.is_file() {
+                info!("skipping '{}': not a file", path.display());
+                continue;
+            }
+            let f = match data::File::from_path(path) {
+                Ok(f) => f,
+                Err(err) => {
+                    scan_error(db, fields, o, &path.to_string_lossy(), err).await?;
+                    continue;
+                }
+            };
+            if resume && db.exists(&f).await? {
+                cached += 1;
+            } else {
+                let (members, dir) = Box::pin(store_file(f, path, fields, unpack, o, db)).await?;
+                count += members;
+                temp_dirs.extend(dir);
+            }
+            count += 1;
+            o.on_progress(Progress::Walking {
+                files: count as u64,
+                cached,
+            });
+        }
+        Ok(temp_dirs)
+    }
+
+    /// List objects in a bucket and store each as a file with its computed fields,
+    /// downloading its content into a temporary folder only while it is processed.
+    /// Later stages, the full text index and chunks find no content for objects.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error on bucket access, download, data processing, or database access failure
+    #[tracing::instrument(level = "trace", skip_all, err)]
+    async fn walk_bucket_and_store(
+        &self,
+        location: &s3::Location,
+        fields: &ComputedFields,
+        db: &Db,
+    ) -> anyhow::Result<Vec<TempDir>> {
+        let (resume, o) = (self.resume, self.observer);
+        let bucket = location.bucket()?;
+        let objects = s3::list(&bucket, &location.prefix).await?;
+        let dir = tempfile::Builder::new().prefix("recon-s3").tempdir()?;
+        let mut cached = 0;
+        for (count, object) in objects.iter().enumerate() {
+            o.on_progress(Progress::Walking {
+                files: count as u64,
+                cached,
+            });
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let url = format!("s3://{}/{}", location.bucket, object.key);
+            // keys may hold `..` and other surprises, keep only the file name
+            let name = Path::new(&object.key)
+                .file_name()
+                .map_or_else(|| "object".into(), std::ffi::OsStr::to_os_string);
+            let object_dir = dir.path().join(count.to_string());
+            let dest = object_dir.join(name);
+            // size and modification time come from the listing, good enough to tell if it changed
+            let listed = data::File::from_object(&dest, url.clone(), object);
+            if resume && db.exists(&listed).await? {
+                cached += 1;
+                continue;
+            }
+            o.on_file_start(&url);
+            if let Err(err) = s3::fetch(&bucket, &object.key, &dest).await {
+                scan_error(db, fields, o, &url, err).await?;
+                continue;
+            }
+            // objects are computed as they arrive and removed right after, so a bucket
+            // never needs room for more than one of them on disk
+            let mut f = data::File::from_object(&dest, url, object)
+                .process_fields(fields)?
+                .process_fields(&self.source.computed_fields())?;
+            f.computed = Some(true);
+            fs::remove_dir_all(&object_dir)?;
+            db.insert_one(&f).await?;
+            file_done(o, &f);
+        }
+        Ok(vec![dir])
+    }
+}
+
+/// Why a cancelled run can't act on its results
+pub const INTERRUPTED: &str = "interrupted, run again with `--update` to resume the scan";
+
+/// Turn Ctrl-C into cancelling the scan, so it stops after the files in flight and what
+/// was stored so far is kept for the next `--update` to resume from. A second Ctrl-C quits
+/// right away. Stops listening once the returned guard is dropped.
+fn cancel_on_ctrl_c(cancel: CancellationToken) -> DropGuard {
+    let done = CancellationToken::new();
+    let guard = done.clone().drop_guard();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                res = tokio::signal::ctrl_c() => {
+                    if res.is_err() {
+                        break;
+                    }
+                    if cancel.is_cancelled() {
+                        std::process::exit(130);
+                    }
+                    cancel.cancel();
+                }
+                () = done.cancelled() => break,
+            }
+        }
+    });
+    guard
+}
+
+/// Clusters of similar text files by their `minhash`, largest first
+async fn similar_table(db: &Db, similar: &Similar) -> Result<data::ValuesTable> {
+    let (paths, signatures): (Vec<_>, Vec<_>) = db
+        .minhashes()
+        .await?
+        .into_iter()
+        .filter_map(|(path, hex)| Some((path, minhash::from_hex(&hex)?)))
+        .unzip();
+    let rows = minhash::clusters(&signatures, similar.threshold)
+        .into_iter()
+        .map(|(items, lowest)| {
+            let paths = items.iter().map(|&i| &paths[i]).collect::<Vec<_>>();
+            vec![
+                serde_json::Value::from(items.len()),
+                // as estimated from 64 values, more digits would be noise
+                serde_json::Value::from((lowest * 100.0).round() / 100.0),
+                serde_json::Value::String(serde_json::to_string(&paths).unwrap_or_default()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    Ok(data::ValuesTable {
+        columns: vec![
+            "files".to_string(),
+            "similarity".to_string(),
+            "paths".to_string(),
+        ],
+        total_rows: u32::try_from(rows.len()).unwrap_or(u32::MAX),
+        rows,
+        incomplete: false,
+    })
+}
+
+/// Pairs of files sharing at least the threshold of the smaller one's bytes in chunks,
+/// most shared bytes first. A chunk repeated in a file counts once.
+fn overlap_query(overlap: &Overlap) -> String {
+    format!(
+        r"with shared as (
+  select a.file_id as a, b.file_id as b, sum(a.size) as bytes
+  from (select distinct file_id, hash, size from chunks) a
+  join (select distinct file_id, hash, size from chunks) b
+    on a.hash = b.hash and a.file_id < b.file_id
+  group by a.file_id, b.file_id
+)
+select fa.path, fb.path as other, s.bytes as shared,
+  round(1.0 * s.bytes / min(fa.size, fb.size), 2) as share
+from shared s join files fa on fa.id = s.a join files fb on fb.id = s.b
+where 1.0 * s.bytes / min(fa.size, fb.size) >= {threshold}
+order by s.bytes desc, fa.path, fb.path",
+        threshold = overlap.threshold
+    )
+}
+
+/// Sets of files sharing a digest, largest waste first
+fn dupes_query(dupes: &Dupes) -> String {
+    // files with the same normalized content may differ in size, by their line endings
+    let (group, size) = if dupes.by.is_exact() {
+        (", size", "size, (sum(nth = 1) - 1) * size")
+    } else {
+        (
+            "",
+            "max(size) as size, sum(case when nth = 1 then size end) - max(size)",
+        )
+    };
+    // hard links of a file are listed, but aren't copies taking space
+    format!(
+        r"select {col}, sum(nth = 1) as files, {size} as wasted,
+  json_group_array(path) as paths
+from (select *, row_number() over (partition by {col}, {copy} order by path) as nth
+  from files where {col} is not null and size >= {min_size} order by path)
+group by {col}{group} having sum(nth = 1) > 1
+order by wasted desc",
+        col = dupes.by.column(),
+        copy = db::COPY_SQL,
+        min_size = dupes.min_size
+    )
+}
+
+/// Keep the first file of each duplicate set (by path), and hardlink or delete the rest.
+/// A file is only acted on when the kept one is still there with the same bytes, otherwise
+/// it's skipped. Returns a table of what was done (or would be, on a dry run) to each file.
+async fn act_on_dupes(db: &Db, dupes: &Dupes, action: DupesAction) -> Result<data::ValuesTable> {
+    let (verb, done) = match action {
+        DupesAction::Hardlink => ("hardlink", "hardlinked"),
+        DupesAction::Delete => ("delete", "deleted"),
+    };
+    let mut rows = vec![];
+    let mut kept: Option<(String, String, String)> = None;
+    for (digest, abs_path, copy) in db.duplicates(dupes.by.column(), dupes.min_size).await? {
+        let keep = match &kept {
+            // a hard link of the kept file is already what it'd be made
+            Some((kept_digest, _, kept_copy)) if *kept_digest == digest && *kept_copy == copy => {
+                continue;
+            }
+            Some((kept_digest, keep, _)) if *kept_digest == digest => keep.clone(),
+            _ => {
+                kept = Some((digest, abs_path, copy));
+                continue;
+            }
+        };
+        // archive members and bucket objects have no file of their own to act on
+        if abs_path.contains("!/") || !Path::new(&abs_path).is_file() {
+            continue;
+        }
+        // the DB may be behind the disk: never act on a file unless its copy is really there
+        let skipped = if Path::new(&keep).is_file() {
+            (!same_content(Path::new(&keep), Path::new(&abs_path))
+                .with_context(|| format!("cannot compare '{}' to '{}'", abs_path, keep))?)
+            .then_some("skipped, content differs")
+        } else {
+            Some("skipped, kept file is missing")
+        };
+        let outcome = if let Some(skipped) = skipped {
+            skipped.to_string()
+        } else if dupes.dry_run {
+            format!("would {}", verb)
+        } else {
+            match action {
+                DupesAction::Hardlink => hardlink(Path::new(&keep), Path::new(&abs_path)),
+                DupesAction::Delete => fs::remove_file(&abs_path).map_err(Into::into),
+            }
+            .with_context(|| format!("cannot {} '{}'", verb, abs_path))?;
+            if action == DupesAction::Delete {
+                db.delete(&abs_path).await?;
+            }
+            done.to_string()
+        };
+        rows.push(vec![
+            serde_json::Value::String(outcome),
+            serde_json::Value::String(abs_path),
+            serde_json::Value::String(keep),
+        ]);
+    }
+    Ok(data::ValuesTable {
+        columns: vec!["action".to_string(), "path".to_string(), "kept".to_string()],
+        total_rows: u32::try_from(rows.len()).unwrap_or(u32::MAX),
+        rows,
+        incomplete: false,
+    })
+}
+
+/// Whether two files have the same bytes
+fn same_content(a: &Path, b: &Path) -> Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (
+        io::BufReader::new(fs::File::open(a)?),
+        io::BufReader::new(fs::File::open(b)?),
+    );
+    loop {
+        let (chunk_a, chunk_b) = (a.fill_buf()?, b.fill_buf()?);
+        if chunk_a.is_empty() || chunk_b.is_empty() {
+            return Ok(chunk_a.is_empty() && chunk_b.is_empty());
+        }
+        let len = chunk_a.len().min(chunk_b.len());
+        if chunk_a[..len] != chunk_b[..len] {
+            return Ok(false);
+        }
+        a.consume(len);
+        b.consume(len);
+    }
+}
+
+/// Replace `dup` with a hard link to `keep`. The link is made next to `dup` first and
+/// renamed over it, so `dup` is never lost midway.
+fn hardlink(keep: &Path, dup: &Path) -> Result<()> {
+    let name = dup.file_name().map(|n| n.to_string_lossy().to_string());
+    let tmp = dup.with_file_name(format!(".{}.recon-link", name.unwrap_or_default()));
+    fs::hard_link(keep, &tmp)?;
+    if let Err(err) = fs::rename(&tmp, dup) {
+        let _res = fs::remove_file(&tmp);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Serve a JSON query API over an existing DB, read-only. With a `schedule` in the config,
+/// the DB is re-scanned on it too, as with `--update`, and its status is served.
 ///
 /// # Errors
 ///
-/// This function will return an error on folder walking I/O failure, data processing, or database access failure
-#[tracing::instrument(level = "trace", skip_all, err)]
-async fn walk_and_store(
+/// This function will return an error if the DB is missing or cannot be opened, or serving failed
+pub async fn serve(opts: &RunOptions, addr: SocketAddr) -> Result<()> {
+    let config = Config::from_sources(
+        &opts.config.iter().map(String::as_str).collect::<Vec<_>>(),
+        opts.profile.as_deref(),
+    )
+    .context("cannot load configuration")?;
+    let db = open_readonly(opts).await?;
+    let Some(schedule) = config.source.schedule()? else {
+        return server::serve(db, addr, None).await;
+    };
+    let scheduler = Arc::new(Scheduler::new(schedule));
+    let scans = async {
+        loop {
+            scheduler.due().await;
+            scheduler.run(rescan(opts)).await;
+        }
+    };
+    tokio::select! {
+        res = server::serve(db, addr, Some(scheduler.clone())) => res,
+        () = scans => Ok(()),
+    }
+}
+
+/// Open an existing DB for reading only
+async fn open_readonly(opts: &RunOptions) -> Result<Db> {
+    let db_url = if let Some(db_url) = &opts.db_url {
+        db_url.clone()
+    } else {
+        if !Path::new(&opts.db_file).exists() {
+            bail!("cannot find '{}', index files first", opts.db_file);
+        }
+        format!("sqlite:{}?mode=ro", opts.db_file)
+    };
+    info!("db: {}", db_url);
+    let config = Config::from_sources(
+        &opts.config.iter().map(String::as_str).collect::<Vec<_>>(),
+        opts.profile.as_deref(),
+    )
+    .context("cannot load configuration")?;
+    Db::connect_readonly(&db_url, &config.db.unwrap_or_default()).await
+}
+
+/// Write an existing DB to a portable bundle, see `import`. Returns the rows of each table.
+///
+/// # Errors
+///
+/// This function will return an error if the DB can't be read, or the bundle written
+pub async fn export(opts: &RunOptions, path: &Path) -> Result<data::ValuesTable> {
+    let db = open_readonly(opts).await?;
+    bundle::export(&db, path).await
+}
+
+/// Replace what the DB holds with a bundle exported by this or another version of recon,
+/// creating the DB if needed. Returns the rows of each table.
+///
+/// # Errors
+///
+/// This function will return an error if the bundle can't be read, or the DB written
+pub async fn import(opts: &RunOptions, path: &Path) -> Result<data::ValuesTable> {
+    let config = Config::from_sources(
+        &opts.config.iter().map(String::as_str).collect::<Vec<_>>(),
+        opts.profile.as_deref(),
+    )
+    .context("cannot load configuration")?;
+    let db_url = opts
+        .db_url
+        .clone()
+        .unwrap_or_else(|| format!("sqlite:{}?mode=rwc", opts.db_file));
+    info!("db: {}", db_url);
+    let db = Db::connect(&db_url, &config.db.unwrap_or_default()).await?;
+    bundle::import(&db, path).await
+}
+
+// hard links of a file take its space once
+const STATS_OVERVIEW_SQL: &str = r"select count(*) as files,
+  coalesce(sum(case when nth = 1 then size end), 0) as total_size,
+  count(distinct ext) as extensions, min(mtime) as oldest, max(mtime) as newest
+from (select *, row_number() over (partition by coalesce(dev || ':' || inode, 'id:' || id)) as nth
+  from files)";
+
+const STATS_CLASSES_SQL: &str = r"select coalesce(sum(is_binary), 0) as binary,
+  coalesce(sum(is_code), 0) as code, coalesce(sum(is_document), 0) as documents,
+  coalesce(sum(is_media), 0) as media, coalesce(sum(is_archive), 0) as archives,
+  coalesce(sum(is_empty), 0) as empty
+from files";
+
+const STATS_EXTENSIONS_SQL: &str = r"select coalesce(ext, '') as ext, count(*) as files,
+  coalesce(sum(size), 0) as size
+from files group by ext order by files desc, ext limit 20";
+
+const STATS_LARGEST_SQL: &str =
+    "select path, size from files where size is not null order by size desc, path limit 10";
+
+const STATS_NEWEST_SQL: &str =
+    "select path, mtime from files where mtime is not null order by mtime desc, path limit 10";
+
+/// Files matched by each `*_match` field, per what matched (its `by` keys)
+pub(crate) fn stats_matches_sql(match_columns: &[String]) -> String {
+    let per_column = match_columns
+        .iter()
+        .map(|col| {
+            format!(
+                "select '{col}' as field, m.key as by, count(*) as files
+from files f, json_each(f.{col}, '$.by') m
+where json_extract(f.{col}, '$.is_match') = 1 and m.value = 1 group by m.key"
+            )
+        })
+        .collect::<Vec<_>>();
+    if per_column.is_empty() {
+        "select null as field, null as by, null as files where 0".to_string()
+    } else {
+        format!(
+            "{} order by field, files desc, by",
+            per_column.join("\nunion all\n")
+        )
+    }
+}
+
+/// An overview of an existing DB, as named tables: counts and sizes, counts by type class and
+/// extension, matches per field, and the largest and newest files
+///
+/// # Errors
+///
+/// This function will return an error if the DB can't be opened or queried
+pub async fn stats(opts: &RunOptions) -> Result<Vec<(&'static str, data::ValuesTable)>> {
+    let db = open_readonly(opts).await?;
+    let match_columns = db.match_columns().await?;
+
+    let mut sections = vec![];
+    for (name, query) in [
+        ("overview", STATS_OVERVIEW_SQL.to_string()),
+        ("classes", STATS_CLASSES_SQL.to_string()),
+        ("extensions", STATS_EXTENSIONS_SQL.to_string()),
+        ("matches", stats_matches_sql(&match_columns)),
+        ("largest", STATS_LARGEST_SQL.to_string()),
+        ("newest", STATS_NEWEST_SQL.to_string()),
+    ] {
+        sections.push((name, db.query_table(&query).await?));
+    }
+    Ok(sections)
+}
+
+/// What a scan would go through, without computing fields or touching the DB: the files
+/// and bytes walked, and for each field turned on, the files it would compute and their
+/// bytes, after `process_if` and size limits. Computed fields and stages are counted over
+/// every walked file, since the queries picking their files need a DB, and archive
+/// members are not counted.
+///
+/// # Errors
+///
+/// This function will return an error if `root` isn't a local folder, or can't be walked
+fn dry_run(
+    root: &str,
+    paths: Option<&[String]>,
+    source: &Source,
+    opts: &RunOptions,
+) -> Result<data::ValuesTable> {
+    if s3::Location::parse(root).is_some() || git::is_remote(root) {
+        bail!("a dry run walks local folders only");
+    }
+    let files: Box<dyn Iterator<Item = File>> = if let Some(paths) = paths {
+        Box::new(
+            paths
+                .iter()
+                .filter(|path| Path::new(path).is_file())
+                .filter_map(|path| File::from_path(Path::new(path)).ok()),
+        )
+    } else {
+        Box::new(
+            walk(
+                root,
+                opts.ignores,
+                opts.one_file_system,
+                opts.follow_symlinks,
+                source,
+                None,
+            )?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| File::from_entry(&entry).ok()),
+        )
+    };
+
+    let sections = [
+        ("default_fields".to_string(), source.default_fields()),
+        ("computed_fields".to_string(), source.computed_fields()),
+    ]
+    .into_iter()
+    .chain(
+        (1..)
+            .zip(source.stages())
+            .map(|(nth, stage)| (stage.label(nth), stage.fields)),
+    )
+    .map(|(section, fields)| {
+        let enabled = fields.enabled_fields();
+        (section, fields, enabled)
+    })
+    .collect::<Vec<_>>();
+    // files and bytes, walked and then by section and field
+    let mut walked = (0u64, 0u64);
+    let mut counts = sections
+        .iter()
+        .map(|(_, _, enabled)| vec![(0u64, 0u64); enabled.len()])
+        .collect::<Vec<_>>();
+    for f in files {
+        let size = f
+            .size
+            .and_then(|size| u64::try_from(size).ok())
+            .unwrap_or(0);
+        walked = (walked.0 + 1, walked.1 + size);
+        for ((_, fields, enabled), counts) in sections.iter().zip(&mut counts) {
+            let process = fields.process_if.as_ref().map_or(Ok(true), |process_if| {
+                process_if
+                    .ast()
+                    .and_then(|ast| scripting::predicate(ast, &f))
+            });
+            if !process.unwrap_or(true) {
+                continue;
+            }
+            for ((_, max_size), count) in enabled.iter().zip(counts.iter_mut()) {
+                if max_size.is_none_or(|max_size| size <= max_size) {
+                    *count = (count.0 + 1, count.1 + size);
+                }
+            }
+        }
+    }
+
+    let mut rows = vec![vec![
+        "walk".into(),
+        serde_json::Value::Null,
+        walked.0.into(),
+        walked.1.into(),
+    ]];
+    for ((section, _, enabled), counts) in sections.iter().zip(counts) {
+        for ((field, _), (files, bytes)) in enabled.iter().zip(counts) {
+            rows.push(vec![
+                section.as_str().into(),
+                field.as_str().into(),
+                files.into(),
+                bytes.into(),
+            ]);
+        }
+    }
+    Ok(data::ValuesTable {
+        columns: vec![
+            "stage".to_string(),
+            "field".to_string(),
+            "files".to_string(),
+            "bytes".to_string(),
+        ],
+        total_rows: u32::try_from(rows.len()).unwrap_or(u32::MAX),
+        rows,
+        incomplete: false,
+    })
+}
+
+/// Problems with the configuration, found without scanning: load and parse errors (with
+/// their line), rule and digest set problems, DB options, and queries that don't prepare
+/// against the `files` table
+///
+/// # Errors
+///
+/// This function will return an error if the in-memory DB used to check queries can't
+/// be created
+pub async fn validate(opts: &RunOptions) -> Result<Vec<String>> {
+    let config = match Config::from_sources(
+        &opts.config.iter().map(String::as_str).collect::<Vec<_>>(),
+        opts.profile.as_deref(),
+    ) {
+        Ok(config) => config,
+        Err(err) => return Ok(vec![config::describe(&err)]),
+    };
+    let mut problems = config.problems();
+    if let Err(err) = Suppressions::load(config.source.suppress_file.as_deref()) {
+        problems.push(format!("suppress_file: {}", config::describe(&err)));
+    }
+
+    let db_opts = config.db.clone().unwrap_or_default();
+    let db = match Db::connect("sqlite::memory:", &db_opts).await {
+        Ok(db) => db,
+        Err(err) => {
+            problems.push(format!("db: {}", config::describe(&err)));
+            Db::connect("sqlite::memory:", &DbOptions::default()).await?
+        }
+    };
+    let queries = [
+        ("source.query", config.source.query.as_ref()),
+        (
+            "source.before_computed_fields_query",
+            config.source.before_computed_fields_query.as_ref(),
+        ),
+        ("--query", opts.query.as_ref()),
+    ];
+    let stages = (1..)
+        .zip(config.source.stages())
+        .map(|(nth, stage)| {
+            (
+                format!("source.{}.where", stage.label(nth)),
+                stage.query(nth),
+            )
+        })
+        .collect::<Vec<_>>();
+    let named = config
+        .source
+        .queries
+        .iter()
+        .flatten()
+        .map(|(name, query)| (format!("source.queries.{}", name), query.clone()));
+    let queries = queries
+        .into_iter()
+        .filter_map(|(name, query)| Some((name.to_string(), query?.clone())))
+        .chain(named)
+        .chain(stages);
+    for (name, query) in queries {
+        // explain prepares the query, without running it
+        if let Err(err) = db.query_table(&format!("explain {}", query)).await {
+            problems.push(format!("{}: {}", name, config::describe(&err)));
+        }
+    }
+    Ok(problems)
+}
+
+/// Ignore files just for recon, in `.gitignore` syntax, honored whatever the `Ignores`
+pub const RECONIGNORE: &str = ".reconignore";
+
+/// The path a walk error is about, for a loop the link leading back
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
+}
+
+/// The symlink `entry` was reached through, walking from `root`: itself, or when
+/// following symlinks, the nearest folder above it that is one
+fn via_symlink(entry: &ignore::DirEntry, root: &str, follow_symlinks: bool) -> Option<String> {
+    if entry.path_is_symlink() {
+        return Some(entry.path().display().to_string());
+    }
+    if !follow_symlinks {
+        return None;
+    }
+    entry
+        .path()
+        .ancestors()
+        .skip(1)
+        .take_while(|folder| *folder != Path::new(root) && !folder.as_os_str().is_empty())
+        .find(|folder| folder.is_symlink())
+        .map(|folder| folder.display().to_string())
+}
+
+/// Walk a tree, honoring the ignore rules `ignores` asks for, and `.reconignore` always.
+/// With `one_file_system`, folders on other filesystems than the root's are not entered.
+/// With `follow_symlinks`, symlinked folders are walked into, and a link leading back to
+/// a folder above it is reported as an error rather than walked in circles.
+/// Files out of the source's depth and size bounds, or its include and exclude globs, are
+/// left out as they're found. With `toward`, only the folders leading to it are walked.
+fn walk(
     path: &str,
+    ignores: Ignores,
+    one_file_system: bool,
+    follow_symlinks: bool,
+    source: &Source,
+    toward: Option<&Path>,
+) -> Result<ignore::Walk> {
+    let toward = toward.map(Path::to_path_buf);
+    let min_size = source.min_size.unwrap_or_default();
+    Ok(WalkBuilder::new(path)
+        .git_ignore(ignores.gitignore)
+        .git_global(ignores.global_gitignore)
+        .git_exclude(ignores.git_exclude)
+        // we leave an escape hatch: .ignore. nobody really uses this ordinarily so leave it on.
+        .hidden(!ignores.hidden)
+        .add_custom_ignore_filename(RECONIGNORE)
+        .same_file_system(one_file_system)
+        .follow_links(follow_symlinks)
+        .max_depth(source.max_depth)
+        .max_filesize(source.max_size)
+        // folders are walked whatever their size
+        .filter_entry(move |entry| {
+            if ignores.skip_git_dir
+                && entry.file_name() == ".git"
+                && entry.file_type().is_some_and(|t| t.is_dir())
+            {
+                return false;
+            }
+            if toward
+                .as_ref()
+                .is_some_and(|toward| !toward.starts_with(entry.path()))
+            {
+                return false;
+            }
+            min_size == 0
+                || !entry.file_type().is_some_and(|t| t.is_file())
+                || entry.metadata().is_ok_and(|m| m.len() >= min_size)
+        })
+        .overrides(source.overrides(path)?)
+        .build())
+}
+
+/// Walk `root` and yield each file once its default and computed fields are filled in.
+/// The walk honors `ignores`, `one_file_system` and `follow_symlinks` as `run` does.
+///
+/// Nothing is stored. Walking runs on a blocking thread a few files ahead of the
+/// consumer, and stops once the stream is dropped. Archives are not unpacked.
+/// Once `cancel` is cancelled, the stream ends with an error, telling it apart from
+/// a complete walk.
+///
+/// Must be called from within a tokio runtime.
+pub fn scan_stream(
+    root: &str,
+    source: &Source,
+    ignores: Ignores,
+    one_file_system: bool,
+    follow_symlinks: bool,
+    cancel: CancellationToken,
+) -> impl Stream<Item = Result<File>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(SCAN_STREAM_AHEAD);
+    let root = root.to_string();
+    let default_fields = source.default_fields();
+    let computed_fields = source.computed_fields();
+    let source = source.clone();
+    tokio::task::spawn_blocking(move || {
+        let walk = match walk(
+            &root,
+            ignores,
+            one_file_system,
+            follow_symlinks,
+            &source,
+            None,
+        ) {
+            Ok(walk) => walk,
+            Err(err) => {
+                let _res = tx.blocking_send(Err(err));
+                return;
+            }
+        };
+        for entry in walk {
+            if cancel.is_cancelled() {
+                let _res = tx.blocking_send(Err(anyhow::anyhow!("scan cancelled")));
+                break;
+            }
+            let f = match entry {
+                Ok(entry) if !entry.path().is_file() => continue,
+                Ok(entry) => data::File::from_entry(&entry)
+                    .map(|mut f| {
+                        f.via_symlink = via_symlink(&entry, &root, follow_symlinks);
+                        f
+                    })
+                    .and_then(|f| f.process_fields(&default_fields))
+                    .and_then(|f| f.process_fields(&computed_fields))
+                    .map(|mut f| {
+                        f.computed = Some(true);
+                        f
+                    })
+                    .with_context(|| format!("cannot scan '{}'", entry.path().display())),
+                Err(err) => Err(anyhow::Error::new(err).context("cannot list entry")),
+            };
+            if tx.blocking_send(f).is_err() {
+                // the stream was dropped
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// How many files `scan_stream` processes ahead of its consumer
+const SCAN_STREAM_AHEAD: usize = 16;
+
+/// Fill in fields for a file found at `path` and store it, unpacking it first if it is
+/// an archive and unpacking is configured. Returns how many archive members were stored,
+/// and the temporary folder they were extracted to.
+///
+/// # Errors
+///
+/// This function will return an error on unpacking, data processing, or database access failure
+async fn store_file(
+    f: data::File,
+    path: &Path,
     fields: &ComputedFields,
-    resume: bool,
-    all_files: bool,
-    s: &ProgressBar,
+    unpack: Option<&UnpackOptions>,
+    o: &dyn Observer,
     db: &Db,
+) -> anyhow::Result<(usize, Option<TempDir>)> {
+    o.on_file_start(&f.path);
+    let mut f = f.process_fields(fields)?;
+    let unpacked = match unpack.map(|unpack| archive::unpack(path, unpack)) {
+        Some(Ok(unpacked)) => unpacked,
+        Some(Err(err)) => {
+            // the archive itself is still stored, without its members
+            scan_error(db, fields, o, &f.path, err.context("cannot unpack")).await?;
+            None
+        }
+        None => None,
+    };
+    let stored = if let Some(mut unpacked) = unpacked {
+        let members = std::mem::take(&mut unpacked.members);
+        let (report, dir) = unpacked.finish(unpack.is_some_and(UnpackOptions::keep_temp));
+        f.unpacked = Some(Json(report));
+        (
+            store_members(members, &f.path, &f.abs_path, fields, false, o, db).await?,
+            dir,
+        )
+    } else {
+        (0, None)
+    };
+    db.insert_one(&f).await?;
+    file_done(o, &f);
+    Ok(stored)
+}
+
+/// Record a file that can't be scanned in `scan_errors` and move on, or fail with it
+/// when `strict`
+///
+/// # Errors
+///
+/// This function will return `err` when `strict`, or an error on db failure
+async fn scan_error(
+    db: &Db,
+    fields: &ComputedFields,
+    o: &dyn Observer,
+    path: &str,
+    err: anyhow::Error,
 ) -> anyhow::Result<()> {
+    if fields.strict {
+        return Err(err.context(format!("cannot scan '{}'", path)));
+    }
+    info!("cannot scan '{}': {:#}", path, err);
+    o.on_error(path, &err);
+    db.store_scan_error(path, &format!("{:#}", err)).await
+}
+
+/// Store the members extracted from an archive as files, addressed as `archive!/member`.
+/// Returns how many members were stored.
+///
+/// # Errors
+///
+/// This function will return an error on data processing or database access failure
+async fn store_members(
+    members: Vec<archive::Member>,
+    path: &str,
+    abs_path: &str,
+    fields: &ComputedFields,
+    resume: bool,
+    o: &dyn Observer,
+    db: &Db,
+) -> anyhow::Result<usize> {
     let mut count = 0;
-    for entry in WalkBuilder::new(path)
-        .git_ignore(!all_files) // user asked to walk all files. disable gitignore consideration
-        //.ignore(!all_files) // actually, we leave an escape hatch: .ignore. nobody really uses this ordinarily so leave it on.
-        .hidden(false) // always look at hidden files
-        .build()
-    {
-        let entry = entry.context("cannot list entry")?;
-        if entry.path().is_file() {
-            let mut f = data::File::from_entry(&entry)?;
-            if resume && db.exists(&f).await? {
-                s.set_message(format!("{} files (cached)", count));
-            } else {
-                s.set_message(format!("{} files", count));
-                f = f.process_fields(fields)?;
-                db.insert_one(&f).await?;
-            }
-            count += 1;
+    for member in members {
+        let m = data::File::from_member(
+            &member.location,
+            format!("{}!/{}", path, member.name),
+            format!("{}!/{}", abs_path, member.name),
+            member
+                .parent
+                .map_or_else(|| abs_path.to_string(), |p| format!("{}!/{}", abs_path, p)),
+        );
+        if !(resume && db.exists(&m).await?) {
+            o.on_file_start(&m.path);
+            let m = m.process_fields(fields)?;
+            db.insert_one(&m).await?;
+            file_done(o, &m);
         }
+        count += 1;
     }
-    Ok(())
+    Ok(count)
 }
 
+/// Compute fields of files and store them. `stage` is 0 for `computed_fields`, and the
+/// position (from 1) of one of `source.stages` otherwise, which files record once done.
 #[tracing::instrument(level = "trace", skip_all, err)]
 pub(crate) async fn compute_fields_and_store(
     files: &[File],
     fields: &ComputedFields,
-    s: &ProgressBar,
+    stage: i64,
+    o: &dyn Observer,
+    cancel: &CancellationToken,
     db: &Db,
 ) -> anyhow::Result<()> {
-    // xxx: move all this to a builder of pb, in out.rs, take the counts
-    s.set_length(files.len() as u64);
-    s.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:16.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .unwrap(),
-    );
-    s.set_position(
-        files
-            .iter()
-            .filter(|f| f.computed.unwrap_or_default())
-            .count() as u64,
-    );
-    s.set_message("Computing fields".to_string());
+    let is_done = |f: &&File| {
+        if stage == 0 {
+            f.computed.unwrap_or_default()
+        } else {
+            f.stage.unwrap_or_default() >= stage
+        }
+    };
+    let total = files.len() as u64;
+    let computed = files.iter().filter(is_done).count() as u64;
+    o.on_progress(Progress::Computing {
+        done: computed,
+        total,
+    });
 
-    for file in files.iter().filter(|f| !f.computed.unwrap_or_default()) {
+    let pending = files.iter().filter(|f| !is_done(f));
+    for (done, file) in (computed + 1..).zip(pending) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        o.on_file_start(&file.path);
         // a file may be in DB, but no longer on disk.
 
         // xxx: move all this inside File
-        let mut new_file = if Path::new(&file.abs_path).exists() {
+        let mut new_file = if file.content_path().exists() {
             file.process_fields(fields)?
         } else {
             file.clone()
         };
-        new_file.computed = Some(true);
+        if stage == 0 {
+            new_file.computed = Some(true);
+        } else {
+            new_file.stage = Some(stage);
+        }
 
         db.insert_one(&new_file).await?;
-        s.inc(1);
+        file_done(o, &new_file);
+        o.on_progress(Progress::Computing { done, total });
     }
     Ok(())
 }
 
-fn spin(no_spinner: bool) -> ProgressBar {
-    let pb = if no_spinner {
-        ProgressBar::hidden()
-    } else {
-        ProgressBar::new_spinner()
+/// Store the content of text files in the `contents` full text index, with what
+/// `content_match` finds redacted when `fields` say so. Other files are stored without
+/// content, so they aren't read again until they change.
+async fn index_contents(
+    files: &[File],
+    fields: &ComputedFields,
+    o: &dyn Observer,
+    cancel: &CancellationToken,
+    db: &Db,
+) -> anyhow::Result<()> {
+    let total = files.len() as u64;
+    o.on_progress(Progress::Computing { done: 0, total });
+    for (done, file) in (1..).zip(files) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        // a file no longer on disk is indexed once it's back
+        if let (Some(id), true) = (file.id, file.content_path().exists()) {
+            let content = Some(file)
+                .filter(|f| f.has_content())
+                .map_or(Ok(None), processing::text_content)
+                .unwrap_or_else(|err| {
+                    info!("cannot index {}: {:#}", file.path, err);
+                    None
+                });
+            let content = match (fields.redact, &fields.content_match, content) {
+                (Some(redaction), Some(patterns), Some(content)) => {
+                    Some(matching::redact_text(&content, patterns, redaction))
+                }
+                (_, _, content) => content,
+            };
+            db.store_content(id, content.as_deref()).await?;
+        }
+        o.on_progress(Progress::Computing { done, total });
+    }
+    Ok(())
+}
+
+/// Match `pattern` against the content of every stored text file, replacing their
+/// `grep_match`. Binary files are told apart by their `bytes_type`, computed when missing.
+async fn grep_and_store(
+    pattern: &str,
+    computed_fields: &ComputedFields,
+    o: &dyn Observer,
+    cancel: &CancellationToken,
+    db: &Db,
+) -> anyhow::Result<()> {
+    let re = regex::bytes::Regex::new(pattern)
+        .with_context(|| format!("invalid pattern '{}'", pattern))?;
+    let bytes_type = ComputedFields {
+        bytes_type: Some(true),
+        ..ComputedFields::default()
+    };
+    let grep = ComputedFields {
+        content_match: Some(config::Patterns::One(serde_regex::Serde(re))),
+        redact: computed_fields.redact,
+        field_timeout: computed_fields.field_timeout,
+        strict: computed_fields.strict,
+        ..ComputedFields::default()
     };
 
-    pb.set_style(ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}").unwrap());
-    pb.enable_steady_tick(Duration::from_millis(120));
-    pb.set_message("Processing...");
-    pb
+    let files = db.query_files("select * from files").await?;
+    let total = files.len() as u64;
+    o.on_progress(Progress::Computing { done: 0, total });
+    for (done, file) in (1..).zip(files) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        o.on_file_start(&file.path);
+        // matches of an earlier pattern are no longer relevant, and neither are its errors
+        let mut file = File {
+            grep_match: None,
+            ..file
+        };
+        if let Some(errors) = &mut file.errors {
+            errors.0.remove("grep_match");
+        }
+        // a file may be in DB, but no longer on disk
+        if file.content_path().exists() {
+            if file.bytes_type.is_none() {
+                file = file.process_fields(&bytes_type)?;
+            }
+            if file.bytes_type.as_deref() != Some("binary") {
+                // the pattern goes through `content_match` of a copy, leaving the file's own
+                let grepped = File {
+                    content_match: None,
+                    errors: None,
+                    ..file.clone()
+                }
+                .process_fields(&grep)?;
+                file.grep_match = grepped.content_match;
+                if let Some(err) = grepped.errors.and_then(|mut e| e.0.remove("content_match")) {
+                    file.errors
+                        .get_or_insert_with(Default::default)
+                        .0
+                        .insert("grep_match".to_string(), err);
+                }
+            }
+        }
+        file.errors = file.errors.filter(|errors| !errors.0.is_empty());
+        db.insert_one(&file).await?;
+        file_done(o, &file);
+        o.on_progress(Progress::Computing { done, total });
+    }
+    o.on_progress(Progress::Finished);
+    Ok(())
+}
+
+/// Split files into content defined chunks, and store them in `chunks`
+async fn chunk_and_store(
+    files: &[File],
+    opts: &ChunksOptions,
+    o: &dyn Observer,
+    cancel: &CancellationToken,
+    db: &Db,
+) -> anyhow::Result<()> {
+    let total = files.len() as u64;
+    o.on_progress(Progress::Computing { done: 0, total });
+    for (done, file) in (1..).zip(files) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        // a file no longer on disk is chunked once it's back
+        if let (Some(id), true) = (file.id, file.has_content() && file.content_path().is_file()) {
+            match processing::chunks(file, opts) {
+                Ok(chunks) => db.store_chunks(id, &chunks).await?,
+                Err(err) => info!("cannot chunk {}: {:#}", file.path, err),
+            }
+        }
+        o.on_progress(Progress::Computing { done, total });
+    }
+    Ok(())
+}
+
+/// What woke watching up
+enum Wakeup {
+    Changes(notify::Result<Event>),
+    Scheduled,
+}
+
+/// Wait until a scheduled scan is due, forever without a schedule
+async fn due(scheduler: Option<&Scheduler>) {
+    match scheduler {
+        Some(scheduler) => scheduler.due().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Count scan events for Prometheus, served on `addr` until the returned guard drops
+///
+/// # Errors
+///
+/// This function will return an error if binding to `addr` failed
+fn serve_metrics(
+    addr: SocketAddr,
+    scheduler: Option<Arc<Scheduler>>,
+) -> Result<(Arc<Metrics>, DropGuard)> {
+    let metrics = Arc::new(Metrics::default());
+    let done = CancellationToken::new();
+    let guard = done.clone().drop_guard();
+    let server = server::serve_metrics(metrics.clone(), scheduler, addr, async move {
+        done.cancelled().await;
+    })?;
+    tokio::spawn(server);
+    Ok((metrics, guard))
+}
+
+/// Passes scan events on to each of its observers
+struct Observers<'a>(Vec<&'a dyn Observer>);
+
+impl Observer for Observers<'_> {
+    fn on_file_start(&self, path: &str) {
+        for o in &self.0 {
+            o.on_file_start(path);
+        }
+    }
+
+    fn on_file_done(&self, file: &File) {
+        for o in &self.0 {
+            o.on_file_done(file);
+        }
+    }
+
+    fn on_match(&self, file: &File, field: &str, m: &data::Match) {
+        for o in &self.0 {
+            o.on_match(file, field, m);
+        }
+    }
+
+    fn on_error(&self, path: &str, error: &anyhow::Error) {
+        for o in &self.0 {
+            o.on_error(path, error);
+        }
+    }
+
+    fn on_progress(&self, progress: Progress) {
+        for o in &self.0 {
+            o.on_progress(progress);
+        }
+    }
+
+    fn on_warning(&self, message: &str) {
+        for o in &self.0 {
+            o.on_warning(message);
+        }
+    }
+}
+
+/// Tell the observer a file is stored, and what it matched
+fn file_done(o: &dyn Observer, f: &File) {
+    o.on_file_done(f);
+    for (field, m) in f.matches() {
+        o.on_match(f, field, m);
+    }
+}
+
+///
+/// Keeps the DB in sync with a folder using filesystem notifications
+///
+struct Watch<'a> {
+    root: &'a str,
+    db_file: &'a str,
+    query: &'a str,
+    db: &'a mut Db,
+    /// receives the updated files
+    observer: &'a dyn Observer,
+    /// times each batch of updates, when serving metrics
+    metrics: Option<&'a Metrics>,
+    /// what a scheduled re-scan goes through
+    scan: Scan<'a>,
+    scheduler: Option<&'a Scheduler>,
+    /// marks updated matches that are accepted
+    suppressions: &'a Suppressions,
+    /// ends watching
+    cancel: &'a CancellationToken,
+}
+
+impl Watch<'_> {
+    /// How long to wait for more changes before updating, so bursts are handled at once
+    const SETTLE: Duration = Duration::from_millis(500);
+
+    /// Watch until the notification channel closes or cancelled, re-indexing changed files with both
+    /// default and computed fields, and removing deleted files from the DB
+    async fn run<F>(&mut self, source: &Source, on_result: &mut F) -> Result<()>
+    where
+        F: FnMut(data::ValuesTable) -> Result<()>,
+    {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _res = tx.send(res);
+        })?;
+        watcher.watch(Path::new(self.root), RecursiveMode::Recursive)?;
+        info!("watching {}", self.root);
+
+        let root = fs::canonicalize(self.root)?;
+        // the DB sits in the watched folder more often than not, don't react to our own writes
+        let db_file = fs::canonicalize(self.db_file).ok();
+        let fields = source.default_fields();
+        let computed_fields = source.computed_fields();
+        let stages = source.stages();
+        let full_text = source.full_text.as_ref();
+
+        loop {
+            let wakeup = tokio::select! {
+                event = rx.recv() => event.map(Wakeup::Changes),
+                () = due(self.scheduler) => Some(Wakeup::Scheduled),
+                () = self.cancel.cancelled() => None,
+            };
+            let event = match wakeup {
+                Some(Wakeup::Changes(event)) => event,
+                Some(Wakeup::Scheduled) => {
+                    let started = Instant::now();
+                    if let Some(scheduler) = self.scheduler {
+                        scheduler.run(self.rescan()).await;
+                    }
+                    if let Some(metrics) = self.metrics {
+                        metrics.scanned(started.elapsed());
+                    }
+                    on_result(self.db.query_table(self.query).await?)?;
+                    continue;
+                }
+                None => break,
+            };
+            let mut changed = BTreeSet::new();
+            let mut add = |event: notify::Result<Event>| -> Result<()> {
+                changed.extend(event?.paths);
+                Ok(())
+            };
+            add(event)?;
+            while let Ok(Some(event)) = tokio::time::timeout(Self::SETTLE, rx.recv()).await {
+                add(event)?;
+            }
+
+            let started = Instant::now();
+            let mut updated = 0;
+            for path in changed {
+                let abs_path = absolute(&path);
+                let is_db = db_file.as_ref().is_some_and(|db_file| {
+                    abs_path
+                        .to_string_lossy()
+                        .starts_with(&*db_file.to_string_lossy())
+                });
+                let Ok(rel) = abs_path.strip_prefix(&root) else {
+                    continue;
+                };
+                // name files the same way walking does, relative to the given root
+                let path = Path::new(self.root).join(rel);
+                if is_db {
+                    continue;
+                }
+                // one file failing is recorded like in a scan, and watching goes on
+                match Box::pin(self.update(
+                    &path,
+                    &abs_path,
+                    &fields,
+                    &computed_fields,
+                    &stages,
+                    full_text,
+                ))
+                .await
+                {
+                    Ok(true) => updated += 1,
+                    Ok(false) => {}
+                    Err(err) => {
+                        let path = path.to_string_lossy();
+                        scan_error(self.db, &fields, self.observer, &path, err).await?;
+                    }
+                }
+            }
+            if updated > 0 {
+                suppress::apply(self.db, self.suppressions).await?;
+                if let Some(metrics) = self.metrics {
+                    metrics.scanned(started.elapsed());
+                }
+                if source.dirs.unwrap_or_default() {
+                    self.db.aggregate_dirs().await?;
+                }
+                on_result(self.db.query_table(self.query).await?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A scheduled re-scan of the whole root, as an update run, catching up on changes
+    /// notifications missed
+    async fn rescan(&mut self) -> Result<()> {
+        self.db.start_run(self.root).await?;
+        let temp_dirs = self.scan.store(self.db).await?;
+        Box::pin(self.scan.compute(self.db)).await?;
+        drop(temp_dirs);
+        suppress::apply(self.db, self.suppressions).await?;
+        if self.cancel.is_cancelled() {
+            self.db.interrupt_run().await
+        } else {
+            self.db.finish_run().await
+        }
+    }
+
+    /// Bring a single path up to date. Returns `false` if there was nothing to do, or the
+    /// file is one walking leaves out.
+    async fn update(
+        &self,
+        path: &Path,
+        abs_path: &Path,
+        fields: &ComputedFields,
+        computed_fields: &ComputedFields,
+        stages: &[Stage],
+        full_text: Option<&FullTextOptions>,
+    ) -> Result<bool> {
+        if path.is_file() {
+            if !self.scan.walks_to(path)? {
+                return Ok(false);
+            }
+            info!("updating {}", path.display());
+            self.observer.on_file_start(&path.to_string_lossy());
+            let mut f = data::File::from_path(path)?;
+            if let Ok(root) = fs::canonicalize(self.root) {
+                f.check_escape(&root);
+            }
+            let mut f = f.process_fields(fields)?.process_fields(computed_fields)?;
+            f.computed = Some(true);
+            self.db.insert_one(&f).await?;
+            // which stages pick the file is up to their filters, so it's asked of the DB
+            let escaped = f.abs_path.replace('\'', "''");
+            for (nth, stage) in (1..).zip(stages) {
+                let query = format!("{} and abs_path = '{}'", stage.query(nth), escaped);
+                for staged in self.db.query_files(&query).await? {
+                    f = staged.process_fields(&stage.fields)?;
+                    f.stage = Some(nth);
+                    self.db.insert_one(&f).await?;
+                }
+            }
+            file_done(self.observer, &f);
+            if let Some(full_text) = full_text {
+                let query = format!("{} and abs_path = '{}'", full_text.query(), escaped);
+                let files = self.db.query_files(&query).await?;
+                index_contents(&files, computed_fields, &Silent, self.cancel, self.db).await?;
+            }
+            Ok(true)
+        } else if path.exists() {
+            // folders come and go with the files in them
+            Ok(false)
+        } else {
+            info!("removing {}", path.display());
+            Ok(self.db.delete(&abs_path.to_string_lossy()).await? > 0)
+        }
+    }
+}
+
+/// Is `path` the DB, or one of the journal files next to it. Only paths named
+/// like the DB are resolved, to keep walking cheap.
+fn is_db_file(path: &Path, db_file: &Path) -> bool {
+    let (Some(name), Some(db_name)) = (path.file_name(), db_file.file_name()) else {
+        return false;
+    };
+    name.to_string_lossy()
+        .starts_with(&*db_name.to_string_lossy())
+        && absolute(path).parent() == db_file.parent()
+}
+
+/// Resolve a path the way `fs::canonicalize` does, also for paths which no longer exist
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        path.parent()
+            .and_then(|parent| fs::canonicalize(parent).ok())
+            .zip(path.file_name())
+            .map_or_else(|| path.to_path_buf(), |(parent, name)| parent.join(name))
+    })
+}
+
+/// Remove files which are no longer on disk, returning how many were removed.
+/// Archive members go with their archive; bucket objects are left alone as
+/// there's no disk to check.
+async fn prune(db: &Db) -> Result<u64> {
+    let mut count = 0;
+    for abs_path in db.abs_paths().await? {
+        // objects and cloned files are only on disk while they're scanned
+        if s3::Location::parse(&abs_path).is_some() || git::is_remote(&abs_path) {
+            continue;
+        }
+        let on_disk = abs_path
+            .split_once("!/")
+            .map_or(abs_path.as_str(), |(archive, _)| archive);
+        // a symlink is on disk even when what it leads to isn't
+        if Path::new(on_disk).symlink_metadata().is_err() {
+            count += db.delete(&abs_path).await?;
+        }
+    }
+    Ok(count)
+}
+
+/// Read a newline separated list of paths from a file, or from stdin when given `-`.
+/// Lines end in `\n` or `\r\n`, any other whitespace is part of the path.
+fn read_paths(from: &str) -> Result<Vec<String>> {
+    let content = if from == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        fs::read_to_string(from)?
+    };
+    Ok(content
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(ToString::to_string)
+        .collect())
 }
@@ -0,0 +1,92 @@
+//!
+//! The YARA engine behind `yara_match`: libyara through the `yara` feature (default), or the
+//! pure-Rust YARA-X through the `yara-x` feature, which wins when both are enabled.
+//!
+use crate::data::File;
+use anyhow::Result;
+use serde_json::Value;
+use std::path::Path;
+
+/// External variables every rule can use, for conditions such as `extension == "docm"`
+#[cfg_attr(not(any(feature = "yara", feature = "yara-x")), allow(dead_code))]
+fn externals(file: &File) -> (String, &str, &str, i64) {
+    let filename = Path::new(&file.abs_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (
+        filename,
+        &file.abs_path,
+        file.ext.as_deref().unwrap_or_default(),
+        file.size.unwrap_or_default(),
+    )
+}
+
+#[cfg(feature = "yara-x")]
+pub struct Rules(yara_x::Rules);
+
+#[cfg(feature = "yara-x")]
+impl Rules {
+    pub fn compile(file: &File, source: &str) -> Result<Self> {
+        let (filename, filepath, extension, file_size) = externals(file);
+        let mut compiler = yara_x::Compiler::new();
+        compiler.define_global("filename", filename.as_str())?;
+        compiler.define_global("filepath", filepath)?;
+        compiler.define_global("extension", extension)?;
+        compiler.define_global("file_size", file_size)?;
+        compiler.add_source(source)?;
+        Ok(Self(compiler.build()))
+    }
+
+    /// Matching rules, serialized
+    pub fn scan(&self, data: &[u8], timeout: i32) -> Result<Vec<Value>> {
+        let mut scanner = yara_x::Scanner::new(&self.0);
+        scanner.set_timeout(std::time::Duration::from_secs(u64::try_from(timeout)?));
+        let res = scanner.scan(data)?;
+        res.matching_rules()
+            .map(|r| Ok(serde_json::to_value(&r)?))
+            .collect()
+    }
+}
+
+#[cfg(all(feature = "yara", not(feature = "yara-x")))]
+pub struct Rules(yara::Rules);
+
+#[cfg(all(feature = "yara", not(feature = "yara-x")))]
+impl Rules {
+    pub fn compile(file: &File, source: &str) -> Result<Self> {
+        let (filename, filepath, extension, file_size) = externals(file);
+        let mut compiler = yara::Compiler::new()?;
+        compiler.define_variable("filename", filename.as_str())?;
+        compiler.define_variable("filepath", filepath)?;
+        compiler.define_variable("extension", extension)?;
+        compiler.define_variable("file_size", file_size)?;
+        let compiler = compiler.add_rules_str(source)?;
+        Ok(Self(compiler.compile_rules()?))
+    }
+
+    /// Matching rules, serialized
+    pub fn scan(&self, data: &[u8], timeout: i32) -> Result<Vec<Value>> {
+        self.0
+            .scan_mem(data, timeout)?
+            .iter()
+            .map(|r| Ok(serde_json::to_value(r)?))
+            .collect()
+    }
+}
+
+#[cfg(not(any(feature = "yara", feature = "yara-x")))]
+pub struct Rules;
+
+#[cfg(not(any(feature = "yara", feature = "yara-x")))]
+impl Rules {
+    pub fn compile(_file: &File, _source: &str) -> Result<Self> {
+        anyhow::bail!("recon was built without yara support, enable the `yara` or `yara-x` feature")
+    }
+
+    /// never reached, as rules can't be compiled
+    #[allow(clippy::unused_self)]
+    pub const fn scan(&self, _data: &[u8], _timeout: i32) -> Result<Vec<Value>> {
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use recon::config::ComputedFields;
+use recon::Recon;
+use serde_json::json;
+use std::fs;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn builder_scans_into_its_db() {
+    let dir = common::temp_dir("builder");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(root.join("todo.txt"), "TODO: test the builder").unwrap();
+    fs::write(root.join("done.rs"), "fn main() {}").unwrap();
+
+    let fields: ComputedFields =
+        serde_yaml::from_str("sha256: true\ncontent_match: TODO\n").unwrap();
+    let mut recon = Recon::builder()
+        .root(root.display().to_string())
+        .db(dir.join("builder.db"))
+        .computed_fields(fields)
+        .build()
+        .await
+        .unwrap();
+    assert!(recon.scan().await.unwrap());
+
+    let table = recon
+        .query("select ext, sha256 is not null, content_match->>'is_match' from files order by ext")
+        .await
+        .unwrap();
+    assert_eq!(
+        table.rows,
+        vec![
+            vec![json!("rs"), json!(1), json!(0)],
+            vec![json!("txt"), json!(1), json!(1)],
+        ]
+    );
+    let matched = recon
+        .query_files("select * from files where content_match->>'is_match'")
+        .await
+        .unwrap();
+    assert_eq!(matched.len(), 1);
+    assert!(matched[0].path.ends_with("todo.txt"));
+
+    // scanning again is a new run over the same files
+    assert!(recon.scan().await.unwrap());
+    let table = recon
+        .query("select (select count(*) from runs), (select count(*) from files)")
+        .await
+        .unwrap();
+    assert_eq!(table.rows, vec![vec![json!(2), json!(2)]]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn builder_defaults_to_a_db_in_memory() {
+    let dir = common::temp_dir("builder-memory");
+    fs::write(dir.join("a.txt"), "a").unwrap();
+    let mut recon = Recon::builder()
+        .root(dir.display().to_string())
+        .build()
+        .await
+        .unwrap();
+    assert!(recon.scan().await.unwrap());
+    let table = recon.query("select count(*) from files").await.unwrap();
+    assert_eq!(table.rows, vec![vec![json!(1)]]);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
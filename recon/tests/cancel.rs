@@ -0,0 +1,81 @@
+mod common;
+
+use recon::observer::{Observer, Progress};
+use recon::workflow::{self, Mode};
+use recon::CancellationToken;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cancels the scan once it has walked `after` files
+struct CancelAfter {
+    after: u64,
+    cancel: CancellationToken,
+}
+
+impl Observer for CancelAfter {
+    fn on_progress(&self, progress: Progress) {
+        if matches!(progress, Progress::Walking { files, .. } if files >= self.after) {
+            self.cancel.cancel();
+        }
+    }
+}
+
+/// A folder of 20 files under `name`, and the DB to scan them into
+fn twenty_files(name: &str) -> (PathBuf, PathBuf) {
+    let dir = common::temp_dir(name);
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    for n in 0..20 {
+        fs::write(root.join(format!("{n}.txt")), format!("file {n}")).unwrap();
+    }
+    (root, dir.join("cancel.db"))
+}
+
+/// Scan `root`, cancelling the token after walking 5 files. Returns whether the results
+/// are incomplete, and how many files were stored.
+async fn cancelled_run(root: &Path, db_file: &Path) -> (bool, i64) {
+    let cancel = CancellationToken::new();
+    let mut opts = common::options(root, db_file);
+    opts.query = Some("select count(*) from files".to_string());
+    opts.cancel = Some(cancel.clone());
+    opts.observer = Some(Box::new(CancelAfter { after: 5, cancel }));
+    let table = workflow::run(&opts).await.unwrap();
+    (table.incomplete, table.rows[0][0].as_i64().unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancelled_walk_is_incomplete() {
+    let (root, db_file) = twenty_files("cancel");
+    let (incomplete, walked) = cancelled_run(&root, &db_file).await;
+    assert!(incomplete);
+    assert!((1..20).contains(&walked), "walked {walked} files");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interrupted_run_is_partial_and_resumes() {
+    let (root, db_file) = twenty_files("interrupt");
+    let (_, walked) = cancelled_run(&root, &db_file).await;
+
+    let mut opts = common::options(&root, &db_file);
+    opts.mode = Mode::Query;
+    opts.pre_delete = false;
+    opts.query = Some("select partial, files, finished_at is not null from runs".to_string());
+    let table = workflow::run(&opts).await.unwrap();
+    assert_eq!(table.rows, vec![vec![json!(1), json!(walked), json!(1)]]);
+
+    // resuming walks the rest, and finishes the run
+    let mut opts = common::options(&root, &db_file);
+    opts.pre_delete = false;
+    opts.update = true;
+    opts.query = Some("select id, ifnull(partial, 0), files from runs order by id".to_string());
+    let table = workflow::run(&opts).await.unwrap();
+    assert!(!table.incomplete);
+    assert_eq!(
+        table.rows,
+        vec![
+            vec![json!(1), json!(1), json!(walked)],
+            vec![json!(2), json!(0), json!(20)],
+        ]
+    );
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use git2::{Repository, Signature};
+use recon::workflow;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+/// A repository of two committed files under `dir`, and the id of its commit
+fn repository(dir: &Path) -> String {
+    let repo = Repository::init(dir).unwrap();
+    fs::create_dir(dir.join("src")).unwrap();
+    fs::write(dir.join("README.md"), "readme").unwrap();
+    fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = Signature::now("recon", "recon@example.com").unwrap();
+    repo.commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cloned_files_are_keyed_by_remote_and_commit() {
+    let dir = common::temp_dir("clone");
+    let origin = dir.join("origin");
+    let commit = repository(&origin);
+    let remote = format!("file://{}", origin.display());
+    let db_file = dir.join("clone.db");
+
+    let mut opts = common::options(&origin, &db_file);
+    opts.root = Some(remote.clone());
+    opts.query = Some("select abs_path, git_commit from files order by abs_path".to_string());
+    assert_eq!(
+        workflow::run(&opts).await.unwrap().rows,
+        vec![
+            vec![json!(format!("{remote}@{commit}/README.md")), json!(commit)],
+            vec![
+                json!(format!("{remote}@{commit}/src/main.rs")),
+                json!(commit)
+            ],
+        ]
+    );
+
+    // a second clone finds the same files already stored, rather than storing them again
+    opts.query = Some("select abs_path, entry_time from files order by abs_path".to_string());
+    let stored = workflow::run(&opts).await.unwrap().rows;
+    opts.pre_delete = false;
+    opts.update = true;
+    assert_eq!(workflow::run(&opts).await.unwrap().rows, stored);
+}
@@ -0,0 +1 @@
+fn main() { let password = "hunter2"; }
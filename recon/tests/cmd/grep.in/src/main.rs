@@ -0,0 +1,4 @@
+fn main() {
+    let v = parse().unwrap();
+    println!("{}", v.unwrap());
+}
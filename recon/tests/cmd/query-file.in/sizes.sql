@@ -0,0 +1,9 @@
+-- files by size, largest first
+with sized as (
+  select path, size
+  from files
+  where path like '%.txt'
+)
+select path, size
+from sized
+order by size desc;
@@ -0,0 +1,53 @@
+// each test crate uses some of these helpers
+#![allow(dead_code)]
+
+use recon::workflow::{Mode, RunOptions};
+use std::path::{Path, PathBuf};
+
+/// A fresh folder for `name` under cargo's temporary folder for tests
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _res = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Options of a plain run over `root` with the default config, storing into `db_file`
+pub fn options(root: &Path, db_file: &Path) -> RunOptions {
+    RunOptions {
+        root: Some(root.display().to_string()),
+        files_from: None,
+        config: vec![],
+        profile: None,
+        db_url: None,
+        db_file: db_file.display().to_string(),
+        pre_delete: true,
+        mode: Mode::Auto,
+        update: false,
+        ignores: recon::workflow::Ignores::default(),
+        one_file_system: false,
+        follow_symlinks: false,
+        sample: None,
+        dry_run: false,
+        max_depth: None,
+        min_size: None,
+        max_size: None,
+        exclude: vec![],
+        no_spinner: true,
+        observer: None,
+        cancel: None,
+        query: None,
+        watch: false,
+        metrics: None,
+        syslog: None,
+        prune: false,
+        force: false,
+        strict: false,
+        baseline: None,
+        dupes: None,
+        similar: None,
+        overlap: None,
+        grep: None,
+        jsonl: None,
+    }
+}
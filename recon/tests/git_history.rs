@@ -0,0 +1,90 @@
+mod common;
+
+use git2::{Repository, Signature, Time};
+use recon::workflow;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+/// Commit everything in the work tree of `repo` as `author`, `seconds` after the epoch
+fn commit_all(repo: &Repository, author: &str, seconds: i64) -> String {
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = Signature::new(author, "recon@example.com", &Time::new(seconds, 0)).unwrap();
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    repo.commit(
+        Some("HEAD"),
+        &sig,
+        &sig,
+        "change",
+        &tree,
+        parent.as_ref().into_iter().collect::<Vec<_>>().as_slice(),
+    )
+    .unwrap()
+    .to_string()
+}
+
+fn write(root: &Path, path: &str, content: &str) {
+    fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+    fs::write(root.join(path), content).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn git_last_fields_follow_history() {
+    let dir = common::temp_dir("git-history");
+    let root = dir.join("root");
+    let repo = Repository::init(&root).unwrap();
+    write(&root, "a.txt", "a");
+    write(&root, "src/b.txt", "b");
+    let first = commit_all(&repo, "alice", 1_600_000_000);
+    write(&root, "src/b.txt", "b, again");
+    let second = commit_all(&repo, "bob", 1_600_000_100);
+    write(&root, "untracked.txt", "untracked");
+
+    let config = dir.join("git.yaml");
+    fs::write(
+        &config,
+        "source:
+  computed_fields:
+    git_last_commit: true
+    git_last_author: true
+",
+    )
+    .unwrap();
+    let mut opts = common::options(&root, &dir.join("git.db"));
+    opts.config = vec![config.display().to_string()];
+    opts.query = Some(format!(
+        "select replace(path, '{}/', ''), git_last_commit, git_last_author from files \
+         where path not like '%.git/%' order by path",
+        root.display()
+    ));
+    assert_eq!(
+        workflow::run(&opts).await.unwrap().rows,
+        vec![
+            vec![
+                json!("a.txt"),
+                json!(first),
+                json!("alice <recon@example.com>")
+            ],
+            vec![
+                json!("src/b.txt"),
+                json!(second),
+                json!("bob <recon@example.com>")
+            ],
+            vec![json!("untracked.txt"), json!(null), json!(null)],
+        ]
+    );
+
+    // the next run walks history again, and finds commits made since
+    write(&root, "a.txt", "a, again");
+    let third = commit_all(&repo, "carol", 1_600_000_200);
+    opts.query = Some("select git_last_commit from files where path like '%/a.txt'".to_string());
+    assert_eq!(
+        workflow::run(&opts).await.unwrap().rows,
+        vec![vec![json!(third)]]
+    );
+}
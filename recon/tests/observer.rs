@@ -0,0 +1,115 @@
+mod common;
+
+use recon::config::ComputedFields;
+use recon::data::{File, Match};
+use recon::observer::{Observer, Progress};
+use recon::Recon;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Records the events of a scan, one line each
+#[derive(Default)]
+struct Events(Mutex<Vec<String>>);
+
+impl Events {
+    fn push(&self, event: String) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    /// Events starting with `kind`, with the file name of their path
+    fn of(&self, kind: &str) -> Vec<String> {
+        let mut events = self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|e| e.strip_prefix(kind))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        events.sort();
+        events
+    }
+}
+
+fn name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string()
+}
+
+impl Observer for Events {
+    fn on_file_start(&self, path: &str) {
+        self.push(format!("start {}", name(path)));
+    }
+
+    fn on_file_done(&self, file: &File) {
+        self.push(format!("done {}", name(&file.path)));
+    }
+
+    fn on_match(&self, file: &File, field: &str, _m: &Match) {
+        self.push(format!("match {} {}", name(&file.path), field));
+    }
+
+    fn on_error(&self, path: &str, _error: &anyhow::Error) {
+        self.push(format!("error {}", name(path)));
+    }
+
+    fn on_progress(&self, progress: Progress) {
+        if matches!(progress, Progress::Finished) {
+            self.push("finished".to_string());
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn observer_sees_files_and_matches() {
+    let dir = common::temp_dir("observer");
+    fs::write(dir.join("todo.txt"), "TODO: observe").unwrap();
+    fs::write(dir.join("done.txt"), "done").unwrap();
+
+    let events = Arc::new(Events::default());
+    let fields: ComputedFields = serde_yaml::from_str("content_match: TODO\n").unwrap();
+    let mut recon = Recon::builder()
+        .root(dir.display().to_string())
+        .computed_fields(fields)
+        .observer(events.clone())
+        .build()
+        .await
+        .unwrap();
+    assert!(recon.scan().await.unwrap());
+
+    // files are seen when walked, and again when their fields are computed
+    assert_eq!(
+        events.of("start "),
+        vec!["done.txt", "done.txt", "todo.txt", "todo.txt"]
+    );
+    assert_eq!(
+        events.of("done "),
+        vec!["done.txt", "done.txt", "todo.txt", "todo.txt"]
+    );
+    assert_eq!(events.of("match "), vec!["todo.txt content_match"]);
+    assert!(!events.of("finished").is_empty());
+    assert!(events.of("error ").is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn observer_sees_scan_errors() {
+    let dir = common::temp_dir("observer-errors");
+    fs::write(dir.join("a.txt"), "a").unwrap();
+    std::os::unix::fs::symlink(&dir, dir.join("loop")).unwrap();
+
+    let events = Arc::new(Events::default());
+    let mut recon = Recon::builder()
+        .root(dir.display().to_string())
+        .follow_symlinks(true)
+        .observer(events.clone())
+        .build()
+        .await
+        .unwrap();
+    assert!(recon.scan().await.unwrap());
+    assert_eq!(events.of("error "), vec!["loop"]);
+}
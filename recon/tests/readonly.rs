@@ -0,0 +1,33 @@
+use recon::config::DbOptions;
+use recon::db::Db;
+
+#[tokio::test]
+async fn readonly_rejects_writes() {
+    let file = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("readonly.db");
+    let _ = std::fs::remove_file(&file);
+    let url = format!("sqlite:{}?mode=rwc", file.display());
+    let opts = DbOptions::default();
+    Db::connect(&url, &opts).await.unwrap();
+
+    // asking for write access in the url doesn't matter
+    let db = Db::connect_readonly(&url, &opts).await.unwrap();
+    let mut conn = db.acquire().await.unwrap();
+    let count: i64 = sqlx::query_scalar("select count(*) from files")
+        .fetch_one(&mut conn.connection)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+    for sql in [
+        "delete from files",
+        "create table evil(x)",
+        "pragma query_only=0; delete from files",
+    ] {
+        assert!(
+            sqlx::query(sql)
+                .execute(&mut conn.connection)
+                .await
+                .is_err(),
+            "{sql}"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server, StatusCode};
+use recon::config::Config;
+use sha2::Digest;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+const RULES: &str = "source:
+  computed_fields:
+    path_match:
+      secrets: \\.pem$
+";
+
+/// Serves `base.yaml`, including `include`, and `rules.yaml`
+fn serve_configs(include: String) -> SocketAddr {
+    let make_svc = make_service_fn(move |_conn| {
+        let include = include.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let res = match req.uri().path() {
+                    "/base.yaml" => Response::new(Body::from(format!("include: [{include}]\n"))),
+                    "/rules.yaml" => Response::new(Body::from(RULES)),
+                    _ => Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap(),
+                };
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+/// Load `base.yaml` served with `include`, pinned to its checksum
+async fn load_pinned(include: &str) -> anyhow::Result<Config> {
+    let addr = serve_configs(include.to_string());
+    let sha256 = format!(
+        "{:x}",
+        sha2::Sha256::digest(format!("include: [{include}]\n"))
+    );
+    let url = format!("http://{addr}/base.yaml#sha256={sha256}");
+    tokio::task::spawn_blocking(move || Config::from_sources(&[&url], None))
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pinned_config_needs_pinned_includes() {
+    std::env::set_var("RECON_CACHE_DIR", common::temp_dir("remote-config"));
+
+    let err = load_pinned("rules.yaml").await.unwrap_err().to_string();
+    assert!(
+        err.ends_with("/rules.yaml' needs a `#sha256=` pin too"),
+        "{err}"
+    );
+
+    let pinned = format!("rules.yaml#sha256={:x}", sha2::Sha256::digest(RULES));
+    let config = load_pinned(&pinned).await.unwrap();
+    let path_match = config.source.computed_fields.unwrap().path_match.unwrap();
+    let names = path_match
+        .named("path_match")
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["secrets"]);
+}
@@ -0,0 +1,52 @@
+#![cfg(unix)]
+mod common;
+
+use recon::config::Source;
+use recon::workflow::{self, Ignores};
+use recon::CancellationToken;
+use std::fs;
+use tokio_stream::StreamExt;
+
+/// Paths the stream yields, and where they were reached through
+async fn streamed(root: &str, follow_symlinks: bool) -> Vec<(String, Option<String>)> {
+    let files = workflow::scan_stream(
+        root,
+        &Source::default(),
+        Ignores::default(),
+        false,
+        follow_symlinks,
+        CancellationToken::new(),
+    );
+    let mut res = files
+        .map(|f| f.map(|f| (f.path, f.via_symlink)))
+        .collect::<Result<Vec<_>, _>>()
+        .await
+        .unwrap();
+    res.sort();
+    res
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_follows_symlinks_when_asked() {
+    let dir = common::temp_dir("stream-symlinks");
+    let (root, shared) = (dir.join("root"), dir.join("shared"));
+    fs::create_dir(&root).unwrap();
+    fs::create_dir(&shared).unwrap();
+    fs::write(root.join("app.txt"), "app").unwrap();
+    fs::write(shared.join("lib.txt"), "lib").unwrap();
+    std::os::unix::fs::symlink(&shared, root.join("shared")).unwrap();
+
+    let root = root.display().to_string();
+    let app = format!("{root}/app.txt");
+    assert_eq!(streamed(&root, false).await, vec![(app.clone(), None)]);
+    assert_eq!(
+        streamed(&root, true).await,
+        vec![
+            (app, None),
+            (
+                format!("{root}/shared/lib.txt"),
+                Some(format!("{root}/shared"))
+            ),
+        ]
+    );
+}
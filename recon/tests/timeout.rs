@@ -0,0 +1,42 @@
+mod common;
+
+use recon::workflow;
+use serde_json::json;
+use std::fs;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_field_times_out() {
+    let dir = common::temp_dir("timeout");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    // sparse, so it takes no room, but hashing it takes a lot longer than a second
+    fs::File::create(root.join("huge.bin"))
+        .unwrap()
+        .set_len(4 << 30)
+        .unwrap();
+    fs::write(root.join("small.txt"), "small").unwrap();
+    let config = dir.join("timeout.yaml");
+    fs::write(
+        &config,
+        "source:
+  computed_fields:
+    field_timeout: 1
+    sha512: true
+",
+    )
+    .unwrap();
+
+    let mut opts = common::options(&root, &dir.join("timeout.db"));
+    opts.config = vec![config.display().to_string()];
+    opts.query = Some(
+        "select ext, sha512 is not null, errors->>'sha512' from files order by ext".to_string(),
+    );
+    let table = workflow::run(&opts).await.unwrap();
+    assert_eq!(
+        table.rows,
+        vec![
+            vec![json!("bin"), json!(0), json!("timed out after 1s")],
+            vec![json!("txt"), json!(1), json!(null)],
+        ]
+    );
+}
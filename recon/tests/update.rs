@@ -0,0 +1,63 @@
+#![cfg(unix)]
+mod common;
+
+use recon::workflow;
+use std::fs;
+use std::path::Path;
+
+/// Update the DB of `root`, and return the sha256 stored for `name`
+async fn updated_sha256(root: &Path, db_file: &Path, name: &str) -> serde_json::Value {
+    let mut opts = common::options(root, db_file);
+    opts.pre_delete = false;
+    opts.update = true;
+    opts.query = Some(format!(
+        "select sha256 from files where path = '{}'",
+        root.join(name).display()
+    ));
+    let config = db_file.with_extension("yaml");
+    fs::write(&config, "source:\n  computed_fields:\n    sha256: true\n").unwrap();
+    opts.config = vec![config.display().to_string()];
+    workflow::run(&opts).await.unwrap().rows[0][0].clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn update_notices_changed_files() {
+    let dir = common::temp_dir("update");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    let db_file = dir.join("update.db");
+    let file = root.join("a.txt");
+    fs::write(&file, "first").unwrap();
+    let first = updated_sha256(&root, &db_file, "a.txt").await;
+
+    // another size
+    fs::write(&file, "second!").unwrap();
+    let resized = updated_sha256(&root, &db_file, "a.txt").await;
+    assert_ne!(resized, first);
+
+    // the same size, modified later
+    let modified = fs::metadata(&file).unwrap().modified().unwrap();
+    fs::write(&file, "third!!").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(modified + std::time::Duration::from_secs(1))
+        .unwrap();
+    let rewritten = updated_sha256(&root, &db_file, "a.txt").await;
+    assert_ne!(rewritten, resized);
+
+    // the same size and time, but another file moved in its place
+    let modified = fs::metadata(&file).unwrap().modified().unwrap();
+    let other = dir.join("other.txt");
+    fs::write(&other, "fourth!").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&other)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    fs::rename(&other, &file).unwrap();
+    let replaced = updated_sha256(&root, &db_file, "a.txt").await;
+    assert_ne!(replaced, rewritten);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server, StatusCode};
+use recon::observer::Observer;
+use recon::workflow;
+use std::convert::Infallible;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+const REPORT: &str =
+    r#"{"data":{"attributes":{"last_analysis_stats":{"malicious":1,"harmless":2}}}}"#;
+
+/// A VirusTotal stand-in answering `quota` lookups, and too many requests after that.
+/// Counts the lookups asked for.
+fn serve_vt(quota: Arc<AtomicUsize>, requests: Arc<AtomicUsize>) -> SocketAddr {
+    let make_svc = make_service_fn(move |_conn| {
+        let (quota, requests) = (quota.clone(), requests.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_req| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let left = quota.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                    left.checked_sub(1)
+                });
+                let res = match left {
+                    Ok(_) => Response::new(Body::from(REPORT)),
+                    Err(_) => Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .body(Body::empty())
+                        .unwrap(),
+                };
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn vt_quota_leaves_lookups_to_later_runs() {
+    let dir = common::temp_dir("vt");
+    let (first, second) = (dir.join("first"), dir.join("second"));
+    fs::create_dir(&first).unwrap();
+    fs::create_dir(&second).unwrap();
+    for n in 0..3 {
+        fs::write(first.join(format!("{n}.txt")), format!("first {n}")).unwrap();
+    }
+    fs::write(second.join("0.txt"), "second").unwrap();
+
+    let quota = Arc::new(AtomicUsize::new(1));
+    let requests = Arc::new(AtomicUsize::new(0));
+    let addr = serve_vt(quota.clone(), requests.clone());
+    let config = dir.join("vt.yaml");
+    fs::write(
+        &config,
+        format!(
+            "source:
+  computed_fields:
+    sha256: true
+    vt_lookup:
+      api_key_env: RECON_TEST_VT_KEY
+      requests_per_minute: 60000
+      url: http://{addr}
+"
+        ),
+    )
+    .unwrap();
+    std::env::set_var("RECON_TEST_VT_KEY", "key");
+
+    let mut opts = common::options(&first, &dir.join("vt.db"));
+    opts.config = vec![config.display().to_string()];
+    opts.query = Some("select count(vt_lookup) from files".to_string());
+    let table = workflow::run(&opts).await.unwrap();
+    assert_eq!(table.rows, vec![vec![serde_json::json!(1)]]);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // files of earlier runs wait for a run which sees them again
+    quota.store(10, Ordering::SeqCst);
+    opts.root = Some(second.display().to_string());
+    opts.pre_delete = false;
+    opts.update = true;
+    let table = workflow::run(&opts).await.unwrap();
+    assert_eq!(table.rows, vec![vec![serde_json::json!(2)]]);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+/// Keeps the warnings of a run
+#[derive(Default)]
+struct Warnings(Arc<Mutex<Vec<String>>>);
+
+impl Observer for Warnings {
+    fn on_warning(&self, message: &str) {
+        self.0.lock().unwrap().push(message.to_string());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn vt_failure_is_a_warning() {
+    let dir = common::temp_dir("vt-failure");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(root.join("0.txt"), "file").unwrap();
+    let config = dir.join("vt.yaml");
+    fs::write(
+        &config,
+        "source:
+  computed_fields:
+    sha256: true
+    vt_lookup:
+      api_key_env: RECON_TEST_VT_NO_KEY
+",
+    )
+    .unwrap();
+
+    let warnings = Warnings::default();
+    let seen = warnings.0.clone();
+    let mut opts = common::options(&root, &dir.join("vt.db"));
+    opts.config = vec![config.display().to_string()];
+    opts.query = Some("select count(sha256), count(vt_lookup) from files".to_string());
+    opts.observer = Some(Box::new(warnings));
+    let table = workflow::run(&opts).await.unwrap();
+    assert_eq!(
+        table.rows,
+        vec![vec![serde_json::json!(1), serde_json::json!(0)]]
+    );
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["vt_lookup: vt_lookup needs a VirusTotal API key in 'RECON_TEST_VT_NO_KEY': environment variable not found"]
+    );
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use recon::workflow;
+use std::fs;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn watch_leaves_out_what_walking_does() {
+    let root = common::temp_dir("watch");
+    let db_file = root.with_extension("db");
+    let _res = fs::remove_file(&db_file);
+    fs::write(root.join(".reconignore"), "skip.txt\n").unwrap();
+    fs::create_dir(root.join("sub")).unwrap();
+
+    let cancel = CancellationToken::new();
+    let mut opts = common::options(&root, &db_file);
+    opts.query = Some("select path from files order by path".to_string());
+    opts.exclude = vec!["*.log".to_string()];
+    opts.max_size = Some(100);
+    opts.watch = true;
+    opts.cancel = Some(cancel.clone());
+
+    let new = root.join("sub/new.txt").display().to_string();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let run = workflow::run_with(&opts, |table| {
+        let _res = tx.send(table);
+        Ok(())
+    });
+    let changes = async {
+        let initial = rx.recv().await.unwrap();
+        assert_eq!(initial.rows.len(), 1);
+
+        fs::write(root.join("sub/skip.txt"), "ignored").unwrap();
+        fs::write(root.join("sub/app.log"), "excluded").unwrap();
+        fs::write(root.join("sub/big.txt"), "too big".repeat(100)).unwrap();
+        fs::write(root.join("sub/new.txt"), "walked").unwrap();
+        let mut paths = vec![];
+        while !paths.contains(&new) {
+            let table = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("no update for the new file")
+                .unwrap();
+            paths = table
+                .rows
+                .iter()
+                .map(|row| row[0].as_str().unwrap().to_string())
+                .collect();
+        }
+        cancel.cancel();
+        paths
+    };
+    let (res, paths) = tokio::join!(run, changes);
+    res.unwrap();
+    assert_eq!(
+        paths,
+        vec![root.join(".reconignore").display().to_string(), new]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn watch_forgets_a_folder_moved_out() {
+    let dir = common::temp_dir("watch-move");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("sub/deeper")).unwrap();
+    fs::write(root.join("kept.txt"), "kept").unwrap();
+    fs::write(root.join("sub/a.txt"), "a").unwrap();
+    fs::write(root.join("sub/deeper/b.txt"), "b").unwrap();
+
+    let cancel = CancellationToken::new();
+    let mut opts = common::options(&root, &dir.join("watch.db"));
+    opts.query = Some("select path from files order by path".to_string());
+    opts.watch = true;
+    opts.cancel = Some(cancel.clone());
+
+    let kept = root.join("kept.txt").display().to_string();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let run = workflow::run_with(&opts, |table| {
+        let _res = tx.send(table);
+        Ok(())
+    });
+    let changes = async {
+        let initial = rx.recv().await.unwrap();
+        assert_eq!(initial.rows.len(), 3);
+
+        fs::rename(root.join("sub"), dir.join("moved")).unwrap();
+        let mut paths = vec![];
+        while paths != vec![kept.clone()] {
+            let table = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("no update for the moved folder")
+                .unwrap();
+            paths = table
+                .rows
+                .iter()
+                .map(|row| row[0].as_str().unwrap().to_string())
+                .collect();
+        }
+        cancel.cancel();
+    };
+    let (res, ()) = tokio::join!(run, changes);
+    res.unwrap();
+}
@@ -0,0 +1,48 @@
+#![cfg(any(feature = "yara", feature = "yara-x"))]
+
+mod common;
+
+use recon::workflow;
+use serde_json::json;
+use std::fs;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rules_see_file_metadata() {
+    let dir = common::temp_dir("yara");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(root.join("report.docm"), "macro").unwrap();
+    fs::write(root.join("notes.txt"), "notes!").unwrap();
+    let config = dir.join("yara.yaml");
+    fs::write(
+        &config,
+        r#"source:
+  computed_fields:
+    yara_match: |
+      rule docm { condition: extension == "docm" }
+      rule named { condition: filename == "notes.txt" }
+      rule rooted { condition: filepath matches /\/root\/notes\.txt$/ }
+      rule sized { condition: file_size == 5 }
+"#,
+    )
+    .unwrap();
+
+    let mut opts = common::options(&root, &dir.join("yara.db"));
+    opts.config = vec![config.display().to_string()];
+    opts.query = Some(
+        "select ext, \
+         coalesce(yara_match->>'$.by.docm', 0), \
+         coalesce(yara_match->>'$.by.named', 0), \
+         coalesce(yara_match->>'$.by.rooted', 0), \
+         coalesce(yara_match->>'$.by.sized', 0) \
+         from files order by ext"
+            .to_string(),
+    );
+    assert_eq!(
+        workflow::run(&opts).await.unwrap().rows,
+        vec![
+            vec![json!("docm"), json!(1), json!(0), json!(0), json!(1)],
+            vec![json!("txt"), json!(0), json!(1), json!(1), json!(0)],
+        ]
+    );
+}